@@ -5,24 +5,58 @@ mod compute_cap;
 mod flash_attn;
 mod layers;
 mod models;
+mod modules;
 
 #[cfg(feature = "cuda")]
 use crate::compute_cap::{
     get_compile_compute_cap, get_runtime_compute_cap, incompatible_compute_cap,
 };
+use crate::layers::Linear;
 #[cfg(feature = "cuda")]
 use crate::models::FlashBertModel;
 #[cfg(feature = "cuda")]
 use crate::models::FlashJinaBertModel;
 use crate::models::{BertModel, JinaBertModel, Model, PositionEmbeddingType};
-use candle::{DType, Device};
+use crate::modules::Modules;
+use candle::{DType, Device, IndexOp, Tensor};
 use candle_nn::VarBuilder;
 use models::Config;
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
 use text_embeddings_backend_core::{Backend, BackendError, Batch, Embedding, ModelType};
 
 pub struct CandleBackend {
     model: Box<dyn Model + Send>,
+    /// Sentence-transformers modules applied after pooling
+    modules: Modules,
+    /// Projection of the last hidden states of ColBERT models
+    colbert_linear: Option<Linear>,
+}
+
+/// Subset of the ColBERT `artifact.metadata`
+#[derive(Debug, Deserialize)]
+struct ColbertMetadata {
+    dim: usize,
+}
+
+/// ColBERT checkpoints store their `linear` projection next to the encoder weights
+fn load_colbert_linear(
+    model_path: &Path,
+    vb: &VarBuilder,
+    hidden_size: usize,
+) -> candle::Result<Option<Linear>> {
+    let metadata_path = model_path.join("artifact.metadata");
+    if !metadata_path.exists() {
+        return Ok(None);
+    }
+
+    let metadata = std::fs::read_to_string(metadata_path)?;
+    let metadata: ColbertMetadata =
+        serde_json::from_str(&metadata).map_err(|err| candle::Error::Msg(err.to_string()))?;
+
+    tracing::info!("Loading ColBERT `linear` projection");
+    let weight = vb.get((metadata.dim, hidden_size), "linear.weight")?;
+    Ok(Some(Linear::new(weight, None, None)))
 }
 
 impl CandleBackend {
@@ -84,6 +118,17 @@ impl CandleBackend {
         }
         .s()?;
 
+        let modules = match model_type {
+            ModelType::Embedding(_) => Modules::load(&model_path, dtype, &device).s()?,
+            ModelType::Classifier | ModelType::TokenClassifier => Modules::default(),
+        };
+        let colbert_linear = match model_type {
+            ModelType::Embedding(_) => {
+                load_colbert_linear(&model_path, &vb, config.hidden_size).s()?
+            }
+            ModelType::Classifier | ModelType::TokenClassifier => None,
+        };
+
         let model: Box<dyn Model + Send> = match device {
             Device::Cpu | Device::Metal(_) => {
                 if config.position_embedding_type == PositionEmbeddingType::Alibi {
@@ -135,7 +180,33 @@ impl CandleBackend {
             }
         };
 
-        Ok(Self { model })
+        Ok(Self {
+            model,
+            modules,
+            colbert_linear,
+        })
+    }
+
+    /// Flattened per-token values of each sequence of a batch
+    fn split_sequences(
+        &self,
+        results: Tensor,
+        cumulative_seq_lengths: &[u32],
+    ) -> candle::Result<Vec<Vec<f32>>> {
+        let results = results.to_dtype(DType::F32)?;
+
+        (0..cumulative_seq_lengths.len() - 1)
+            .map(|i| {
+                let start = cumulative_seq_lengths[i] as usize;
+                let len = cumulative_seq_lengths[i + 1] as usize - start;
+                let values = if self.model.is_padded() {
+                    results.i(i)?.narrow(0, 0, len)?
+                } else {
+                    results.narrow(0, start, len)?
+                };
+                values.flatten_all()?.to_vec1()
+            })
+            .collect()
     }
 }
 
@@ -150,6 +221,7 @@ impl Backend for CandleBackend {
 
     fn embed(&self, batch: Batch) -> Result<Vec<Embedding>, BackendError> {
         let results = self.model.embed(batch).e()?;
+        let results = self.modules.forward(results).e()?;
         let results = results.to_dtype(DType::F32).e()?.to_vec2().e()?;
         Ok(results)
     }
@@ -159,6 +231,29 @@ impl Backend for CandleBackend {
         let results = results.to_dtype(DType::F32).e()?.to_vec2().e()?;
         Ok(results)
     }
+
+    fn embed_all(&self, batch: Batch, layer: Option<usize>) -> Result<Vec<Vec<f32>>, BackendError> {
+        let cumulative_seq_lengths = batch.cumulative_seq_lengths.clone();
+        let results = self.model.embed_all(batch, layer).e()?;
+        self.split_sequences(results, &cumulative_seq_lengths).e()
+    }
+
+    fn embed_colbert(&self, batch: Batch) -> Result<Vec<Vec<f32>>, BackendError> {
+        let cumulative_seq_lengths = batch.cumulative_seq_lengths.clone();
+        let results = self.model.embed_all(batch, None).e()?;
+        // Checkpoints without `artifact.metadata` have no projection
+        let results = match &self.colbert_linear {
+            Some(linear) => linear.forward(&results).e()?,
+            None => results,
+        };
+        self.split_sequences(results, &cumulative_seq_lengths).e()
+    }
+
+    fn predict_tokens(&self, batch: Batch) -> Result<Vec<Vec<f32>>, BackendError> {
+        let cumulative_seq_lengths = batch.cumulative_seq_lengths.clone();
+        let results = self.model.predict_tokens(batch).e()?;
+        self.split_sequences(results, &cumulative_seq_lengths).e()
+    }
 }
 
 pub trait WrapErr<O> {
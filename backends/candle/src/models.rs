@@ -34,4 +34,17 @@ pub(crate) trait Model {
     fn predict(&self, _batch: Batch) -> Result<Tensor> {
         candle::bail!("`predict is not implemented for this model");
     }
+
+    /// Hidden states of every token after `layer` layers, the last one by default. Padded models
+    /// return `(batch_size, max_length, hidden_size)` hidden states and the others
+    /// `(total_tokens, hidden_size)` hidden states
+    fn embed_all(&self, _batch: Batch, _layer: Option<usize>) -> Result<Tensor> {
+        candle::bail!("`embed_all` is not implemented for this model");
+    }
+
+    /// Logits of every token. Padded models return `(batch_size, max_length, n_labels)` logits
+    /// and the others `(total_tokens, n_labels)` logits
+    fn predict_tokens(&self, _batch: Batch) -> Result<Tensor> {
+        candle::bail!("`predict_tokens` is not implemented for this model");
+    }
 }
@@ -345,13 +345,19 @@ impl BertEncoder {
         Ok(BertEncoder { layers, span })
     }
 
-    fn forward(&self, hidden_states: &Tensor, attention_bias: Option<&Tensor>) -> Result<Tensor> {
+    /// Run the first `num_layers` layers
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        attention_bias: Option<&Tensor>,
+        num_layers: usize,
+    ) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let mut hidden_states = hidden_states.clone();
 
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
+        for layer in self.layers.iter().take(num_layers) {
             hidden_states = layer.forward(&hidden_states, attention_bias)?;
         }
 
@@ -402,11 +408,126 @@ impl BertClassificationHead {
     }
 }
 
+/// Linear layer applied to the hidden states of every token, used by `*ForTokenClassification`
+/// models
+pub(crate) struct BertTokenClassificationHead {
+    classifier: Linear,
+    span: tracing::Span,
+}
+
+impl BertTokenClassificationHead {
+    pub(crate) fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        let n_classes = match &config.id2label {
+            None => candle::bail!("`id2label` must be set for token classifier models"),
+            Some(id2label) => id2label.len(),
+        };
+
+        let weight = vb.get((n_classes, config.hidden_size), "weight")?;
+        let bias = vb.get(n_classes, "bias")?;
+
+        Ok(Self {
+            classifier: Linear::new(weight, Some(bias), None),
+            span: tracing::span!(tracing::Level::TRACE, "token_classifier"),
+        })
+    }
+
+    pub(crate) fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+
+        self.classifier.forward(hidden_states)
+    }
+}
+
+/// Masked language modeling head, used for SPLADE pooling
+pub(crate) struct BertSpladeHead {
+    transform: Linear,
+    transform_layer_norm: LayerNorm,
+    decoder: Linear,
+    span: tracing::Span,
+}
+
+impl BertSpladeHead {
+    /// `word_embeddings` is used when the decoder weight is tied to the input embeddings and
+    /// not stored in the checkpoint
+    pub(crate) fn load(vb: VarBuilder, config: &Config, word_embeddings: &Tensor) -> Result<Self> {
+        // Bert checkpoints store the head in `cls.predictions` and Roberta ones in `lm_head`
+        Self::load_with_names(
+            vb.pp("cls.predictions"),
+            config,
+            word_embeddings,
+            ("transform.dense", "transform.LayerNorm"),
+        )
+        .or_else(|_| {
+            Self::load_with_names(
+                vb.pp("lm_head"),
+                config,
+                word_embeddings,
+                ("dense", "layer_norm"),
+            )
+        })
+    }
+
+    fn load_with_names(
+        vb: VarBuilder,
+        config: &Config,
+        word_embeddings: &Tensor,
+        (dense_name, layer_norm_name): (&str, &str),
+    ) -> Result<Self> {
+        let transform_weight = vb
+            .pp(dense_name)
+            .get((config.hidden_size, config.hidden_size), "weight")?;
+        let transform_bias = vb.pp(dense_name).get(config.hidden_size, "bias")?;
+        let transform = Linear::new(
+            transform_weight,
+            Some(transform_bias),
+            Some(config.hidden_act.clone()),
+        );
+
+        let transform_layer_norm = LayerNorm::load(
+            vb.pp(layer_norm_name),
+            config.hidden_size,
+            config.layer_norm_eps as f32,
+        )?;
+
+        let decoder_weight = vb
+            .pp("decoder")
+            .get((config.vocab_size, config.hidden_size), "weight")
+            .unwrap_or_else(|_| word_embeddings.clone());
+        let decoder_bias = vb
+            .get(config.vocab_size, "bias")
+            .or_else(|_| vb.pp("decoder").get(config.vocab_size, "bias"))?;
+        let decoder = Linear::new(decoder_weight, Some(decoder_bias), None);
+
+        Ok(Self {
+            transform,
+            transform_layer_norm,
+            decoder,
+            span: tracing::span!(tracing::Level::TRACE, "splade"),
+        })
+    }
+
+    /// `log(1 + relu(logits))` of every token
+    pub(crate) fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+
+        let hidden_states = self.transform.forward(hidden_states)?;
+        // Our layer norm is fused with a residual add
+        let hidden_states = self
+            .transform_layer_norm
+            .forward(&hidden_states, &hidden_states.zeros_like()?)?;
+        let logits = self.decoder.forward(&hidden_states)?;
+
+        (logits.relu()? + 1.0)?.log()
+    }
+}
+
 pub struct BertModel {
     embeddings: BertEmbeddings,
     encoder: BertEncoder,
     pool: Pool,
     classifier: Option<BertClassificationHead>,
+    token_classifier: Option<BertTokenClassificationHead>,
+    splade: Option<BertSpladeHead>,
 
     num_attention_heads: usize,
 
@@ -423,7 +544,7 @@ impl BertModel {
             candle::bail!("Bert only supports absolute position embeddings")
         }
 
-        let (pool, classifier) = match model_type {
+        let (pool, classifier, token_classifier) = match model_type {
             // Classifier models always use CLS pooling
             ModelType::Classifier => {
                 if config.model_type == Some("bert".to_string()) {
@@ -432,9 +553,19 @@ impl BertModel {
                 (
                     Pool::Cls,
                     Some(BertClassificationHead::load(vb.pp("classifier"), config)?),
+                    None,
                 )
             }
-            ModelType::Embedding(pool) => (pool, None),
+            // Token classifier models do not pool the hidden states
+            ModelType::TokenClassifier => (
+                Pool::Cls,
+                None,
+                Some(BertTokenClassificationHead::load(
+                    vb.pp("classifier"),
+                    config,
+                )?),
+            ),
+            ModelType::Embedding(pool) => (pool, None, None),
         };
 
         let (embeddings, encoder) = match (
@@ -461,11 +592,22 @@ impl BertModel {
             }
         };
 
+        let splade = match pool {
+            Pool::Splade => Some(BertSpladeHead::load(
+                vb.clone(),
+                config,
+                embeddings.word_embeddings.embeddings(),
+            )?),
+            _ => None,
+        };
+
         Ok(Self {
             embeddings,
             encoder,
             pool,
             classifier,
+            token_classifier,
+            splade,
             num_attention_heads: config.num_attention_heads,
             device: vb.device().clone(),
             dtype: vb.dtype(),
@@ -473,7 +615,9 @@ impl BertModel {
         })
     }
 
-    pub fn forward(&self, batch: Batch) -> Result<Tensor> {
+    /// Pooled embeddings, or the unpooled hidden states of every token after `hidden_layer`
+    /// layers if it is set
+    pub fn forward(&self, batch: Batch, hidden_layer: Option<usize>) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let batch_size = batch.cumulative_seq_lengths.len() - 1;
@@ -527,20 +671,21 @@ impl BertModel {
 
                 let (attention_bias, attention_mask) = match masking {
                     true => {
-                        // We only need the mask if we use mean pooling
-                        // For CLS pooling, the bias is enough
-                        let attention_mask = if self.pool == Pool::Mean {
-                            let attention_mask = Tensor::from_vec(
-                                attention_mask,
-                                (batch_size, max_length, 1),
-                                &self.device,
-                            )?
-                            .to_dtype(self.dtype)?;
-
-                            Some(attention_mask)
-                        } else {
-                            None
-                        };
+                        // We only need the mask if we use mean or SPLADE pooling
+                        // For the other poolings, the bias and the sequence lengths are enough
+                        let attention_mask =
+                            if matches!(self.pool, Pool::Mean | Pool::MeanSqrtLen | Pool::Splade) {
+                                let attention_mask = Tensor::from_vec(
+                                    attention_mask,
+                                    (batch_size, max_length, 1),
+                                    &self.device,
+                                )?
+                                .to_dtype(self.dtype)?;
+
+                                Some(attention_mask)
+                            } else {
+                                None
+                            };
 
                         let attention_bias = Tensor::from_vec(
                             attention_bias,
@@ -592,9 +737,14 @@ impl BertModel {
             .embeddings
             .forward(&input_ids, &type_ids, &position_ids)?;
 
-        let mut outputs = self
-            .encoder
-            .forward(&embedding_output, attention_bias.as_ref())?;
+        let num_layers = hidden_layer.unwrap_or(self.encoder.layers.len());
+        let mut outputs =
+            self.encoder
+                .forward(&embedding_output, attention_bias.as_ref(), num_layers)?;
+
+        if hidden_layer.is_some() {
+            return Ok(outputs);
+        }
 
         let results = match self.pool {
             // CLS pooling
@@ -608,6 +758,83 @@ impl BertModel {
 
                 (outputs.sum(1)?.broadcast_div(&input_lengths))?
             }
+            // Mean pooling divided by the square root of the sequence length
+            Pool::MeanSqrtLen => {
+                if let Some(attention_mask) = attention_mask {
+                    // Mask padded values
+                    outputs = outputs.broadcast_mul(&attention_mask)?;
+                }
+
+                (outputs.sum(1)?.broadcast_div(&input_lengths.sqrt()?))?
+            }
+            // Mean pooling weighted by the token positions
+            Pool::WeightedMean => {
+                // Normalized position weights. Padded values have a zero weight
+                let mut weights = Vec::with_capacity(batch_size * max_length);
+                for i in 0..batch_size {
+                    let seq_length = (batch.cumulative_seq_lengths[i + 1]
+                        - batch.cumulative_seq_lengths[i])
+                        as usize;
+                    let total = (seq_length * (seq_length + 1) / 2) as f32;
+                    weights.extend((0..max_length).map(|j| match j < seq_length {
+                        true => (j + 1) as f32 / total,
+                        false => 0.0,
+                    }));
+                }
+                let weights = Tensor::from_vec(weights, (batch_size, max_length, 1), &self.device)?
+                    .to_dtype(self.dtype)?;
+
+                outputs.broadcast_mul(&weights)?.sum(1)?
+            }
+            // Max pooling
+            Pool::Max => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let seq_length =
+                            batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
+
+                        // Max over the non padded values
+                        outputs
+                            .i(i)?
+                            .narrow(0, 0, seq_length as usize)?
+                            .max_keepdim(0)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Last token pooling
+            Pool::LastToken => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let seq_length =
+                            batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
+
+                        // Padding is on the right
+                        outputs.i(i)?.narrow(0, seq_length as usize - 1, 1)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // SPLADE pooling
+            Pool::Splade => {
+                // The head is always loaded for this pooling
+                let splade = self.splade.as_ref().unwrap();
+                let mut relu_log = splade.forward(&outputs)?;
+
+                if let Some(attention_mask) = attention_mask {
+                    // Mask padded values
+                    // `relu_log` is positive so padded values do not change the maximum
+                    relu_log = relu_log.broadcast_mul(&attention_mask)?;
+                }
+
+                relu_log.max(1)?
+            }
         };
 
         Ok(results)
@@ -620,16 +847,37 @@ impl Model for BertModel {
     }
 
     fn embed(&self, batch: Batch) -> Result<Tensor> {
-        self.forward(batch)
+        self.forward(batch, None)
+    }
+
+    fn embed_all(&self, batch: Batch, layer: Option<usize>) -> Result<Tensor> {
+        let num_layers = self.encoder.layers.len();
+        match layer {
+            Some(layer) if layer > num_layers => {
+                candle::bail!("`layer` must be <= {num_layers}. Given: {layer}")
+            }
+            layer => self.forward(batch, Some(layer.unwrap_or(num_layers))),
+        }
     }
 
     fn predict(&self, batch: Batch) -> Result<Tensor> {
         match &self.classifier {
             None => candle::bail!("`predict` is not implemented for this model"),
             Some(classifier) => {
-                let hidden_states = self.forward(batch)?;
+                let hidden_states = self.forward(batch, None)?;
                 classifier.forward(&hidden_states)
             }
         }
     }
+
+    fn predict_tokens(&self, batch: Batch) -> Result<Tensor> {
+        match &self.token_classifier {
+            None => candle::bail!("`predict_tokens` is not implemented for this model"),
+            Some(token_classifier) => {
+                // Token classifier models classify the hidden states of every token
+                let hidden_states = self.forward(batch, Some(self.encoder.layers.len()))?;
+                token_classifier.forward(&hidden_states)
+            }
+        }
+    }
 }
@@ -1,6 +1,8 @@
 use crate::flash_attn::flash_attn_varlen;
 use crate::layers::{LayerNorm, Linear};
-use crate::models::bert::{Config, PositionEmbeddingType};
+use crate::models::bert::{
+    BertSpladeHead, BertTokenClassificationHead, Config, PositionEmbeddingType,
+};
 use crate::models::Model;
 use candle::{DType, Device, Result, Tensor};
 use candle_nn::{Embedding, Module, VarBuilder};
@@ -257,13 +259,20 @@ impl BertEncoder {
         Ok(BertEncoder { layers, span })
     }
 
-    fn forward(&self, hidden_states: &Tensor, cu_seqlens: &Tensor, max_s: usize) -> Result<Tensor> {
+    /// Run the first `num_layers` layers
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        cu_seqlens: &Tensor,
+        max_s: usize,
+        num_layers: usize,
+    ) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let mut hidden_states = hidden_states.clone();
 
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
+        for layer in self.layers.iter().take(num_layers) {
             hidden_states = layer.forward(&hidden_states, cu_seqlens, max_s)?
         }
 
@@ -319,6 +328,8 @@ pub struct FlashBertModel {
     encoder: BertEncoder,
     pool: Pool,
     classifier: Option<BertClassificationHead>,
+    token_classifier: Option<BertTokenClassificationHead>,
+    splade: Option<BertSpladeHead>,
     pub device: Device,
 
     span: tracing::Span,
@@ -340,7 +351,7 @@ impl FlashBertModel {
             candle::bail!("FlashBert only supports absolute position embeddings")
         }
 
-        let (pool, classifier) = match model_type {
+        let (pool, classifier, token_classifier) = match model_type {
             // Classifier models always use CLS pooling
             ModelType::Classifier => {
                 if config.model_type == Some("bert".to_string()) {
@@ -349,9 +360,19 @@ impl FlashBertModel {
                 (
                     Pool::Cls,
                     Some(BertClassificationHead::load(vb.pp("classifier"), config)?),
+                    None,
                 )
             }
-            ModelType::Embedding(pool) => (pool, None),
+            // Token classifier models do not pool the hidden states
+            ModelType::TokenClassifier => (
+                Pool::Cls,
+                None,
+                Some(BertTokenClassificationHead::load(
+                    vb.pp("classifier"),
+                    config,
+                )?),
+            ),
+            ModelType::Embedding(pool) => (pool, None, None),
         };
 
         let (embeddings, encoder) = match (
@@ -378,17 +399,30 @@ impl FlashBertModel {
             }
         };
 
+        let splade = match pool {
+            Pool::Splade => Some(BertSpladeHead::load(
+                vb.clone(),
+                config,
+                embeddings.word_embeddings.embeddings(),
+            )?),
+            _ => None,
+        };
+
         Ok(Self {
             embeddings,
             encoder,
             pool,
             classifier,
+            token_classifier,
+            splade,
             device: vb.device().clone(),
             span: tracing::span!(tracing::Level::TRACE, "model"),
         })
     }
 
-    pub fn forward(&self, batch: Batch) -> Result<Tensor> {
+    /// Pooled embeddings, or the unpooled hidden states of every token after `hidden_layer`
+    /// layers if it is set
+    pub fn forward(&self, batch: Batch, hidden_layer: Option<usize>) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let batch_size = batch.cumulative_seq_lengths.len() - 1;
@@ -408,9 +442,17 @@ impl FlashBertModel {
             .embeddings
             .forward(&input_ids, &type_ids, &position_ids)?;
 
-        let outputs =
-            self.encoder
-                .forward(&embedding_output, &cu_seqlens, batch.max_length as usize)?;
+        let num_layers = hidden_layer.unwrap_or(self.encoder.layers.len());
+        let outputs = self.encoder.forward(
+            &embedding_output,
+            &cu_seqlens,
+            batch.max_length as usize,
+            num_layers,
+        )?;
+
+        if hidden_layer.is_some() {
+            return Ok(outputs);
+        }
 
         let results = match self.pool {
             // CLS pooling
@@ -436,6 +478,101 @@ impl FlashBertModel {
                     (outputs.sum_keepdim(0)? / (batch.max_length as f64))?
                 }
             }
+            // Mean pooling divided by the square root of the sequence length
+            Pool::MeanSqrtLen => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let start = batch.cumulative_seq_lengths[i];
+                        let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                        let embeddings = outputs.narrow(0, start as usize, len as usize)?;
+                        embeddings.sum_keepdim(0)? / (len as f64).sqrt()
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Mean pooling weighted by the token positions
+            Pool::WeightedMean => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let start = batch.cumulative_seq_lengths[i];
+                        let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                        // Normalized position weights
+                        let total = (len * (len + 1) / 2) as f32;
+                        let weights: Vec<f32> = (1..=len).map(|j| j as f32 / total).collect();
+                        let weights = Tensor::from_vec(weights, (len as usize, 1), &self.device)?
+                            .to_dtype(outputs.dtype())?;
+
+                        let embeddings = outputs.narrow(0, start as usize, len as usize)?;
+                        embeddings.broadcast_mul(&weights)?.sum_keepdim(0)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Max pooling
+            Pool::Max => {
+                if batch_size > 1 {
+                    // for each request
+                    let results: Result<Vec<Tensor>> = (0..batch.cumulative_seq_lengths.len() - 1)
+                        .map(|i| {
+                            let start = batch.cumulative_seq_lengths[i];
+                            let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                            // Max
+                            outputs
+                                .narrow(0, start as usize, len as usize)?
+                                .max_keepdim(0)
+                        })
+                        .collect();
+
+                    // Concatenate all results
+                    Tensor::cat(&results?, 0)?
+                } else {
+                    outputs.max_keepdim(0)?
+                }
+            }
+            // Last token pooling
+            Pool::LastToken => {
+                let last_token_ids: Vec<u32> = batch.cumulative_seq_lengths[1..]
+                    .iter()
+                    .map(|end| end - 1)
+                    .collect();
+                let last_token_ids = Tensor::from_vec(last_token_ids, batch_size, &self.device)?;
+                outputs.index_select(&last_token_ids, 0)?
+            }
+            // SPLADE pooling
+            Pool::Splade => {
+                // The head is always loaded for this pooling
+                let splade = self.splade.as_ref().unwrap();
+                let relu_log = splade.forward(&outputs)?;
+
+                if batch_size > 1 {
+                    // for each request
+                    let results: Result<Vec<Tensor>> = (0..batch.cumulative_seq_lengths.len() - 1)
+                        .map(|i| {
+                            let start = batch.cumulative_seq_lengths[i];
+                            let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                            // Max
+                            relu_log
+                                .narrow(0, start as usize, len as usize)?
+                                .max_keepdim(0)
+                        })
+                        .collect();
+
+                    // Concatenate all results
+                    Tensor::cat(&results?, 0)?
+                } else {
+                    relu_log.max_keepdim(0)?
+                }
+            }
         };
 
         Ok(results)
@@ -447,16 +584,37 @@ impl Model for FlashBertModel {
         false
     }
     fn embed(&self, batch: Batch) -> Result<Tensor> {
-        self.forward(batch)
+        self.forward(batch, None)
+    }
+
+    fn embed_all(&self, batch: Batch, layer: Option<usize>) -> Result<Tensor> {
+        let num_layers = self.encoder.layers.len();
+        match layer {
+            Some(layer) if layer > num_layers => {
+                candle::bail!("`layer` must be <= {num_layers}. Given: {layer}")
+            }
+            layer => self.forward(batch, Some(layer.unwrap_or(num_layers))),
+        }
     }
 
     fn predict(&self, batch: Batch) -> Result<Tensor> {
         match &self.classifier {
             None => candle::bail!("`predict` is not implemented for this model"),
             Some(classifier) => {
-                let hidden_states = self.forward(batch)?;
+                let hidden_states = self.forward(batch, None)?;
                 classifier.forward(&hidden_states)
             }
         }
     }
+
+    fn predict_tokens(&self, batch: Batch) -> Result<Tensor> {
+        match &self.token_classifier {
+            None => candle::bail!("`predict_tokens` is not implemented for this model"),
+            Some(token_classifier) => {
+                // Token classifier models classify the hidden states of every token
+                let hidden_states = self.forward(batch, Some(self.encoder.layers.len()))?;
+                token_classifier.forward(&hidden_states)
+            }
+        }
+    }
 }
@@ -272,13 +272,20 @@ impl BertEncoder {
         Ok(BertEncoder { layers, span })
     }
 
-    fn forward(&self, hidden_states: &Tensor, cu_seqlens: &Tensor, max_s: usize) -> Result<Tensor> {
+    /// Run the first `num_layers` layers
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        cu_seqlens: &Tensor,
+        max_s: usize,
+        num_layers: usize,
+    ) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let mut hidden_states = hidden_states.clone();
 
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
+        for layer in self.layers.iter().take(num_layers) {
             hidden_states = layer.forward(&hidden_states, cu_seqlens, max_s)?
         }
 
@@ -322,6 +329,12 @@ impl FlashJinaBertModel {
             ModelType::Classifier => {
                 candle::bail!("`classifier` model type is not supported for Jina")
             }
+            ModelType::TokenClassifier => {
+                candle::bail!("`token_classifier` model type is not supported for Jina")
+            }
+            ModelType::Embedding(Pool::Splade) => {
+                candle::bail!("`splade` pooling is not supported for Jina")
+            }
             ModelType::Embedding(pool) => pool,
         };
 
@@ -362,7 +375,9 @@ impl FlashJinaBertModel {
         })
     }
 
-    pub fn forward(&self, batch: Batch) -> Result<Tensor> {
+    /// Pooled embeddings, or the unpooled hidden states of every token after `hidden_layer`
+    /// layers if it is set
+    pub fn forward(&self, batch: Batch, hidden_layer: Option<usize>) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let batch_size = batch.cumulative_seq_lengths.len() - 1;
@@ -382,9 +397,17 @@ impl FlashJinaBertModel {
             .embeddings
             .forward(&input_ids, &type_ids, &position_ids)?;
 
-        let outputs =
-            self.encoder
-                .forward(&embedding_output, &cu_seqlens, batch.max_length as usize)?;
+        let num_layers = hidden_layer.unwrap_or(self.encoder.layers.len());
+        let outputs = self.encoder.forward(
+            &embedding_output,
+            &cu_seqlens,
+            batch.max_length as usize,
+            num_layers,
+        )?;
+
+        if hidden_layer.is_some() {
+            return Ok(outputs);
+        }
 
         let results = match self.pool {
             // CLS pooling
@@ -410,6 +433,77 @@ impl FlashJinaBertModel {
                     (outputs.sum_keepdim(0)? / (batch.max_length as f64))?
                 }
             }
+            // Mean pooling divided by the square root of the sequence length
+            Pool::MeanSqrtLen => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let start = batch.cumulative_seq_lengths[i];
+                        let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                        let embeddings = outputs.narrow(0, start as usize, len as usize)?;
+                        embeddings.sum_keepdim(0)? / (len as f64).sqrt()
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Mean pooling weighted by the token positions
+            Pool::WeightedMean => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let start = batch.cumulative_seq_lengths[i];
+                        let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                        // Normalized position weights
+                        let total = (len * (len + 1) / 2) as f32;
+                        let weights: Vec<f32> = (1..=len).map(|j| j as f32 / total).collect();
+                        let weights = Tensor::from_vec(weights, (len as usize, 1), &self.device)?
+                            .to_dtype(outputs.dtype())?;
+
+                        let embeddings = outputs.narrow(0, start as usize, len as usize)?;
+                        embeddings.broadcast_mul(&weights)?.sum_keepdim(0)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Max pooling
+            Pool::Max => {
+                if batch_size > 1 {
+                    // for each request
+                    let results: Result<Vec<Tensor>> = (0..batch.cumulative_seq_lengths.len() - 1)
+                        .map(|i| {
+                            let start = batch.cumulative_seq_lengths[i];
+                            let len = batch.cumulative_seq_lengths[i + 1] - start;
+
+                            // Max
+                            outputs
+                                .narrow(0, start as usize, len as usize)?
+                                .max_keepdim(0)
+                        })
+                        .collect();
+
+                    // Concatenate all results
+                    Tensor::cat(&results?, 0)?
+                } else {
+                    outputs.max_keepdim(0)?
+                }
+            }
+            // Last token pooling
+            Pool::LastToken => {
+                let last_token_ids: Vec<u32> = batch.cumulative_seq_lengths[1..]
+                    .iter()
+                    .map(|end| end - 1)
+                    .collect();
+                let last_token_ids = Tensor::from_vec(last_token_ids, batch_size, &self.device)?;
+                outputs.index_select(&last_token_ids, 0)?
+            }
+            // Rejected when loading the model
+            Pool::Splade => unreachable!(),
         };
 
         Ok(results)
@@ -421,6 +515,16 @@ impl Model for FlashJinaBertModel {
         false
     }
     fn embed(&self, batch: Batch) -> Result<Tensor> {
-        self.forward(batch)
+        self.forward(batch, None)
+    }
+
+    fn embed_all(&self, batch: Batch, layer: Option<usize>) -> Result<Tensor> {
+        let num_layers = self.encoder.layers.len();
+        match layer {
+            Some(layer) if layer > num_layers => {
+                candle::bail!("`layer` must be <= {num_layers}. Given: {layer}")
+            }
+            layer => self.forward(batch, Some(layer.unwrap_or(num_layers))),
+        }
     }
 }
@@ -323,13 +323,19 @@ impl BertEncoder {
         Ok(BertEncoder { layers, span })
     }
 
-    fn forward(&self, hidden_states: &Tensor, attention_bias: Option<&Tensor>) -> Result<Tensor> {
+    /// Run the first `num_layers` layers
+    fn forward(
+        &self,
+        hidden_states: &Tensor,
+        attention_bias: Option<&Tensor>,
+        num_layers: usize,
+    ) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let mut hidden_states = hidden_states.clone();
 
         // Use a loop rather than a fold as it's easier to modify when adding debug/...
-        for layer in self.layers.iter() {
+        for layer in self.layers.iter().take(num_layers) {
             hidden_states = layer.forward(&hidden_states, attention_bias)?;
         }
 
@@ -368,6 +374,12 @@ impl JinaBertModel {
             ModelType::Classifier => {
                 candle::bail!("`classifier` model type is not supported for Jina")
             }
+            ModelType::TokenClassifier => {
+                candle::bail!("`token_classifier` model type is not supported for Jina")
+            }
+            ModelType::Embedding(Pool::Splade) => {
+                candle::bail!("`splade` pooling is not supported for Jina")
+            }
             ModelType::Embedding(pool) => pool,
         };
 
@@ -407,7 +419,9 @@ impl JinaBertModel {
         })
     }
 
-    pub fn forward(&self, batch: Batch) -> Result<Tensor> {
+    /// Pooled embeddings, or the unpooled hidden states of every token after `hidden_layer`
+    /// layers if it is set
+    pub fn forward(&self, batch: Batch, hidden_layer: Option<usize>) -> Result<Tensor> {
         let _enter = self.span.enter();
 
         let batch_size = batch.cumulative_seq_lengths.len() - 1;
@@ -462,8 +476,9 @@ impl JinaBertModel {
                 let (attention_bias, attention_mask) = match masking {
                     true => {
                         // We only need the mask if we use mean pooling
-                        // For CLS pooling, the bias is enough
-                        let attention_mask = if self.pool == Pool::Mean {
+                        // For the other poolings, the bias and the sequence lengths are enough
+                        let attention_mask = if matches!(self.pool, Pool::Mean | Pool::MeanSqrtLen)
+                        {
                             let attention_mask = Tensor::from_vec(
                                 attention_mask,
                                 (batch_size, max_length, 1),
@@ -569,9 +584,14 @@ impl JinaBertModel {
             .embeddings
             .forward(&input_ids, &type_ids, &position_ids)?;
 
-        let mut outputs = self
-            .encoder
-            .forward(&embedding_output, attention_bias.as_ref())?;
+        let num_layers = hidden_layer.unwrap_or(self.encoder.layers.len());
+        let mut outputs =
+            self.encoder
+                .forward(&embedding_output, attention_bias.as_ref(), num_layers)?;
+
+        if hidden_layer.is_some() {
+            return Ok(outputs);
+        }
 
         let results = match self.pool {
             // CLS pooling
@@ -585,6 +605,71 @@ impl JinaBertModel {
 
                 (outputs.sum(1)?.broadcast_div(&input_lengths))?
             }
+            // Mean pooling divided by the square root of the sequence length
+            Pool::MeanSqrtLen => {
+                if let Some(attention_mask) = attention_mask {
+                    // Mask padded values
+                    outputs = outputs.broadcast_mul(&attention_mask)?;
+                }
+
+                (outputs.sum(1)?.broadcast_div(&input_lengths.sqrt()?))?
+            }
+            // Mean pooling weighted by the token positions
+            Pool::WeightedMean => {
+                // Normalized position weights. Padded values have a zero weight
+                let mut weights = Vec::with_capacity(batch_size * max_length);
+                for i in 0..batch_size {
+                    let seq_length = (batch.cumulative_seq_lengths[i + 1]
+                        - batch.cumulative_seq_lengths[i])
+                        as usize;
+                    let total = (seq_length * (seq_length + 1) / 2) as f32;
+                    weights.extend((0..max_length).map(|j| match j < seq_length {
+                        true => (j + 1) as f32 / total,
+                        false => 0.0,
+                    }));
+                }
+                let weights = Tensor::from_vec(weights, (batch_size, max_length, 1), &self.device)?
+                    .to_dtype(self.dtype)?;
+
+                outputs.broadcast_mul(&weights)?.sum(1)?
+            }
+            // Max pooling
+            Pool::Max => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let seq_length =
+                            batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
+
+                        // Max over the non padded values
+                        outputs
+                            .i(i)?
+                            .narrow(0, 0, seq_length as usize)?
+                            .max_keepdim(0)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Last token pooling
+            Pool::LastToken => {
+                // for each request
+                let results: Result<Vec<Tensor>> = (0..batch_size)
+                    .map(|i| {
+                        let seq_length =
+                            batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
+
+                        // Padding is on the right
+                        outputs.i(i)?.narrow(0, seq_length as usize - 1, 1)
+                    })
+                    .collect();
+
+                // Concatenate all results
+                Tensor::cat(&results?, 0)?
+            }
+            // Rejected when loading the model
+            Pool::Splade => unreachable!(),
         };
 
         Ok(results)
@@ -596,6 +681,16 @@ impl Model for JinaBertModel {
         true
     }
     fn embed(&self, batch: Batch) -> Result<Tensor> {
-        self.forward(batch)
+        self.forward(batch, None)
+    }
+
+    fn embed_all(&self, batch: Batch, layer: Option<usize>) -> Result<Tensor> {
+        let num_layers = self.encoder.layers.len();
+        match layer {
+            Some(layer) if layer > num_layers => {
+                candle::bail!("`layer` must be <= {num_layers}. Given: {layer}")
+            }
+            layer => self.forward(batch, Some(layer.unwrap_or(num_layers))),
+        }
     }
 }
@@ -0,0 +1,136 @@
+/// Sentence-transformers modules applied after pooling
+use crate::layers::Linear;
+use candle::{DType, Device, Result, Tensor, D};
+use candle_nn::VarBuilder;
+use serde::Deserialize;
+use std::path::Path;
+
+const DENSE_MODULE: &str = "sentence_transformers.models.Dense";
+const NORMALIZE_MODULE: &str = "sentence_transformers.models.Normalize";
+
+#[derive(Debug, Deserialize)]
+struct ModuleConfig {
+    path: String,
+    #[serde(rename = "type")]
+    module_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DenseConfig {
+    in_features: usize,
+    out_features: usize,
+    bias: bool,
+    activation_function: Option<String>,
+}
+
+#[derive(Debug)]
+enum DenseActivation {
+    Identity,
+    Tanh,
+}
+
+#[derive(Debug)]
+pub(crate) struct Dense {
+    linear: Linear,
+    activation: DenseActivation,
+    span: tracing::Span,
+}
+
+impl Dense {
+    fn load(path: &Path, dtype: DType, device: &Device) -> Result<Self> {
+        let config = std::fs::read_to_string(path.join("config.json"))?;
+        let config: DenseConfig =
+            serde_json::from_str(&config).map_err(|err| candle::Error::Msg(err.to_string()))?;
+
+        // Sentence-transformers defaults to `Tanh`
+        let activation = match config.activation_function.as_deref() {
+            None | Some("torch.nn.modules.activation.Tanh") => DenseActivation::Tanh,
+            Some("torch.nn.modules.linear.Identity") => DenseActivation::Identity,
+            Some(activation) => {
+                candle::bail!("Dense activation `{activation}` is not supported")
+            }
+        };
+
+        let safetensors_path = path.join("model.safetensors");
+        let vb = if safetensors_path.exists() {
+            unsafe { VarBuilder::from_mmaped_safetensors(&[safetensors_path], dtype, device) }
+        } else {
+            VarBuilder::from_pth(path.join("pytorch_model.bin"), dtype, device)
+        }?;
+
+        let weight = vb.get((config.out_features, config.in_features), "linear.weight")?;
+        let bias = if config.bias {
+            Some(vb.get(config.out_features, "linear.bias")?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            linear: Linear::new(weight, bias, None),
+            activation,
+            span: tracing::span!(tracing::Level::TRACE, "dense"),
+        })
+    }
+
+    pub fn forward(&self, hidden_states: &Tensor) -> Result<Tensor> {
+        let _enter = self.span.enter();
+
+        let hidden_states = self.linear.forward(hidden_states)?;
+        match self.activation {
+            DenseActivation::Identity => Ok(hidden_states),
+            DenseActivation::Tanh => hidden_states.tanh(),
+        }
+    }
+}
+
+/// Modules listed in `modules.json`. Only `Dense` and `Normalize` change the pooled embeddings
+#[derive(Debug, Default)]
+pub(crate) struct Modules {
+    dense: Vec<Dense>,
+    normalize: bool,
+}
+
+impl Modules {
+    pub fn load(model_path: &Path, dtype: DType, device: &Device) -> Result<Self> {
+        let modules_path = model_path.join("modules.json");
+        if !modules_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let modules = std::fs::read_to_string(modules_path)?;
+        let modules: Vec<ModuleConfig> =
+            serde_json::from_str(&modules).map_err(|err| candle::Error::Msg(err.to_string()))?;
+
+        let mut dense = Vec::new();
+        let mut normalize = false;
+        for module in modules {
+            match module.module_type.as_str() {
+                DENSE_MODULE => {
+                    tracing::info!("Loading `{}` Dense module", module.path);
+                    dense.push(Dense::load(&model_path.join(&module.path), dtype, device)?)
+                }
+                NORMALIZE_MODULE => normalize = true,
+                _ => {}
+            }
+        }
+
+        Ok(Self { dense, normalize })
+    }
+
+    /// Apply the modules to the pooled embeddings
+    pub fn forward(&self, embeddings: Tensor) -> Result<Tensor> {
+        let mut embeddings = embeddings;
+        for dense in &self.dense {
+            embeddings = dense.forward(&embeddings)?;
+        }
+
+        if self.normalize {
+            // Normalize in f32 to avoid overflows
+            let embeddings = embeddings.to_dtype(DType::F32)?;
+            let norm = embeddings.sqr()?.sum_keepdim(D::Minus1)?.sqrt()?;
+            embeddings.broadcast_div(&norm)
+        } else {
+            Ok(embeddings)
+        }
+    }
+}
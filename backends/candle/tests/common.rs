@@ -73,6 +73,32 @@ pub fn download_artifacts(model_id: &'static str) -> Result<PathBuf> {
     Ok(model_root)
 }
 
+/// Download the sentence-transformers `modules.json` and the weights of its `Dense` modules.
+/// Only use it for models whose snapshots were taken with their modules applied.
+#[allow(dead_code)]
+pub fn download_modules(model_id: &'static str) -> Result<()> {
+    let builder = ApiBuilder::new().with_progress(false);
+
+    let api = builder.build().unwrap();
+    let api_repo = api.repo(Repo::new(model_id.to_string(), RepoType::Model));
+
+    let modules = std::fs::read_to_string(api_repo.get("modules.json")?)?;
+    let modules: Vec<serde_json::Value> = serde_json::from_str(&modules)?;
+
+    for module in modules {
+        if module["type"] != "sentence_transformers.models.Dense" {
+            continue;
+        }
+        let path = module["path"].as_str().unwrap();
+
+        api_repo.get(&format!("{path}/config.json"))?;
+        if api_repo.get(&format!("{path}/model.safetensors")).is_err() {
+            api_repo.get(&format!("{path}/pytorch_model.bin"))?;
+        }
+    }
+    Ok(())
+}
+
 pub fn relative_matcher() -> YamlMatcher<SnapshotScores> {
     YamlMatcher::new()
 }
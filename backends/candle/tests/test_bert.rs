@@ -2,7 +2,7 @@ mod common;
 
 use crate::common::SnapshotScores;
 use anyhow::Result;
-use common::{batch, download_artifacts, load_tokenizer, relative_matcher};
+use common::{batch, download_artifacts, download_modules, load_tokenizer, relative_matcher};
 use text_embeddings_backend_candle::CandleBackend;
 use text_embeddings_backend_core::{Backend, ModelType, Pool};
 
@@ -42,6 +42,257 @@ fn test_mini() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[serial_test::serial]
+fn test_mini_max() -> Result<()> {
+    let model_root = download_artifacts("sentence-transformers/all-MiniLM-L6-v2")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Max),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("mini_max_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = SnapshotScores::from(backend.embed(input_single)?);
+
+    insta::assert_yaml_snapshot!("mini_max_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
+#[test]
+#[serial_test::serial]
+fn test_mini_last_token() -> Result<()> {
+    let model_root = download_artifacts("sentence-transformers/all-MiniLM-L6-v2")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::LastToken),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("mini_last_token_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = SnapshotScores::from(backend.embed(input_single)?);
+
+    insta::assert_yaml_snapshot!("mini_last_token_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
+#[test]
+#[serial_test::serial]
+fn test_mini_weighted_mean() -> Result<()> {
+    let model_root = download_artifacts("sentence-transformers/all-MiniLM-L6-v2")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::WeightedMean),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("mini_weighted_mean_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = SnapshotScores::from(backend.embed(input_single)?);
+
+    insta::assert_yaml_snapshot!("mini_weighted_mean_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
+#[test]
+#[serial_test::serial]
+fn test_mini_mean_sqrt_len() -> Result<()> {
+    let model_root = download_artifacts("sentence-transformers/all-MiniLM-L6-v2")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root.clone(),
+        "float32".to_string(),
+        ModelType::Embedding(Pool::MeanSqrtLen),
+    )?;
+    let mean_backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("mini_mean_sqrt_len_batch", embeddings_batch, &matcher);
+
+    let encoding = tokenizer.encode("What is Deep Learning?", true).unwrap();
+    let length = encoding.len() as f32;
+
+    let embeddings_single = SnapshotScores::from(backend.embed(batch(vec![encoding.clone()]))?);
+
+    insta::assert_yaml_snapshot!("mini_mean_sqrt_len_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    // The sum divided by `sqrt(len)` is the mean multiplied by `sqrt(len)`
+    let mean = mean_backend.embed(batch(vec![encoding]))?;
+    let expected = SnapshotScores::from(vec![mean[0]
+        .iter()
+        .map(|v| v * length.sqrt())
+        .collect::<Vec<f32>>()]);
+    assert_eq!(embeddings_single[0], expected[0]);
+
+    Ok(())
+}
+
+#[test]
+#[serial_test::serial]
+fn test_splade() -> Result<()> {
+    let model_root = download_artifacts("naver/efficient-splade-VI-BT-large-query")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let config = std::fs::read_to_string(model_root.join("config.json"))?;
+    let config: serde_json::Value = serde_json::from_str(&config)?;
+    let vocab_size = config["vocab_size"].as_u64().unwrap() as usize;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Splade),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("splade_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = backend.embed(input_single)?;
+
+    // One non negative weight per vocabulary entry
+    assert_eq!(embeddings_single[0].len(), vocab_size);
+    assert!(embeddings_single[0].iter().all(|v| *v >= 0.0));
+    assert!(embeddings_single[0].iter().any(|v| *v > 0.0));
+
+    let embeddings_single = SnapshotScores::from(embeddings_single);
+
+    insta::assert_yaml_snapshot!("splade_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
+#[test]
+#[serial_test::serial]
+fn test_labse() -> Result<()> {
+    // LaBSE applies a `Dense` projection and a `Normalize` module after the CLS pooling
+    let model_root = download_artifacts("sentence-transformers/LaBSE")?;
+    download_modules("sentence-transformers/LaBSE")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Cls),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("labse_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = backend.embed(input_single)?;
+
+    assert_eq!(embeddings_single[0].len(), 768);
+    let norm = embeddings_single[0]
+        .iter()
+        .map(|v| v * v)
+        .sum::<f32>()
+        .sqrt();
+    assert!((norm - 1.0).abs() < 1e-3);
+
+    let embeddings_single = SnapshotScores::from(embeddings_single);
+
+    insta::assert_yaml_snapshot!("labse_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
 #[test]
 #[serial_test::serial]
 fn test_emotions() -> Result<()> {
@@ -47,6 +47,46 @@ fn test_flash_mini() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[serial_test::serial]
+#[cfg(all(
+    feature = "cuda",
+    any(feature = "flash-attn", feature = "flash-attn-v1")
+))]
+fn test_flash_splade() -> Result<()> {
+    let model_root = download_artifacts("naver/efficient-splade-VI-BT-large-query")?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let backend = CandleBackend::new(
+        model_root,
+        "float16".to_string(),
+        ModelType::Embedding(Pool::Splade),
+    )?;
+
+    let input_batch = batch(vec![
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+        tokenizer.encode("Deep Learning is...", true).unwrap(),
+        tokenizer.encode("What is Deep Learning?", true).unwrap(),
+    ]);
+
+    let matcher = relative_matcher();
+
+    let embeddings_batch = SnapshotScores::from(backend.embed(input_batch)?);
+    insta::assert_yaml_snapshot!("splade_batch", embeddings_batch, &matcher);
+
+    let input_single = batch(vec![tokenizer
+        .encode("What is Deep Learning?", true)
+        .unwrap()]);
+
+    let embeddings_single = SnapshotScores::from(backend.embed(input_single)?);
+
+    insta::assert_yaml_snapshot!("splade_single", embeddings_single, &matcher);
+    assert_eq!(embeddings_batch[0], embeddings_single[0]);
+    assert_eq!(embeddings_batch[2], embeddings_single[0]);
+
+    Ok(())
+}
+
 #[test]
 #[serial_test::serial]
 #[cfg(all(
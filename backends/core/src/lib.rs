@@ -25,11 +25,40 @@ pub trait Backend {
     fn embed(&self, batch: Batch) -> Result<Vec<Embedding>, BackendError>;
 
     fn predict(&self, batch: Batch) -> Result<Vec<Vec<f32>>, BackendError>;
+
+    /// Unpooled hidden states of every token of each sequence after `layer` layers, the last
+    /// one by default. Flattened to `seq_len * hidden_size` values
+    fn embed_all(
+        &self,
+        _batch: Batch,
+        _layer: Option<usize>,
+    ) -> Result<Vec<Vec<f32>>, BackendError> {
+        Err(BackendError::Inference(
+            "`embed_all` is not implemented for this backend".to_string(),
+        ))
+    }
+
+    /// Per-token vectors of a ColBERT model: the last hidden states of every token of each
+    /// sequence through the `linear` projection of the checkpoint. Flattened to `seq_len * dim`
+    /// values
+    fn embed_colbert(&self, _batch: Batch) -> Result<Vec<Vec<f32>>, BackendError> {
+        Err(BackendError::Inference(
+            "`embed_colbert` is not implemented for this backend".to_string(),
+        ))
+    }
+
+    /// Logits of every token of each sequence, flattened to `seq_len * n_labels` values
+    fn predict_tokens(&self, _batch: Batch) -> Result<Vec<Vec<f32>>, BackendError> {
+        Err(BackendError::Inference(
+            "`predict_tokens` is not implemented for this backend".to_string(),
+        ))
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ModelType {
     Classifier,
+    TokenClassifier,
     Embedding(Pool),
 }
 
@@ -38,6 +67,12 @@ pub enum ModelType {
 pub enum Pool {
     Cls,
     Mean,
+    MeanSqrtLen,
+    WeightedMean,
+    Max,
+    LastToken,
+    // Max pooling of `log(1 + relu(logits))` over the masked language modeling logits
+    Splade,
 }
 
 impl fmt::Display for Pool {
@@ -45,6 +80,11 @@ impl fmt::Display for Pool {
         match self {
             Pool::Cls => write!(f, "cls"),
             Pool::Mean => write!(f, "mean"),
+            Pool::MeanSqrtLen => write!(f, "mean-sqrt-len"),
+            Pool::WeightedMean => write!(f, "weighted-mean"),
+            Pool::Max => write!(f, "max"),
+            Pool::LastToken => write!(f, "last-token"),
+            Pool::Splade => write!(f, "splade"),
         }
     }
 }
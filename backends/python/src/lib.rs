@@ -25,6 +25,11 @@ impl PythonBackend {
                     "`classifier` model type is not supported".to_string(),
                 ))
             }
+            ModelType::TokenClassifier => {
+                return Err(BackendError::Start(
+                    "`token_classifier` model type is not supported".to_string(),
+                ))
+            }
             ModelType::Embedding(pool) => pool,
         };
 
@@ -89,6 +89,7 @@ impl Backend {
             };
             match &self.model_type {
                 ModelType::Classifier => self.predict(batch).await.map(|_| ()),
+                ModelType::TokenClassifier => self.predict_tokens(batch).await.map(|_| ()),
                 ModelType::Embedding(_) => self.embed(batch).await.map(|_| ()),
             }
         }
@@ -111,6 +112,42 @@ impl Backend {
         )
     }
 
+    #[instrument(skip_all)]
+    pub async fn embed_all(
+        &self,
+        batch: Batch,
+        layer: Option<usize>,
+    ) -> Result<(Vec<Vec<f32>>, Duration), BackendError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.backend_sender
+            .send(BackendCommand::EmbedAll(
+                batch,
+                layer,
+                Span::current(),
+                sender,
+            ))
+            .expect("No backend receiver. This is a bug.");
+        receiver.await.expect(
+            "Backend blocking task dropped the sender without send a response. This is a bug.",
+        )
+    }
+
+    #[instrument(skip_all)]
+    pub async fn embed_colbert(
+        &self,
+        batch: Batch,
+    ) -> Result<(Vec<Vec<f32>>, Duration), BackendError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.backend_sender
+            .send(BackendCommand::EmbedColbert(batch, Span::current(), sender))
+            .expect("No backend receiver. This is a bug.");
+        receiver.await.expect(
+            "Backend blocking task dropped the sender without send a response. This is a bug.",
+        )
+    }
+
     #[instrument(skip_all)]
     pub async fn predict(&self, batch: Batch) -> Result<(Vec<Vec<f32>>, Duration), BackendError> {
         let (sender, receiver) = oneshot::channel();
@@ -122,6 +159,25 @@ impl Backend {
             "Backend blocking task dropped the sender without send a response. This is a bug.",
         )
     }
+
+    #[instrument(skip_all)]
+    pub async fn predict_tokens(
+        &self,
+        batch: Batch,
+    ) -> Result<(Vec<Vec<f32>>, Duration), BackendError> {
+        let (sender, receiver) = oneshot::channel();
+
+        self.backend_sender
+            .send(BackendCommand::PredictTokens(
+                batch,
+                Span::current(),
+                sender,
+            ))
+            .expect("No backend receiver. This is a bug.");
+        receiver.await.expect(
+            "Backend blocking task dropped the sender without send a response. This is a bug.",
+        )
+    }
 }
 
 #[allow(unused)]
@@ -185,6 +241,20 @@ impl BackendThread {
                             (e, start.elapsed())
                         }));
                     }
+                    BackendCommand::EmbedAll(batch, layer, span, sender) => {
+                        let _span = span.entered();
+                        let _ = sender.send(backend.embed_all(batch, layer).map(|e| {
+                            healthy = true;
+                            (e, start.elapsed())
+                        }));
+                    }
+                    BackendCommand::EmbedColbert(batch, span, sender) => {
+                        let _span = span.entered();
+                        let _ = sender.send(backend.embed_colbert(batch).map(|e| {
+                            healthy = true;
+                            (e, start.elapsed())
+                        }));
+                    }
                     BackendCommand::Predict(batch, span, sender) => {
                         let _span = span.entered();
                         let _ = sender.send(backend.predict(batch).map(|e| {
@@ -192,6 +262,13 @@ impl BackendThread {
                             (e, start.elapsed())
                         }));
                     }
+                    BackendCommand::PredictTokens(batch, span, sender) => {
+                        let _span = span.entered();
+                        let _ = sender.send(backend.predict_tokens(batch).map(|e| {
+                            healthy = true;
+                            (e, start.elapsed())
+                        }));
+                    }
                 };
                 let _ = health_sender.send(healthy);
             }
@@ -213,10 +290,29 @@ enum BackendCommand {
         Span,
         oneshot::Sender<Result<(Vec<Embedding>, Duration), BackendError>>,
     ),
+    EmbedAll(
+        Batch,
+        Option<usize>,
+        Span,
+        #[allow(clippy::type_complexity)]
+        oneshot::Sender<Result<(Vec<Vec<f32>>, Duration), BackendError>>,
+    ),
+    EmbedColbert(
+        Batch,
+        Span,
+        #[allow(clippy::type_complexity)]
+        oneshot::Sender<Result<(Vec<Vec<f32>>, Duration), BackendError>>,
+    ),
     Predict(
         Batch,
         Span,
         #[allow(clippy::type_complexity)]
         oneshot::Sender<Result<(Vec<Vec<f32>>, Duration), BackendError>>,
     ),
+    PredictTokens(
+        Batch,
+        Span,
+        #[allow(clippy::type_complexity)]
+        oneshot::Sender<Result<(Vec<Vec<f32>>, Duration), BackendError>>,
+    ),
 }
@@ -0,0 +1,318 @@
+use crate::infer::InferResponse;
+use crate::tokenization::{EncodingInput, Truncation};
+use std::collections::{BTreeMap, HashMap};
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Estimated bookkeeping cost of an entry, on top of its inputs and results
+const ENTRY_OVERHEAD: usize = 128;
+
+/// Bounded LRU cache of inference results.
+///
+/// A single cache, and its memory budget, is shared by all the served models: each `Infer`
+/// holds a handle scoped to its own model with `Cache::for_model`.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    state: Arc<Mutex<CacheState>>,
+    model: Arc<str>,
+}
+
+impl Cache {
+    /// Create a cache using at most `max_memory` bytes
+    pub fn new(max_memory: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(CacheState {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+                memory: 0,
+                max_memory,
+            })),
+            model: Arc::from(""),
+        }
+    }
+
+    /// Handle on the same cache for the entries of `model`
+    pub fn for_model(&self, model: &str) -> Self {
+        Self {
+            state: self.state.clone(),
+            model: Arc::from(model),
+        }
+    }
+
+    /// Key of a request. Entries hold the raw model outputs so the post-processing parameters
+    /// are not part of the key
+    pub(crate) fn key(&self, inputs: &EncodingInput, truncation: Truncation) -> CacheKey {
+        CacheKey {
+            model: self.model.clone(),
+            inputs: inputs.clone(),
+            truncation,
+        }
+    }
+
+    /// Get a cached response and mark it as the most recently used
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<InferResponse> {
+        let mut state = self.lock();
+        state.tick += 1;
+        let tick = state.tick;
+
+        let response = match state.entries.get_mut(key) {
+            Some(entry) => {
+                let previous_tick = entry.tick;
+                entry.tick = tick;
+                let response = InferResponse {
+                    results: entry.results.clone(),
+                    prompt_tokens: entry.prompt_tokens,
+                    tokenization: Duration::default(),
+                    queue: Duration::default(),
+                    inference: Duration::default(),
+                    cached: true,
+                };
+                Some((previous_tick, response))
+            }
+            None => None,
+        };
+
+        match response {
+            Some((previous_tick, response)) => {
+                let key = state
+                    .recency
+                    .remove(&previous_tick)
+                    .expect("Cache recency is out of sync. This is a bug.");
+                state.recency.insert(tick, key);
+                metrics::increment_counter!("te_cache_hit");
+                Some(response)
+            }
+            None => {
+                metrics::increment_counter!("te_cache_miss");
+                None
+            }
+        }
+    }
+
+    /// Insert a response, evicting the least recently used entries to stay under the memory
+    /// budget
+    pub(crate) fn insert(&self, key: CacheKey, response: &InferResponse) {
+        let size = key.size() + response.results.len() * size_of::<f32>() + ENTRY_OVERHEAD;
+
+        let mut state = self.lock();
+        if size > state.max_memory || state.entries.contains_key(&key) {
+            return;
+        }
+
+        while state.memory + size > state.max_memory {
+            let (_, evicted) = state
+                .recency
+                .pop_first()
+                .expect("Cache memory is out of sync. This is a bug.");
+            let evicted = state
+                .entries
+                .remove(&evicted)
+                .expect("Cache entries are out of sync. This is a bug.");
+            state.memory -= evicted.size;
+            metrics::increment_counter!("te_cache_eviction");
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+        let key = Arc::new(key);
+        state.recency.insert(tick, key.clone());
+        state.entries.insert(
+            key,
+            CacheEntry {
+                results: response.results.clone(),
+                prompt_tokens: response.prompt_tokens,
+                tick,
+                size,
+            },
+        );
+        state.memory += size;
+        metrics::gauge!("te_cache_memory", state.memory as f64);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state
+            .lock()
+            .expect("Cache lock was poisoned. This is a bug.")
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub(crate) struct CacheKey {
+    model: Arc<str>,
+    inputs: EncodingInput,
+    truncation: Truncation,
+}
+
+impl CacheKey {
+    fn size(&self) -> usize {
+        let inputs = match &self.inputs {
+            EncodingInput::Single(s) => s.len(),
+            EncodingInput::Dual(s1, s2) => s1.len() + s2.len(),
+            EncodingInput::Ids(ids) => ids.len() * size_of::<u32>(),
+        };
+        inputs + size_of::<Self>()
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    results: Vec<f32>,
+    prompt_tokens: usize,
+    /// Last time this entry was used
+    tick: u64,
+    /// Estimated memory used by this entry
+    size: usize,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    entries: HashMap<Arc<CacheKey>, CacheEntry>,
+    /// Keys ordered from the least to the most recently used
+    recency: BTreeMap<u64, Arc<CacheKey>>,
+    tick: u64,
+    memory: usize,
+    max_memory: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::normalize_embedding;
+
+    fn response(results: Vec<f32>) -> InferResponse {
+        InferResponse {
+            results,
+            prompt_tokens: 3,
+            tokenization: Duration::default(),
+            queue: Duration::default(),
+            inference: Duration::default(),
+            cached: false,
+        }
+    }
+
+    fn single(input: &str) -> EncodingInput {
+        EncodingInput::Single(input.to_string())
+    }
+
+    #[test]
+    fn hits_are_flagged() {
+        let cache = Cache::new(1 << 20);
+        let key = cache.key(&single("a"), Truncation::default());
+
+        assert!(cache.get(&key).is_none());
+        cache.insert(
+            cache.key(&single("a"), Truncation::default()),
+            &response(vec![1.0]),
+        );
+
+        let hit = cache.get(&key).unwrap();
+        assert!(hit.cached);
+        assert_eq!(hit.results, vec![1.0]);
+        assert_eq!(hit.prompt_tokens, 3);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_under_budget() {
+        let cache = Cache::new(1 << 20);
+        cache.insert(
+            cache.key(&single("a"), Truncation::default()),
+            &response(vec![0.0; 4]),
+        );
+        let size = cache.lock().memory;
+
+        // Room for two entries
+        let cache = Cache::new(2 * size + size / 2);
+        for input in ["a", "b"] {
+            cache.insert(
+                cache.key(&single(input), Truncation::default()),
+                &response(vec![0.0; 4]),
+            );
+        }
+        // `a` becomes the most recently used
+        assert!(cache
+            .get(&cache.key(&single("a"), Truncation::default()))
+            .is_some());
+        cache.insert(
+            cache.key(&single("c"), Truncation::default()),
+            &response(vec![0.0; 4]),
+        );
+
+        assert!(cache
+            .get(&cache.key(&single("a"), Truncation::default()))
+            .is_some());
+        assert!(cache
+            .get(&cache.key(&single("b"), Truncation::default()))
+            .is_none());
+        assert!(cache
+            .get(&cache.key(&single("c"), Truncation::default()))
+            .is_some());
+        assert_eq!(cache.lock().memory, 2 * size);
+
+        // Entries larger than the whole budget are never cached
+        cache.insert(
+            cache.key(&single("d"), Truncation::default()),
+            &response(vec![0.0; 1024]),
+        );
+        assert!(cache
+            .get(&cache.key(&single("d"), Truncation::default()))
+            .is_none());
+        assert_eq!(cache.lock().entries.len(), 2);
+    }
+
+    #[test]
+    fn keys_are_separated() {
+        let cache = Cache::new(1 << 20);
+        let truncate = Truncation {
+            truncate: true,
+            ..Default::default()
+        };
+        let pair = EncodingInput::Dual("a".to_string(), "b".to_string());
+        cache.insert(
+            cache.key(&single("a"), Truncation::default()),
+            &response(vec![1.0]),
+        );
+
+        assert!(cache.get(&cache.key(&single("a"), truncate)).is_none());
+        assert!(cache
+            .get(&cache.key(&pair, Truncation::default()))
+            .is_none());
+        let other_model = cache.for_model("other");
+        assert!(other_model
+            .get(&other_model.key(&single("a"), Truncation::default()))
+            .is_none());
+
+        cache.insert(cache.key(&single("a"), truncate), &response(vec![2.0]));
+        cache.insert(
+            cache.key(&pair, Truncation::default()),
+            &response(vec![3.0]),
+        );
+        let get = |inputs: &EncodingInput, truncation| cache.get(&cache.key(inputs, truncation));
+        assert_eq!(
+            get(&single("a"), Truncation::default()).unwrap().results,
+            vec![1.0]
+        );
+        assert_eq!(get(&single("a"), truncate).unwrap().results, vec![2.0]);
+        assert_eq!(
+            get(&pair, Truncation::default()).unwrap().results,
+            vec![3.0]
+        );
+    }
+
+    #[test]
+    fn entries_hold_raw_outputs() {
+        let cache = Cache::new(1 << 20);
+        let key = cache.key(&single("a"), Truncation::default());
+        cache.insert(
+            cache.key(&single("a"), Truncation::default()),
+            &response(vec![3.0, 4.0]),
+        );
+
+        // Normalization is applied to the returned copy, not to the entry
+        let mut normalized = cache.get(&key).unwrap();
+        normalize_embedding(&mut normalized.results);
+        assert_eq!(normalized.results, vec![0.6, 0.8]);
+        assert_eq!(cache.get(&key).unwrap().results, vec![3.0, 4.0]);
+    }
+}
@@ -0,0 +1,45 @@
+/// Long document chunking
+use crate::infer::normalize_embedding;
+
+/// Embedding of a window of a long input
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub embedding: Vec<f32>,
+    /// Character span of the window in the input
+    pub start: usize,
+    pub end: usize,
+    pub prompt_tokens: usize,
+}
+
+/// How the chunk embeddings of a document are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkAggregation {
+    Mean,
+    /// Mean weighted by the number of tokens of each chunk
+    WeightedMean,
+}
+
+/// Combine the chunk embeddings of a document into a single embedding
+pub fn aggregate(chunks: &[Chunk], aggregation: ChunkAggregation, normalize: bool) -> Vec<f32> {
+    let size = chunks.first().map(|c| c.embedding.len()).unwrap_or(0);
+    let weights: Vec<f32> = chunks
+        .iter()
+        .map(|c| match aggregation {
+            ChunkAggregation::Mean => 1.0,
+            ChunkAggregation::WeightedMean => c.prompt_tokens as f32,
+        })
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    let mut embedding = vec![0.0; size];
+    for (chunk, weight) in chunks.iter().zip(weights) {
+        for (v, c) in embedding.iter_mut().zip(&chunk.embedding) {
+            *v += c * weight / total;
+        }
+    }
+
+    if normalize {
+        normalize_embedding(&mut embedding);
+    }
+    embedding
+}
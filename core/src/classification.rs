@@ -0,0 +1,34 @@
+/// Function applied to the logits of a classifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionToApply {
+    Softmax,
+    Sigmoid,
+    None,
+}
+
+/// Apply `function` to `logits` in place
+pub fn apply(function: FunctionToApply, logits: &mut [f32]) {
+    match function {
+        FunctionToApply::Softmax => {
+            let max = *logits
+                .iter()
+                .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap())
+                .unwrap();
+
+            let mut den = 0.0;
+            for v in logits.iter_mut() {
+                *v = (*v - max).exp();
+                den += *v;
+            }
+            for v in logits.iter_mut() {
+                *v /= den;
+            }
+        }
+        FunctionToApply::Sigmoid => {
+            for v in logits.iter_mut() {
+                *v = 1.0 / (1.0 + (-*v).exp());
+            }
+        }
+        FunctionToApply::None => {}
+    }
+}
@@ -0,0 +1,69 @@
+/// ColBERT multi-vector embeddings
+use crate::tokenization::{Encoding, Truncation};
+
+/// Side of a late interaction the inputs are encoded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputType {
+    Query,
+    Document,
+}
+
+/// Markers and lengths of a ColBERT checkpoint (`artifact.metadata`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColbertConfig {
+    /// Token inserted after the first special token of queries
+    pub query_marker_id: u32,
+    /// Token inserted after the first special token of documents
+    pub document_marker_id: u32,
+    /// Token queries are padded with
+    pub mask_token_id: u32,
+    /// Length of the queries, markers and padding included
+    pub query_maxlen: usize,
+    /// Maximum length of the documents, markers included
+    pub document_maxlen: usize,
+}
+
+impl ColbertConfig {
+    /// Inputs are always truncated to leave room for the marker
+    pub(crate) fn truncation(&self, input_type: InputType) -> Truncation {
+        let maxlen = match input_type {
+            InputType::Query => self.query_maxlen,
+            InputType::Document => self.document_maxlen,
+        };
+        Truncation {
+            truncate: true,
+            max_tokens: Some(maxlen.saturating_sub(1).max(1)),
+            ..Default::default()
+        }
+    }
+
+    /// Insert the marker after the first special token and augment queries with mask tokens
+    pub(crate) fn prepare(&self, encoding: &mut Encoding, input_type: InputType) {
+        let marker_id = match input_type {
+            InputType::Query => self.query_marker_id,
+            InputType::Document => self.document_marker_id,
+        };
+        let index = encoding.input_ids.len().min(1);
+        encoding.input_ids.insert(index, marker_id);
+        encoding.token_type_ids.insert(index, 0);
+        push_position(encoding);
+
+        if input_type == InputType::Query {
+            while encoding.input_ids.len() < self.query_maxlen {
+                encoding.input_ids.push(self.mask_token_id);
+                encoding.token_type_ids.push(0);
+                push_position(encoding);
+            }
+        }
+    }
+}
+
+/// Positions are contiguous, the new token takes the next one
+fn push_position(encoding: &mut Encoding) {
+    let position = encoding
+        .position_ids
+        .last()
+        .map(|position| position + 1)
+        .unwrap_or_default();
+    encoding.position_ids.push(position);
+}
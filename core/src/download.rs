@@ -32,3 +32,39 @@ pub async fn download_pool_config(api: &ApiRepo) -> Result<PathBuf, ApiError> {
     let pool_config_path = api.get("1_Pooling/config.json").await?;
     Ok(pool_config_path)
 }
+
+/// Download the optional configuration files that set the maximum sequence length of a model
+#[instrument(skip_all)]
+pub async fn download_max_length_configs(api: &ApiRepo) {
+    let _ = api.get("sentence_bert_config.json").await;
+    let _ = api.get("tokenizer_config.json").await;
+}
+
+/// Download the optional sentence-transformers configuration holding the model prompts
+#[instrument(skip_all)]
+pub async fn download_prompts_config(api: &ApiRepo) {
+    let _ = api.get("config_sentence_transformers.json").await;
+}
+
+/// Download the optional metadata of ColBERT checkpoints
+#[instrument(skip_all)]
+pub async fn download_colbert_metadata(api: &ApiRepo) {
+    let _ = api.get("artifact.metadata").await;
+}
+
+#[instrument(skip_all)]
+pub async fn download_st_modules(api: &ApiRepo) -> Result<PathBuf, ApiError> {
+    let modules_path = api.get("modules.json").await?;
+    Ok(modules_path)
+}
+
+/// Download the config and weights of a sentence-transformers `Dense` module
+#[instrument(skip(api))]
+pub async fn download_dense_module(api: &ApiRepo, path: &str) -> Result<PathBuf, ApiError> {
+    api.get(&format!("{path}/config.json")).await?;
+
+    match api.get(&format!("{path}/model.safetensors")).await {
+        Ok(p) => Ok(p),
+        Err(_) => api.get(&format!("{path}/pytorch_model.bin")).await,
+    }
+}
@@ -1,9 +1,15 @@
-use crate::queue::{Entry, Metadata, NextBatch, Queue};
-use crate::tokenization::{EncodingInput, Tokenization};
+use crate::cache::Cache;
+use crate::chunking::Chunk;
+use crate::classification::{self, FunctionToApply};
+use crate::colbert::{ColbertConfig, InputType};
+use crate::quantization::{self, Precision, QuantizationRanges};
+use crate::queue::{Entry, Metadata, NextBatch, Output, Priority, Queue};
+use crate::tokenization::{Encoding, EncodingInput, Tokenization, Truncation};
 use crate::TextEmbeddingsError;
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{Backend, BackendError, ModelType};
+use text_embeddings_backend::{Backend, BackendError, ModelType, Pool};
 use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit, Semaphore};
 use tracing::{instrument, Span};
 
@@ -16,6 +22,14 @@ pub struct Infer {
     notify_batching_task: Arc<Notify>,
     /// Inference limit
     limit_concurrent_requests: Arc<Semaphore>,
+    /// Number of permits the inference limit is configured with
+    max_concurrent_requests: Arc<Mutex<ConcurrencyLimit>>,
+    /// Cache of inference results
+    cache: Option<Cache>,
+    /// Calibration ranges of the scalar quantization
+    quantization_ranges: Option<Arc<QuantizationRanges>>,
+    /// Markers and lengths of ColBERT models
+    colbert: Option<ColbertConfig>,
     backend: Backend,
 }
 
@@ -26,6 +40,9 @@ impl Infer {
         queue: Queue,
         max_concurrent_requests: usize,
         backend: Backend,
+        cache: Option<Cache>,
+        quantization_ranges: Option<QuantizationRanges>,
+        colbert: Option<ColbertConfig>,
     ) -> Self {
         let notify_batching_task = Arc::new(Notify::new());
 
@@ -54,6 +71,13 @@ impl Infer {
             queue,
             notify_batching_task,
             limit_concurrent_requests: semaphore,
+            max_concurrent_requests: Arc::new(Mutex::new(ConcurrencyLimit {
+                limit: max_concurrent_requests,
+                retiring: 0,
+            })),
+            cache,
+            quantization_ranges: quantization_ranges.map(Arc::new),
+            colbert,
             backend,
         }
     }
@@ -80,15 +104,212 @@ impl Infer {
             .expect("Semaphore has been closed. This is a bug.")
     }
 
+    /// Update the maximum number of concurrent requests.
+    /// Requests holding a permit are not affected: if the limit is lowered, permits are retired
+    /// as they are released. Raising the limit first cancels the permits still to be retired
+    #[instrument(skip(self))]
+    pub fn set_max_concurrent_requests(&self, max_concurrent_requests: usize) {
+        let mut current = self
+            .max_concurrent_requests
+            .lock()
+            .expect("Lock was poisoned. This is a bug.");
+
+        match max_concurrent_requests.cmp(&current.limit) {
+            Ordering::Greater => {
+                let added = max_concurrent_requests - current.limit;
+                let cancelled = added.min(current.retiring);
+                current.retiring -= cancelled;
+                self.limit_concurrent_requests
+                    .add_permits(added - cancelled);
+            }
+            Ordering::Less => {
+                current.retiring += current.limit - max_concurrent_requests;
+                let semaphore = self.limit_concurrent_requests.clone();
+                let limit = self.max_concurrent_requests.clone();
+                tokio::spawn(retire_permits(semaphore, limit));
+            }
+            Ordering::Equal => {}
+        }
+        current.limit = max_concurrent_requests;
+    }
+
+    /// Update the maximum number of tokens in a batch
+    #[instrument(skip(self))]
+    pub fn set_max_batch_tokens(&self, max_batch_tokens: usize) {
+        self.queue.set_max_batch_tokens(max_batch_tokens)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, _permit))]
     pub async fn embed<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
         inputs: I,
-        truncate: bool,
+        truncation: Truncation,
         normalize: bool,
+        dimensions: Option<usize>,
+        precision: Precision,
+        priority: Priority,
+        deadline: Option<Instant>,
         _permit: OwnedSemaphorePermit,
     ) -> Result<InferResponse, TextEmbeddingsError> {
-        if self.is_classifier() {
+        self.check_dense_embedding_model()?;
+
+        quantization::validate(precision, self.quantization_ranges.as_deref()).map_err(|err| {
+            metrics::increment_counter!("te_request_failure", "err" => "validation");
+            tracing::error!("{err}");
+            err
+        })?;
+
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_embed_count");
+
+        let mut response = self
+            .infer(inputs.into(), truncation, priority, deadline, start_time)
+            .await?;
+
+        if let Some(dimensions) = dimensions {
+            // Matryoshka truncation
+            response.results.truncate(dimensions);
+        }
+
+        if normalize {
+            normalize_embedding(&mut response.results);
+        }
+
+        if precision != Precision::Float32 {
+            response.results = quantization::quantize(
+                &response.results,
+                precision,
+                self.quantization_ranges.as_deref(),
+            );
+        }
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        metrics::increment_counter!("te_embed_success");
+        metrics::histogram!("te_embed_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_tokenization_duration",
+            response.tokenization.as_secs_f64()
+        );
+        metrics::histogram!("te_embed_queue_duration", response.queue.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_inference_duration",
+            response.inference.as_secs_f64()
+        );
+
+        Ok(response)
+    }
+
+    /// Embed the overlapping windows of a long input. Inputs split in more than `max_chunks`
+    /// windows are rejected. Chunks are not cached
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, _permit))]
+    pub async fn embed_chunks(
+        &self,
+        inputs: String,
+        chunk_size: usize,
+        stride: usize,
+        max_chunks: usize,
+        normalize: bool,
+        priority: Priority,
+        deadline: Option<Instant>,
+        _permit: OwnedSemaphorePermit,
+    ) -> Result<InferChunksResponse, TextEmbeddingsError> {
+        self.check_dense_embedding_model()?;
+
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_embed_chunks_count");
+
+        // Tokenization
+        let chunk_encodings = self
+            .tokenization
+            .encode_chunks(inputs, chunk_size, stride, max_chunks)
+            .await
+            .map_err(|err| {
+                metrics::increment_counter!("te_request_failure", "err" => "tokenization");
+                tracing::error!("{err}");
+                err
+            })?;
+        let tokenization = start_time.elapsed();
+
+        // Append all the chunks before waiting so that they can be batched together
+        let receivers: Vec<_> = chunk_encodings
+            .into_iter()
+            .map(|chunk| {
+                let response_rx = self.append(
+                    chunk.encoding,
+                    priority,
+                    deadline,
+                    tokenization,
+                    Output::Model,
+                );
+                (response_rx, chunk.start, chunk.end)
+            })
+            .collect();
+        self.notify_batching_task.notify_one();
+
+        let mut chunks = Vec::with_capacity(receivers.len());
+        let mut queue = Duration::ZERO;
+        let mut inference = Duration::ZERO;
+        for (response_rx, start, end) in receivers {
+            let mut response = self.response(response_rx).await?;
+            if normalize {
+                normalize_embedding(&mut response.results);
+            }
+
+            // Chunks are inferred concurrently
+            queue = queue.max(response.queue);
+            inference = inference.max(response.inference);
+            chunks.push(Chunk {
+                embedding: response.results,
+                start,
+                end,
+                prompt_tokens: response.prompt_tokens,
+            });
+        }
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        metrics::increment_counter!("te_embed_chunks_success");
+        metrics::histogram!("te_embed_chunks_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_chunks_tokenization_duration",
+            tokenization.as_secs_f64()
+        );
+        metrics::histogram!("te_embed_chunks_queue_duration", queue.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_chunks_inference_duration",
+            inference.as_secs_f64()
+        );
+
+        Ok(InferChunksResponse {
+            prompt_tokens: chunks.iter().map(|c| c.prompt_tokens).sum(),
+            chunks,
+            tokenization,
+            queue,
+            inference,
+        })
+    }
+
+    /// Unpooled hidden states of every token after `layer` layers, the last one by default.
+    /// Hidden states are not cached
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, _permit))]
+    pub async fn embed_all<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncation: Truncation,
+        layer: Option<usize>,
+        priority: Priority,
+        deadline: Option<Instant>,
+        _permit: OwnedSemaphorePermit,
+    ) -> Result<InferAllResponse, TextEmbeddingsError> {
+        if self.is_classifier() || self.is_token_classifier() {
             metrics::increment_counter!("te_request_failure", "err" => "model_type");
             let message = "Model is not an embedding model".to_string();
             tracing::error!("{message}");
@@ -98,12 +319,12 @@ impl Infer {
         }
 
         let start_time = Instant::now();
-        metrics::increment_counter!("te_embed_count");
+        metrics::increment_counter!("te_embed_all_count");
 
         // Tokenization
         let encoding = self
             .tokenization
-            .encode(inputs.into(), truncate)
+            .encode(inputs.into(), truncation)
             .await
             .map_err(|err| {
                 metrics::increment_counter!("te_request_failure", "err" => "tokenization");
@@ -111,64 +332,152 @@ impl Infer {
                 err
             })?;
 
-        // MPSC channel to communicate with the background batching task
-        let (response_tx, response_rx) = oneshot::channel();
+        let response = self
+            .hidden_states(
+                encoding,
+                Output::HiddenStates { layer },
+                priority,
+                deadline,
+                start_time,
+            )
+            .await?;
 
-        // Append the request to the queue
-        self.queue.append(Entry {
-            metadata: Metadata {
-                response_tx,
-                span: Span::current(),
-                tokenization: start_time.elapsed(),
-                queue_time: Instant::now(),
-                prompt_tokens: encoding.input_ids.len(),
-            },
-            encoding,
-        });
+        // Timings
+        let total_time = start_time.elapsed();
 
-        self.notify_batching_task.notify_one();
+        // Metrics
+        metrics::increment_counter!("te_embed_all_success");
+        metrics::histogram!("te_embed_all_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_all_tokenization_duration",
+            response.tokenization.as_secs_f64()
+        );
+        metrics::histogram!("te_embed_all_queue_duration", response.queue.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_all_inference_duration",
+            response.inference.as_secs_f64()
+        );
+
+        Ok(response)
+    }
+
+    /// L2 normalized per-token vectors of a ColBERT model. Queries are padded with mask tokens
+    /// up to the query length of the model. Vectors are not cached
+    #[instrument(skip(self, _permit))]
+    pub async fn embed_colbert<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        input_type: InputType,
+        priority: Priority,
+        deadline: Option<Instant>,
+        _permit: OwnedSemaphorePermit,
+    ) -> Result<InferAllResponse, TextEmbeddingsError> {
+        let colbert = match &self.colbert {
+            Some(colbert) => colbert,
+            None => {
+                metrics::increment_counter!("te_request_failure", "err" => "model_type");
+                let message = "Model is not a ColBERT model".to_string();
+                tracing::error!("{message}");
+                return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                    message,
+                )));
+            }
+        };
 
-        let mut response = response_rx
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_embed_colbert_count");
+
+        // Tokenization
+        let mut encoding = self
+            .tokenization
+            .encode(inputs.into(), colbert.truncation(input_type))
             .await
-            .expect(
-                "Infer batching task dropped the sender without sending a response. This is a bug.",
-            )
             .map_err(|err| {
-                metrics::increment_counter!("te_request_failure", "err" => "inference");
+                metrics::increment_counter!("te_request_failure", "err" => "tokenization");
                 tracing::error!("{err}");
                 err
             })?;
+        colbert.prepare(&mut encoding, input_type);
+
+        let mut response = self
+            .hidden_states(
+                encoding,
+                Output::ColbertVectors,
+                priority,
+                deadline,
+                start_time,
+            )
+            .await?;
+        response
+            .results
+            .iter_mut()
+            .for_each(|vector| normalize_embedding(vector));
 
-        if normalize {
-            // Normalize embedding
-            let scale = (1.0
-                / response
-                    .results
-                    .iter()
-                    .map(|v| {
-                        let v = *v as f64;
-                        v * v
-                    })
-                    .sum::<f64>()
-                    .sqrt()) as f32;
-            for v in response.results.iter_mut() {
-                *v *= scale;
-            }
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        metrics::increment_counter!("te_embed_colbert_success");
+        metrics::histogram!("te_embed_colbert_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_embed_colbert_tokenization_duration",
+            response.tokenization.as_secs_f64()
+        );
+        metrics::histogram!(
+            "te_embed_colbert_queue_duration",
+            response.queue.as_secs_f64()
+        );
+        metrics::histogram!(
+            "te_embed_colbert_inference_duration",
+            response.inference.as_secs_f64()
+        );
+
+        Ok(response)
+    }
+
+    /// Vocabulary sized `log(1 + relu(logits))` vector of a SPLADE model. Most of the values
+    /// are zeros
+    #[instrument(skip(self, _permit))]
+    pub async fn embed_sparse<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncation: Truncation,
+        priority: Priority,
+        deadline: Option<Instant>,
+        _permit: OwnedSemaphorePermit,
+    ) -> Result<InferResponse, TextEmbeddingsError> {
+        if !self.is_splade() {
+            metrics::increment_counter!("te_request_failure", "err" => "model_type");
+            let message = "Model is not a SPLADE model".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
         }
 
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_embed_sparse_count");
+
+        let response = self
+            .infer(inputs.into(), truncation, priority, deadline, start_time)
+            .await?;
+
         // Timings
         let total_time = start_time.elapsed();
 
         // Metrics
-        metrics::increment_counter!("te_embed_success");
-        metrics::histogram!("te_embed_duration", total_time.as_secs_f64());
+        metrics::increment_counter!("te_embed_sparse_success");
+        metrics::histogram!("te_embed_sparse_duration", total_time.as_secs_f64());
         metrics::histogram!(
-            "te_embed_tokenization_duration",
+            "te_embed_sparse_tokenization_duration",
             response.tokenization.as_secs_f64()
         );
-        metrics::histogram!("te_embed_queue_duration", response.queue.as_secs_f64());
         metrics::histogram!(
-            "te_embed_inference_duration",
+            "te_embed_sparse_queue_duration",
+            response.queue.as_secs_f64()
+        );
+        metrics::histogram!(
+            "te_embed_sparse_inference_duration",
             response.inference.as_secs_f64()
         );
 
@@ -179,8 +488,10 @@ impl Infer {
     pub async fn predict<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
         inputs: I,
-        truncate: bool,
-        raw_scores: bool,
+        truncation: Truncation,
+        function_to_apply: FunctionToApply,
+        priority: Priority,
+        deadline: Option<Instant>,
         _permit: OwnedSemaphorePermit,
     ) -> Result<InferResponse, TextEmbeddingsError> {
         if !self.is_classifier() {
@@ -194,10 +505,138 @@ impl Infer {
         let start_time = Instant::now();
         metrics::increment_counter!("te_predict_count");
 
+        let mut response = self
+            .infer(inputs.into(), truncation, priority, deadline, start_time)
+            .await?;
+
+        classification::apply(function_to_apply, &mut response.results);
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        metrics::increment_counter!("te_predict_success");
+        metrics::histogram!("te_predict_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_predict_tokenization_duration",
+            response.tokenization.as_secs_f64()
+        );
+        metrics::histogram!("te_predict_queue_duration", response.queue.as_secs_f64());
+        metrics::histogram!(
+            "te_predict_inference_duration",
+            response.inference.as_secs_f64()
+        );
+
+        Ok(response)
+    }
+
+    /// Logits of every token of `inputs`, flattened to `n_tokens * n_labels` values, along with
+    /// the tokenizer encoding of `inputs` holding the character offsets of the tokens.
+    /// `inputs` is never truncated
+    #[instrument(skip(self, _permit))]
+    pub async fn predict_tokens(
+        &self,
+        inputs: String,
+        priority: Priority,
+        deadline: Option<Instant>,
+        _permit: OwnedSemaphorePermit,
+    ) -> Result<(tokenizers::Encoding, InferResponse), TextEmbeddingsError> {
+        if !self.is_token_classifier() {
+            metrics::increment_counter!("te_request_failure", "err" => "model_type");
+            let message = "Model is not a token classifier model".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
+        }
+
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_token_classify_count");
+
+        // Cache key of the same inputs through `infer`
+        let cache_key = self.cache.as_ref().map(|cache| {
+            (
+                cache,
+                cache.key(&inputs.clone().into(), Truncation::default()),
+            )
+        });
+
+        // Tokenization
+        let (encoding, offsets) = self
+            .tokenization
+            .encode_with_offsets(inputs)
+            .await
+            .map_err(|err| {
+                metrics::increment_counter!("te_request_failure", "err" => "tokenization");
+                tracing::error!("{err}");
+                err
+            })?;
+
+        let response = match cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            Some(response) => response,
+            None => {
+                let response_rx = self.append(
+                    encoding,
+                    priority,
+                    deadline,
+                    start_time.elapsed(),
+                    Output::Model,
+                );
+                self.notify_batching_task.notify_one();
+                let response = self.response(response_rx).await?;
+                if let Some((cache, key)) = cache_key {
+                    cache.insert(key, &response);
+                }
+                response
+            }
+        };
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        metrics::increment_counter!("te_token_classify_success");
+        metrics::histogram!("te_token_classify_duration", total_time.as_secs_f64());
+        metrics::histogram!(
+            "te_token_classify_tokenization_duration",
+            response.tokenization.as_secs_f64()
+        );
+        metrics::histogram!(
+            "te_token_classify_queue_duration",
+            response.queue.as_secs_f64()
+        );
+        metrics::histogram!(
+            "te_token_classify_inference_duration",
+            response.inference.as_secs_f64()
+        );
+
+        Ok((offsets, response))
+    }
+
+    /// Raw model outputs for `inputs`, from the cache or from the batching queue.
+    /// The cache stores outputs before any post-processing so that it is shared by all the
+    /// request parameters applied after inference
+    async fn infer(
+        &self,
+        inputs: EncodingInput,
+        truncation: Truncation,
+        priority: Priority,
+        deadline: Option<Instant>,
+        start_time: Instant,
+    ) -> Result<InferResponse, TextEmbeddingsError> {
+        // Cache lookup
+        let cache_key = self
+            .cache
+            .as_ref()
+            .map(|cache| (cache, cache.key(&inputs, truncation)));
+        if let Some(response) = cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            return Ok(response);
+        }
+
         // Tokenization
         let encoding = self
             .tokenization
-            .encode(inputs.into(), truncate)
+            .encode(inputs, truncation)
             .await
             .map_err(|err| {
                 metrics::increment_counter!("te_request_failure", "err" => "tokenization");
@@ -205,6 +644,65 @@ impl Infer {
                 err
             })?;
 
+        let response_rx = self.append(
+            encoding,
+            priority,
+            deadline,
+            start_time.elapsed(),
+            Output::Model,
+        );
+        self.notify_batching_task.notify_one();
+
+        let response = self.response(response_rx).await?;
+
+        if let Some((cache, key)) = cache_key {
+            cache.insert(key, &response);
+        }
+
+        Ok(response)
+    }
+
+    /// Per-token vectors of an encoding, hidden states or ColBERT vectors depending on `output`,
+    /// from the batching queue
+    async fn hidden_states(
+        &self,
+        encoding: Encoding,
+        output: Output,
+        priority: Priority,
+        deadline: Option<Instant>,
+        start_time: Instant,
+    ) -> Result<InferAllResponse, TextEmbeddingsError> {
+        let response_rx = self.append(encoding, priority, deadline, start_time.elapsed(), output);
+        self.notify_batching_task.notify_one();
+
+        let response = self.response(response_rx).await?;
+
+        // The backend flattens the hidden states of the tokens
+        let hidden_size = response.results.len() / response.prompt_tokens;
+        let results = response
+            .results
+            .chunks(hidden_size)
+            .map(|hidden_state| hidden_state.to_vec())
+            .collect();
+
+        Ok(InferAllResponse {
+            results,
+            prompt_tokens: response.prompt_tokens,
+            tokenization: response.tokenization,
+            queue: response.queue,
+            inference: response.inference,
+        })
+    }
+
+    /// Append an encoding to the batching queue. The batching task still needs to be notified
+    fn append(
+        &self,
+        encoding: Encoding,
+        priority: Priority,
+        deadline: Option<Instant>,
+        tokenization: Duration,
+        output: Output,
+    ) -> oneshot::Receiver<Result<InferResponse, TextEmbeddingsError>> {
         // MPSC channel to communicate with the background batching task
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -213,67 +711,56 @@ impl Infer {
             metadata: Metadata {
                 response_tx,
                 span: Span::current(),
-                tokenization: start_time.elapsed(),
+                tokenization,
                 queue_time: Instant::now(),
                 prompt_tokens: encoding.input_ids.len(),
+                priority,
+                deadline,
+                output,
             },
             encoding,
         });
 
-        self.notify_batching_task.notify_one();
+        response_rx
+    }
 
-        let mut response = response_rx
+    /// Wait for the response of an appended encoding
+    async fn response(
+        &self,
+        response_rx: oneshot::Receiver<Result<InferResponse, TextEmbeddingsError>>,
+    ) -> Result<InferResponse, TextEmbeddingsError> {
+        response_rx
             .await
             .expect(
                 "Infer batching task dropped the sender without sending a response. This is a bug.",
             )
             .map_err(|err| {
-                metrics::increment_counter!("te_request_failure", "err" => "inference");
-                tracing::error!("{err}");
-                err
-            })?;
-
-        if !raw_scores {
-            // Softmax
-            if response.results.len() > 1 {
-                let max = *response
-                    .results
-                    .iter()
-                    .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap())
-                    .unwrap();
-
-                let mut den = 0.0;
-                for v in response.results.iter_mut() {
-                    *v = (*v - max).exp();
-                    den += *v;
+                // Expired entries are already counted by the queue
+                if !matches!(err, TextEmbeddingsError::DeadlineExceeded) {
+                    metrics::increment_counter!("te_request_failure", "err" => "inference");
+                    tracing::error!("{err}");
                 }
-                for v in response.results.iter_mut() {
-                    *v /= den;
-                }
-            }
-            // Sigmoid
-            else {
-                response.results[0] = 1.0 / (1.0 + (-response.results[0]).exp());
-            }
-        }
-
-        // Timings
-        let total_time = start_time.elapsed();
+                err
+            })
+    }
 
-        // Metrics
-        metrics::increment_counter!("te_predict_success");
-        metrics::histogram!("te_predict_duration", total_time.as_secs_f64());
-        metrics::histogram!(
-            "te_predict_tokenization_duration",
-            response.tokenization.as_secs_f64()
-        );
-        metrics::histogram!("te_predict_queue_duration", response.queue.as_secs_f64());
-        metrics::histogram!(
-            "te_predict_inference_duration",
-            response.inference.as_secs_f64()
-        );
+    /// Only models returning dense embeddings can be used by `embed` and `embed_chunks`
+    fn check_dense_embedding_model(&self) -> Result<(), TextEmbeddingsError> {
+        let message = if self.is_classifier() || self.is_token_classifier() {
+            "Model is not an embedding model"
+        } else if self.is_splade() {
+            "SPLADE models only return sparse embeddings"
+        } else if self.is_colbert() {
+            "ColBERT models only return multi-vector embeddings"
+        } else {
+            return Ok(());
+        };
 
-        Ok(response)
+        metrics::increment_counter!("te_request_failure", "err" => "model_type");
+        tracing::error!("{message}");
+        Err(TextEmbeddingsError::Backend(BackendError::Inference(
+            message.to_string(),
+        )))
     }
 
     #[instrument(skip(self))]
@@ -281,6 +768,59 @@ impl Infer {
         matches!(self.backend.model_type, ModelType::Classifier)
     }
 
+    #[instrument(skip(self))]
+    pub fn is_token_classifier(&self) -> bool {
+        matches!(self.backend.model_type, ModelType::TokenClassifier)
+    }
+
+    #[instrument(skip(self))]
+    pub fn is_splade(&self) -> bool {
+        matches!(self.backend.model_type, ModelType::Embedding(Pool::Splade))
+    }
+
+    #[instrument(skip(self))]
+    pub fn is_colbert(&self) -> bool {
+        self.colbert.is_some()
+    }
+
+    #[instrument(skip(self))]
+    pub async fn tokenize(
+        &self,
+        inputs: String,
+        add_special_tokens: bool,
+    ) -> Result<tokenizers::Encoding, TextEmbeddingsError> {
+        self.tokenization
+            .tokenize(inputs, add_special_tokens)
+            .await
+            .map_err(|err| {
+                metrics::increment_counter!("te_request_failure", "err" => "tokenization");
+                tracing::error!("{err}");
+                err
+            })
+    }
+
+    /// Vocabulary tokens of `ids`. Unknown ids map to an empty string
+    #[instrument(skip(self))]
+    pub async fn id_to_tokens(&self, ids: Vec<u32>) -> Vec<String> {
+        self.tokenization.id_to_tokens(ids).await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn decode(
+        &self,
+        ids: Vec<u32>,
+        skip_special_tokens: bool,
+    ) -> Result<String, TextEmbeddingsError> {
+        self.tokenization
+            .decode(ids, skip_special_tokens)
+            .await
+            .map_err(|err| {
+                metrics::increment_counter!("te_request_failure", "err" => "tokenization");
+                tracing::error!("{err}");
+                err
+            })
+    }
+
     #[instrument(skip(self))]
     pub async fn health(&self) -> bool {
         self.backend.health().await.is_ok()
@@ -292,6 +832,26 @@ impl Infer {
     }
 }
 
+/// Permits of the inference limit and how many of them are still to be retired
+#[derive(Debug)]
+struct ConcurrencyLimit {
+    limit: usize,
+    retiring: usize,
+}
+
+/// Retire permits one at a time as they are released, until no retirement is outstanding
+async fn retire_permits(semaphore: Arc<Semaphore>, limit: Arc<Mutex<ConcurrencyLimit>>) {
+    while let Ok(permit) = semaphore.clone().acquire_owned().await {
+        let mut limit = limit.lock().expect("Lock was poisoned. This is a bug.");
+        if limit.retiring == 0 {
+            // The retirement was cancelled by a raise of the limit
+            return;
+        }
+        limit.retiring -= 1;
+        permit.forget();
+    }
+}
+
 #[instrument(skip_all)]
 async fn batching_task(
     queue: Queue,
@@ -317,9 +877,13 @@ async fn backend_task(
     mut embed_receiver: mpsc::UnboundedReceiver<(NextBatch, oneshot::Sender<()>)>,
 ) {
     while let Some((batch, _callback)) = embed_receiver.recv().await {
-        let results = match &backend.model_type {
-            ModelType::Classifier => backend.predict(batch.1).await,
-            ModelType::Embedding(_) => backend.embed(batch.1).await,
+        // Batches are never empty and all their entries have the same output
+        let results = match (batch.0[0].output, &backend.model_type) {
+            (Output::HiddenStates { layer }, _) => backend.embed_all(batch.1, layer).await,
+            (Output::ColbertVectors, _) => backend.embed_colbert(batch.1).await,
+            (Output::Model, ModelType::Classifier) => backend.predict(batch.1).await,
+            (Output::Model, ModelType::TokenClassifier) => backend.predict_tokens(batch.1).await,
+            (Output::Model, ModelType::Embedding(_)) => backend.embed(batch.1).await,
         };
 
         // Handle sending responses in another thread to avoid starving the backend
@@ -332,18 +896,54 @@ async fn backend_task(
                         tokenization: m.tokenization,
                         queue: m.queue_time.elapsed() - inference_duration,
                         inference: inference_duration,
+                        cached: false,
                     }));
                 });
             }
             Err(err) => {
                 batch.0.into_iter().for_each(|m| {
-                    let _ = m.response_tx.send(Err(err.clone()));
+                    let _ = m.response_tx.send(Err(err.clone().into()));
                 });
             }
         });
     }
 }
 
+/// Normalize an embedding to a unit L2 norm
+pub(crate) fn normalize_embedding(embedding: &mut [f32]) {
+    let scale = (1.0
+        / embedding
+            .iter()
+            .map(|v| {
+                let v = *v as f64;
+                v * v
+            })
+            .sum::<f64>()
+            .sqrt()) as f32;
+    for v in embedding.iter_mut() {
+        *v *= scale;
+    }
+}
+
+#[derive(Debug)]
+pub struct InferChunksResponse {
+    pub chunks: Vec<Chunk>,
+    pub prompt_tokens: usize,
+    pub tokenization: Duration,
+    pub queue: Duration,
+    pub inference: Duration,
+}
+
+#[derive(Debug)]
+pub struct InferAllResponse {
+    /// Hidden state of each token
+    pub results: Vec<Vec<f32>>,
+    pub prompt_tokens: usize,
+    pub tokenization: Duration,
+    pub queue: Duration,
+    pub inference: Duration,
+}
+
 #[derive(Debug)]
 pub struct InferResponse {
     pub results: Vec<f32>,
@@ -351,4 +951,6 @@ pub struct InferResponse {
     pub tokenization: Duration,
     pub queue: Duration,
     pub inference: Duration,
+    /// The response was served from the cache
+    pub cached: bool,
 }
@@ -1,6 +1,13 @@
+pub mod cache;
+pub mod chunking;
+pub mod classification;
+pub mod colbert;
 pub mod download;
 pub mod infer;
+pub mod quantization;
 pub mod queue;
+pub mod similarity;
+pub mod token_classification;
 pub mod tokenization;
 
 use text_embeddings_backend::BackendError;
@@ -13,8 +20,13 @@ pub enum TextEmbeddingsError {
     Tokenizer(#[from] tokenizers::Error),
     #[error("Input validation error: {0}")]
     Validation(String),
+    /// Well formed inputs the model cannot process, such as out of vocabulary token ids
+    #[error("Input validation error: {0}")]
+    InvalidInput(String),
     #[error("Model is overloaded")]
     Overloaded(#[from] TryAcquireError),
     #[error("Backend error: {0}")]
     Backend(#[from] BackendError),
+    #[error("Request deadline exceeded")]
+    DeadlineExceeded,
 }
@@ -0,0 +1,102 @@
+/// Embedding quantization
+use crate::TextEmbeddingsError;
+use std::fmt;
+
+/// Precision of the returned embeddings.
+///
+/// Quantized values are still returned as `f32` but are always integers: `int8` and `binary`
+/// values fit in an `i8`, `uint8` and `ubinary` values fit in an `u8`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    #[default]
+    Float32,
+    /// Scalar quantization to `[-128, 127]`
+    Int8,
+    /// Scalar quantization to `[0, 255]`
+    Uint8,
+    /// Sign bits packed 8 by 8, shifted to `[-128, 127]`
+    Binary,
+    /// Sign bits packed 8 by 8
+    Ubinary,
+}
+
+impl Precision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Precision::Float32 => "float32",
+            Precision::Int8 => "int8",
+            Precision::Uint8 => "uint8",
+            Precision::Binary => "binary",
+            Precision::Ubinary => "ubinary",
+        }
+    }
+
+    /// The precision stores signed values
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Precision::Int8 | Precision::Binary)
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Per dimension calibration ranges used by the scalar quantization of a model embeddings
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizationRanges {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+/// Check that `precision` can be used with the calibration ranges of a model
+pub(crate) fn validate(
+    precision: Precision,
+    ranges: Option<&QuantizationRanges>,
+) -> Result<(), TextEmbeddingsError> {
+    if matches!(precision, Precision::Int8 | Precision::Uint8) && ranges.is_none() {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`{precision}` precision requires calibration ranges for this model"
+        )));
+    }
+    Ok(())
+}
+
+/// Quantize an embedding. Scalar quantization uses the first `embedding.len()` values of
+/// `ranges` so that it also applies to truncated embeddings
+pub(crate) fn quantize(
+    embedding: &[f32],
+    precision: Precision,
+    ranges: Option<&QuantizationRanges>,
+) -> Vec<f32> {
+    let offset = if precision.is_signed() { 128.0 } else { 0.0 };
+
+    match precision {
+        Precision::Float32 => embedding.to_vec(),
+        Precision::Int8 | Precision::Uint8 => {
+            let ranges = ranges.expect("Calibration ranges were not validated. This is a bug.");
+            embedding
+                .iter()
+                .zip(ranges.min.iter().zip(&ranges.max))
+                .map(|(v, (min, max))| {
+                    let step = (max - min) / 255.0;
+                    let bucket = if step > 0.0 { (v - min) / step } else { 0.0 };
+                    bucket.floor().clamp(0.0, 255.0) - offset
+                })
+                .collect()
+        }
+        Precision::Binary | Precision::Ubinary => embedding
+            .chunks(8)
+            .map(|chunk| {
+                // Most significant bit first, the last chunk is padded with zeros
+                let byte = chunk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, v)| **v > 0.0)
+                    .fold(0u8, |byte, (i, _)| byte | (0x80 >> i));
+                byte as f32 - offset
+            })
+            .collect(),
+    }
+}
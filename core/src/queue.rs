@@ -1,12 +1,77 @@
 use crate::infer::InferResponse;
 use crate::tokenization::Encoding;
+use crate::TextEmbeddingsError;
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{BackendError, Batch};
+use text_embeddings_backend::Batch;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{instrument, Span};
 
+/// Time an entry has to wait in the queue to be promoted to the next priority class.
+/// This guarantees that low priority entries are eventually batched under sustained load
+const PRIORITY_AGING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Request priority class
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            _ => Err(format!(
+                "`{s}` is not a valid priority. Valid values are `low`, `normal` and `high`"
+            )),
+        }
+    }
+}
+
+/// Model output of an entry. A batch only holds entries with the same output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Output of the model type: pooled embeddings or classification logits
+    #[default]
+    Model,
+    /// Unpooled hidden states of every token after `layer` layers
+    HiddenStates { layer: Option<usize> },
+    /// Per-token vectors of a ColBERT model, projected from the last hidden states
+    ColbertVectors,
+}
+
 /// Queue entry
 #[derive(Debug)]
 pub struct Entry {
@@ -20,7 +85,7 @@ pub struct Entry {
 #[derive(Debug)]
 pub struct Metadata {
     /// InferResponse sender to communicate between the Infer struct and the batching_task
-    pub response_tx: oneshot::Sender<Result<InferResponse, BackendError>>,
+    pub response_tx: oneshot::Sender<Result<InferResponse, TextEmbeddingsError>>,
     /// Span that will live as long as entry
     pub span: Span,
     /// Tokenization duration
@@ -29,6 +94,12 @@ pub struct Metadata {
     pub queue_time: Instant,
     /// Number of tokens in the prompt
     pub prompt_tokens: usize,
+    /// Priority class of the request
+    pub priority: Priority,
+    /// Instant after which the client is no longer interested in the response
+    pub deadline: Option<Instant>,
+    /// Model output returned to the client
+    pub output: Output,
 }
 
 /// Request Queue
@@ -72,6 +143,15 @@ impl Queue {
             .expect("Queue background task dropped the receiver. This is a bug.");
     }
 
+    /// Update the maximum number of tokens in a batch.
+    /// Batches already sent to the backend are not affected
+    #[instrument(skip(self))]
+    pub fn set_max_batch_tokens(&self, max_batch_tokens: usize) {
+        self.queue_sender
+            .send(QueueCommand::SetMaxBatchTokens(max_batch_tokens))
+            .expect("Queue background task dropped the receiver. This is a bug.");
+    }
+
     /// Get the next batch from the queue
     #[instrument(skip(self))]
     pub async fn next_batch(&self) -> Option<NextBatch> {
@@ -96,21 +176,26 @@ impl Queue {
 // Background task responsible of the queue state
 fn queue_blocking_task(
     padded_model: bool,
-    max_batch_tokens: usize,
+    mut max_batch_tokens: usize,
     max_batch_requests: Option<usize>,
     max_concurrent_requests: usize,
     mut queue_receiver: mpsc::UnboundedReceiver<QueueCommand>,
 ) {
     let capacity = max_batch_requests.unwrap_or(max_concurrent_requests);
 
-    let mut entries: VecDeque<Entry> = VecDeque::with_capacity(max_concurrent_requests);
+    let mut entries = PriorityEntries::new(max_concurrent_requests);
 
     while let Some(cmd) = queue_receiver.blocking_recv() {
         match cmd {
             QueueCommand::Append(entry, span) => {
                 let _span = span.entered();
+                let priority = entry.metadata.priority;
                 entries.push_back(*entry);
                 metrics::increment_gauge!("te_queue_size", 1.0);
+                metrics::increment_gauge!("te_queue_priority_size", 1.0, "priority" => priority.as_str());
+            }
+            QueueCommand::SetMaxBatchTokens(value) => {
+                max_batch_tokens = value;
             }
             QueueCommand::NextBatch {
                 response_sender,
@@ -129,7 +214,8 @@ fn queue_blocking_task(
                 let mut current_tokens = 0;
                 let mut max_length = 0;
 
-                while let Some(entry) = entries.pop_front() {
+                let now = Instant::now();
+                while let Some(entry) = entries.pop_next(now) {
                     // Filter entries where the response receiver was dropped (== entries where the request
                     // was dropped by the client)
                     if entry.metadata.response_tx.is_closed() {
@@ -137,6 +223,30 @@ fn queue_blocking_task(
                         continue;
                     }
 
+                    // Filter entries that expired while waiting in the queue
+                    if entry
+                        .metadata
+                        .deadline
+                        .is_some_and(|deadline| deadline <= now)
+                    {
+                        metrics::increment_counter!("te_request_failure", "err" => "deadline");
+                        tracing::error!("Request deadline exceeded while queued");
+                        let _ = entry
+                            .metadata
+                            .response_tx
+                            .send(Err(TextEmbeddingsError::DeadlineExceeded));
+                        continue;
+                    }
+
+                    // Entries with a different output go to another batch
+                    if metadata
+                        .first()
+                        .is_some_and(|first: &Metadata| first.output != entry.metadata.output)
+                    {
+                        entries.push_front(entry);
+                        break;
+                    }
+
                     let entry_tokens = entry.encoding.input_ids.len();
 
                     let total_tokens = if padded_model {
@@ -153,6 +263,12 @@ fn queue_blocking_task(
 
                     max_length = max(max_length, entry_tokens as u32);
 
+                    metrics::histogram!(
+                        "te_queue_priority_duration",
+                        entry.metadata.queue_time.elapsed().as_secs_f64(),
+                        "priority" => entry.metadata.priority.as_str()
+                    );
+
                     input_ids.extend(entry.encoding.input_ids);
                     token_type_ids.extend(entry.encoding.token_type_ids);
                     position_ids.extend(entry.encoding.position_ids);
@@ -187,18 +303,193 @@ fn queue_blocking_task(
                 metrics::histogram!("te_batch_next_size", batch_size as f64);
                 metrics::histogram!("te_batch_next_tokens", current_tokens as f64);
                 metrics::gauge!("te_queue_size", entries.len() as f64);
+                for priority in Priority::ALL {
+                    metrics::gauge!(
+                        "te_queue_priority_size",
+                        entries.queues[priority.index()].len() as f64,
+                        "priority" => priority.as_str()
+                    );
+                }
             }
         }
     }
 }
 
+/// One FIFO queue per priority class
+struct PriorityEntries {
+    queues: [VecDeque<Entry>; 3],
+}
+
+impl PriorityEntries {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queues: [
+                VecDeque::new(),
+                VecDeque::with_capacity(capacity),
+                VecDeque::new(),
+            ],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn push_back(&mut self, entry: Entry) {
+        self.queues[entry.metadata.priority.index()].push_back(entry);
+    }
+
+    fn push_front(&mut self, entry: Entry) {
+        self.queues[entry.metadata.priority.index()].push_front(entry);
+    }
+
+    /// Pop the entry with the highest effective priority.
+    ///
+    /// The effective priority of an entry is its priority class raised by one class for every
+    /// `PRIORITY_AGING_INTERVAL` spent in the queue. Ties are broken by queue time so the oldest
+    /// entry goes first. Only the head of each queue needs to be considered as entries of a same
+    /// class are ordered by queue time.
+    fn pop_next(&mut self, now: Instant) -> Option<Entry> {
+        let (index, _) = self
+            .queues
+            .iter()
+            .enumerate()
+            .filter_map(|(index, queue)| queue.front().map(|entry| (index, entry)))
+            .max_by(|(_, a), (_, b)| {
+                effective_priority(a, now)
+                    .cmp(&effective_priority(b, now))
+                    // Older entries have a smaller queue time
+                    .then_with(|| b.metadata.queue_time.cmp(&a.metadata.queue_time))
+            })?;
+        self.queues[index].pop_front()
+    }
+}
+
+fn effective_priority(entry: &Entry, now: Instant) -> usize {
+    let waited = now.saturating_duration_since(entry.metadata.queue_time);
+    let promotions = (waited.as_millis() / PRIORITY_AGING_INTERVAL.as_millis()) as usize;
+    (entry.metadata.priority.index() + promotions).min(Priority::High.index())
+}
+
 pub type NextBatch = (Vec<Metadata>, Batch);
 
 #[derive(Debug)]
 enum QueueCommand {
     Append(Box<Entry>, Span),
+    SetMaxBatchTokens(usize),
     NextBatch {
         response_sender: oneshot::Sender<Option<NextBatch>>,
         span: Span,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    fn entry(
+        priority: Priority,
+        queue_time: Instant,
+        deadline: Option<Instant>,
+    ) -> (
+        Entry,
+        oneshot::Receiver<Result<InferResponse, TextEmbeddingsError>>,
+    ) {
+        let (response_tx, response_rx) = oneshot::channel();
+        let entry = Entry {
+            encoding: Encoding {
+                input_ids: vec![0; 4],
+                token_type_ids: vec![0; 4],
+                position_ids: (0..4).collect(),
+            },
+            metadata: Metadata {
+                response_tx,
+                span: Span::none(),
+                tokenization: Duration::default(),
+                queue_time,
+                prompt_tokens: 4,
+                priority,
+                deadline,
+                output: Output::Model,
+            },
+        };
+        (entry, response_rx)
+    }
+
+    #[test]
+    fn low_priority_entries_are_not_starved() {
+        let start = Instant::now();
+        let step = PRIORITY_AGING_INTERVAL / 10;
+        let mut entries = PriorityEntries::new(16);
+        let mut receivers = Vec::new();
+
+        let (low, rx) = entry(Priority::Low, start, None);
+        entries.push_back(low);
+        receivers.push(rx);
+
+        // A new high priority entry arrives before every pop
+        let mut popped = Vec::new();
+        for i in 1..=30 {
+            let now = start + step * i;
+            let (high, rx) = entry(Priority::High, now, None);
+            entries.push_back(high);
+            receivers.push(rx);
+
+            let next = entries.pop_next(now).unwrap();
+            popped.push(next.metadata.priority);
+            if next.metadata.priority == Priority::Low {
+                break;
+            }
+        }
+
+        // High priority entries go first until the low priority entry is promoted twice
+        assert_eq!(popped.last(), Some(&Priority::Low));
+        assert_eq!(popped.len(), 20);
+        assert!(popped[..19].iter().all(|p| *p == Priority::High));
+    }
+
+    #[test]
+    fn expired_entries_are_skipped() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        recorder.install().unwrap();
+
+        let queue = Queue::new(false, 16, None, 4);
+        let (expired, mut expired_rx) = entry(Priority::High, Instant::now(), Some(Instant::now()));
+        let (valid, _valid_rx) = entry(Priority::Normal, Instant::now(), None);
+        queue.append(expired);
+        queue.append(valid);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let (metadata, batch) = runtime.block_on(queue.next_batch()).unwrap();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].priority, Priority::Normal);
+        assert_eq!(batch.input_ids.len(), 4);
+
+        assert!(matches!(
+            expired_rx.try_recv(),
+            Ok(Err(TextEmbeddingsError::DeadlineExceeded))
+        ));
+
+        let deadline_failures =
+            snapshotter
+                .snapshot()
+                .into_vec()
+                .into_iter()
+                .find_map(|(key, _, _, value)| {
+                    let key = key.key();
+                    let deadline = key.name() == "te_request_failure"
+                        && key
+                            .labels()
+                            .any(|label| label.key() == "err" && label.value() == "deadline");
+                    match value {
+                        DebugValue::Counter(count) if deadline => Some(count),
+                        _ => None,
+                    }
+                });
+        assert_eq!(deadline_failures, Some(1));
+    }
+}
@@ -0,0 +1,55 @@
+/// Sentence similarity
+use std::collections::HashMap;
+
+/// Score of a candidate embedding against the source embedding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimilarityMetric {
+    Cosine,
+    Dot,
+}
+
+/// Unique values of `inputs` and, for each input, the index of its unique value
+pub fn deduplicate(inputs: Vec<String>) -> (Vec<String>, Vec<usize>) {
+    let mut unique = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    let indices = inputs
+        .into_iter()
+        .map(|input| {
+            *positions.entry(input).or_insert_with_key(|input| {
+                unique.push(input.clone());
+                unique.len() - 1
+            })
+        })
+        .collect();
+
+    (unique, indices)
+}
+
+/// Similarity of two embeddings
+pub fn score(source: &[f32], candidate: &[f32], metric: SimilarityMetric) -> f32 {
+    let dot: f32 = source.iter().zip(candidate).map(|(s, c)| s * c).sum();
+    match metric {
+        SimilarityMetric::Dot => dot,
+        SimilarityMetric::Cosine => {
+            let source_norm = source.iter().map(|v| v * v).sum::<f32>().sqrt();
+            let candidate_norm = candidate.iter().map(|v| v * v).sum::<f32>().sqrt();
+            dot / (source_norm * candidate_norm).max(f32::EPSILON)
+        }
+    }
+}
+
+/// Late interaction (MaxSim) score: sum over the query vectors of their best dot product with a
+/// document vector
+pub fn max_sim(query: &[Vec<f32>], document: &[Vec<f32>]) -> f32 {
+    query
+        .iter()
+        .map(|q| {
+            document
+                .iter()
+                .map(|d| score(q, d, SimilarityMetric::Dot))
+                .fold(f32::NEG_INFINITY, f32::max)
+        })
+        .filter(|s| s.is_finite())
+        .sum()
+}
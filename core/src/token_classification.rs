@@ -0,0 +1,248 @@
+/// Token classification post-processing
+use crate::classification::{self, FunctionToApply};
+use std::collections::HashMap;
+use tokenizers::Encoding;
+
+/// How the tokens are grouped in entities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationStrategy {
+    /// Group adjacent tokens with the same entity
+    Simple,
+    /// Label each word with its first token, then group like `Simple`
+    First,
+    /// Label each word with its highest scoring token, then group like `Simple`
+    Max,
+}
+
+/// Span of `text` classified as a single entity. `start` and `end` are character offsets
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub label: String,
+    pub score: f32,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Label of a token or of a word
+#[derive(Debug, Clone, Copy)]
+struct Prediction {
+    label: usize,
+    score: f32,
+    start: usize,
+    end: usize,
+}
+
+/// Group the tokens of `text` in entities. `encoding` is the untruncated encoding of `text` with
+/// its special tokens and `logits` holds `n_labels` logits for each of its tokens.
+/// Entities labelled `O` are dropped
+pub fn aggregate(
+    text: &str,
+    encoding: &Encoding,
+    logits: &[f32],
+    id2label: &HashMap<String, String>,
+    strategy: AggregationStrategy,
+) -> Vec<Entity> {
+    let n_labels = id2label.len();
+    let special_tokens_mask = encoding.get_special_tokens_mask();
+    let offsets = encoding.get_offsets();
+    let word_ids = encoding.get_word_ids();
+
+    // Label of each token
+    let mut tokens = Vec::with_capacity(encoding.len());
+    for (i, logits) in logits.chunks(n_labels).enumerate().take(encoding.len()) {
+        if special_tokens_mask[i] == 1 {
+            continue;
+        }
+
+        let mut scores = logits.to_vec();
+        classification::apply(FunctionToApply::Softmax, &mut scores);
+        let (label, score) = scores
+            .into_iter()
+            .enumerate()
+            .max_by(|(_, x), (_, y)| x.partial_cmp(y).unwrap())
+            .unwrap();
+
+        tokens.push((
+            word_ids[i],
+            Prediction {
+                label,
+                score,
+                start: offsets[i].0,
+                end: offsets[i].1,
+            },
+        ));
+    }
+
+    // Label of each word
+    let predictions: Vec<Prediction> = match strategy {
+        AggregationStrategy::Simple => tokens.into_iter().map(|(_, token)| token).collect(),
+        AggregationStrategy::First | AggregationStrategy::Max => {
+            let mut words: Vec<(Option<u32>, Prediction)> = Vec::new();
+            for (word_id, token) in tokens {
+                match words.last_mut() {
+                    Some((last_word_id, word)) if word_id.is_some() && word_id == *last_word_id => {
+                        if strategy == AggregationStrategy::Max && token.score > word.score {
+                            word.label = token.label;
+                            word.score = token.score;
+                        }
+                        word.end = token.end;
+                    }
+                    _ => words.push((word_id, token)),
+                }
+            }
+            words.into_iter().map(|(_, word)| word).collect()
+        }
+    };
+
+    // Group adjacent predictions with the same entity. A `B-` label starts a new entity
+    let mut groups: Vec<(&str, Vec<Prediction>)> = Vec::new();
+    for prediction in predictions {
+        let label = id2label
+            .get(&prediction.label.to_string())
+            .map(String::as_str)
+            .unwrap_or_default();
+        let (begin, entity) = match (label.strip_prefix("B-"), label.strip_prefix("I-")) {
+            (Some(entity), _) => (true, entity),
+            (_, Some(entity)) => (false, entity),
+            _ => (false, label),
+        };
+
+        match groups.last_mut() {
+            Some((last_entity, group)) if !begin && *last_entity == entity => {
+                group.push(prediction)
+            }
+            _ => groups.push((entity, vec![prediction])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(entity, _)| *entity != "O")
+        .map(|(entity, group)| {
+            let start = group[0].start;
+            let end = group[group.len() - 1].end;
+            let score = group.iter().map(|p| p.score).sum::<f32>() / group.len() as f32;
+            Entity {
+                label: entity.to_string(),
+                score,
+                start,
+                end,
+                text: text.chars().skip(start).take(end - start).collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokenizers::Tokenizer;
+
+    /// Lowercasing word piece tokenizer adding `[CLS]` and `[SEP]`
+    fn encode(text: &str) -> Encoding {
+        let tokenizer = Tokenizer::from_str(
+            r###"{
+                "version": "1.0",
+                "truncation": null,
+                "padding": null,
+                "added_tokens": [],
+                "normalizer": {"type": "Lowercase"},
+                "pre_tokenizer": {"type": "Whitespace"},
+                "post_processor": {"type": "BertProcessing", "sep": ["[SEP]", 1], "cls": ["[CLS]", 0]},
+                "decoder": null,
+                "model": {
+                    "type": "WordPiece",
+                    "unk_token": "[UNK]",
+                    "continuing_subword_prefix": "##",
+                    "max_input_chars_per_word": 100,
+                    "vocab": {
+                        "[CLS]": 0, "[SEP]": 1, "[UNK]": 2, "hug": 3, "##ging": 4, "face": 5, "in": 6,
+                        "paris": 7, "london": 8
+                    }
+                }
+            }"###,
+        )
+        .unwrap();
+        tokenizer.encode_char_offsets(text, true).unwrap()
+    }
+
+    fn id2label() -> HashMap<String, String> {
+        ["O", "B-ORG", "I-ORG", "B-LOC"]
+            .iter()
+            .enumerate()
+            .map(|(id, label)| (id.to_string(), label.to_string()))
+            .collect()
+    }
+
+    /// Logits predicting `labels` for each token, `high` ones with a higher score
+    fn logits(labels: &[(usize, bool)]) -> Vec<f32> {
+        labels
+            .iter()
+            .flat_map(|&(label, high)| {
+                let mut logits = vec![0.0; 4];
+                logits[label] = if high { 8.0 } else { 5.0 };
+                logits
+            })
+            .collect()
+    }
+
+    fn spans(entities: &[Entity]) -> Vec<(&str, &str)> {
+        entities
+            .iter()
+            .map(|e| (e.label.as_str(), e.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn strategies_label_words() {
+        let text = "Hugging Face in Paris";
+        let encoding = encode(text);
+        assert_eq!(encoding.get_ids(), [0, 3, 4, 5, 6, 7, 1]);
+
+        // `##ging` is confidently outside of the entity started by `hug`
+        let logits = logits(&[
+            (0, false),
+            (1, false),
+            (0, true),
+            (2, false),
+            (0, false),
+            (3, false),
+            (0, false),
+        ]);
+        let aggregate = |strategy| aggregate(text, &encoding, &logits, &id2label(), strategy);
+
+        let simple = aggregate(AggregationStrategy::Simple);
+        assert_eq!(
+            spans(&simple),
+            [("ORG", "Hug"), ("ORG", "Face"), ("LOC", "Paris")]
+        );
+        assert_eq!(simple[0].start, 0);
+        assert_eq!(simple[0].end, 3);
+
+        let first = aggregate(AggregationStrategy::First);
+        assert_eq!(spans(&first), [("ORG", "Hugging Face"), ("LOC", "Paris")]);
+        assert_eq!((first[0].start, first[0].end), (0, 12));
+
+        let max = aggregate(AggregationStrategy::Max);
+        assert_eq!(spans(&max), [("ORG", "Face"), ("LOC", "Paris")]);
+    }
+
+    #[test]
+    fn begin_labels_start_new_entities() {
+        let text = "Paris London";
+        let encoding = encode(text);
+        let logits = logits(&[(0, false), (3, false), (3, false), (0, false)]);
+
+        let entities = aggregate(
+            text,
+            &encoding,
+            &logits,
+            &id2label(),
+            AggregationStrategy::Simple,
+        );
+        assert_eq!(spans(&entities), [("LOC", "Paris"), ("LOC", "London")]);
+        assert!(entities.iter().all(|e| e.score > 0.9 && e.score <= 1.0));
+    }
+}
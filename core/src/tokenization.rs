@@ -1,7 +1,7 @@
 /// Payload tokenization logic
 use crate::TextEmbeddingsError;
 use tokenizers::tokenizer::Tokenizer;
-use tokenizers::{EncodeInput, TruncationDirection, TruncationParams, TruncationStrategy};
+use tokenizers::{EncodeInput, PostProcessor, TruncationParams};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{instrument, Span};
 
@@ -10,12 +10,17 @@ use tracing::{instrument, Span};
 pub struct Tokenization {
     /// Channel to communicate with the background tokenization task
     sender: mpsc::UnboundedSender<TokenizerRequest>,
+    /// Size of the embedding table of the model. Used to validate pre-tokenized inputs
+    vocab_size: usize,
+    max_input_length: usize,
+    position_offset: usize,
 }
 
 impl Tokenization {
     pub fn new(
         workers: usize,
         tokenizer: Tokenizer,
+        vocab_size: usize,
         max_input_length: usize,
         position_offset: usize,
     ) -> Self {
@@ -55,14 +60,19 @@ impl Tokenization {
             }
         });
 
-        Self { sender }
+        Self {
+            sender,
+            vocab_size,
+            max_input_length,
+            position_offset,
+        }
     }
 
     #[instrument(skip_all)]
     pub async fn encode(
         &self,
         inputs: EncodingInput,
-        truncate: bool,
+        truncation: Truncation,
     ) -> Result<Encoding, TextEmbeddingsError> {
         // Check if inputs is empty
         if inputs.is_empty() {
@@ -71,12 +81,173 @@ impl Tokenization {
             ));
         }
 
+        // Pre-tokenized inputs do not need the tokenizer workers
+        if let EncodingInput::Ids(ids) = inputs {
+            return encode_ids(
+                ids,
+                truncation,
+                self.vocab_size,
+                self.max_input_length,
+                self.position_offset,
+            );
+        }
+
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::Encode(
+                inputs,
+                truncation,
+                response_sender,
+                Span::current(),
+            ))
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
+    /// Split `inputs` in at most `max_chunks` windows of at most `chunk_size` tokens overlapping
+    /// by `stride` tokens
+    #[instrument(skip_all)]
+    pub async fn encode_chunks(
+        &self,
+        inputs: String,
+        chunk_size: usize,
+        stride: usize,
+        max_chunks: usize,
+    ) -> Result<Vec<ChunkEncoding>, TextEmbeddingsError> {
+        // Check if inputs is empty
+        if inputs.is_empty() {
+            return Err(TextEmbeddingsError::Validation(
+                "`inputs` cannot be empty".to_string(),
+            ));
+        }
+
         // Create response channel
         let (response_sender, response_receiver) = oneshot::channel();
         // Send request to the background validation task
         // Unwrap is safe here
         self.sender
-            .send((inputs, truncate, response_sender, Span::current()))
+            .send(TokenizerRequest::EncodeChunks(
+                inputs,
+                chunk_size,
+                stride,
+                max_chunks,
+                response_sender,
+                Span::current(),
+            ))
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
+    /// Encode `inputs` without truncation, along with the tokenizer encoding holding the offsets
+    /// of its tokens in characters
+    #[instrument(skip_all)]
+    pub async fn encode_with_offsets(
+        &self,
+        inputs: String,
+    ) -> Result<(Encoding, tokenizers::Encoding), TextEmbeddingsError> {
+        // Check if inputs is empty
+        if inputs.is_empty() {
+            return Err(TextEmbeddingsError::Validation(
+                "`inputs` cannot be empty".to_string(),
+            ));
+        }
+
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::EncodeWithOffsets(
+                inputs,
+                response_sender,
+                Span::current(),
+            ))
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
+    /// Tokenize `inputs` without truncation. Offsets are expressed in characters
+    #[instrument(skip_all)]
+    pub async fn tokenize(
+        &self,
+        inputs: String,
+        add_special_tokens: bool,
+    ) -> Result<tokenizers::Encoding, TextEmbeddingsError> {
+        // Check if inputs is empty
+        if inputs.is_empty() {
+            return Err(TextEmbeddingsError::Validation(
+                "`inputs` cannot be empty".to_string(),
+            ));
+        }
+
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::Tokenize(
+                inputs,
+                add_special_tokens,
+                response_sender,
+                Span::current(),
+            ))
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
+    /// Vocabulary tokens of `ids`
+    #[instrument(skip_all)]
+    pub async fn id_to_tokens(&self, ids: Vec<u32>) -> Vec<String> {
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::IdToTokens(
+                ids,
+                response_sender,
+                Span::current(),
+            ))
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
+    /// Decode token ids back to text
+    #[instrument(skip_all)]
+    pub async fn decode(
+        &self,
+        ids: Vec<u32>,
+        skip_special_tokens: bool,
+    ) -> Result<String, TextEmbeddingsError> {
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::Decode(
+                ids,
+                skip_special_tokens,
+                response_sender,
+                Span::current(),
+            ))
             .expect("Tokenization background task dropped the receiver. This is a bug.");
 
         // Await on response channel
@@ -93,42 +264,178 @@ fn tokenizer_worker(
     mut receiver: mpsc::UnboundedReceiver<TokenizerRequest>,
 ) {
     // Loop over requests
-    while let Some((inputs, truncate, response_tx, parent_span)) = receiver.blocking_recv() {
-        parent_span.in_scope(|| {
-            if !response_tx.is_closed() {
-                // It's possible that the user dropped its request resulting in a send error.
-                // We just discard the error
-                let _ = response_tx.send(encode_input(
-                    inputs,
-                    truncate,
-                    max_input_length,
-                    position_offset,
-                    &mut tokenizer,
-                ));
+    while let Some(request) = receiver.blocking_recv() {
+        match request {
+            TokenizerRequest::Encode(inputs, truncation, response_tx, parent_span) => {
+                parent_span.in_scope(|| {
+                    if !response_tx.is_closed() {
+                        // It's possible that the user dropped its request resulting in a send error.
+                        // We just discard the error
+                        let _ = response_tx.send(encode_input(
+                            inputs,
+                            truncation,
+                            max_input_length,
+                            position_offset,
+                            &mut tokenizer,
+                        ));
+                    }
+                })
             }
-        })
+            TokenizerRequest::EncodeChunks(
+                inputs,
+                chunk_size,
+                stride,
+                max_chunks,
+                response_tx,
+                parent_span,
+            ) => parent_span.in_scope(|| {
+                if !response_tx.is_closed() {
+                    let _ = response_tx.send(encode_chunks(
+                        inputs,
+                        chunk_size,
+                        stride,
+                        max_chunks,
+                        max_input_length,
+                        position_offset,
+                        &mut tokenizer,
+                    ));
+                }
+            }),
+            TokenizerRequest::EncodeWithOffsets(inputs, response_tx, parent_span) => parent_span
+                .in_scope(|| {
+                    if !response_tx.is_closed() {
+                        let _ = response_tx.send(encode_with_offsets(
+                            inputs,
+                            max_input_length,
+                            position_offset,
+                            &mut tokenizer,
+                        ));
+                    }
+                }),
+            TokenizerRequest::Tokenize(inputs, add_special_tokens, response_tx, parent_span) => {
+                parent_span.in_scope(|| {
+                    if !response_tx.is_closed() {
+                        let _ = response_tx.send(tokenize_input(
+                            inputs,
+                            add_special_tokens,
+                            &mut tokenizer,
+                        ));
+                    }
+                })
+            }
+            TokenizerRequest::IdToTokens(ids, response_tx, parent_span) => {
+                parent_span.in_scope(|| {
+                    if !response_tx.is_closed() {
+                        let _ = response_tx.send(
+                            ids.into_iter()
+                                .map(|id| tokenizer.id_to_token(id).unwrap_or_default())
+                                .collect(),
+                        );
+                    }
+                })
+            }
+            TokenizerRequest::Decode(ids, skip_special_tokens, response_tx, parent_span) => {
+                parent_span.in_scope(|| {
+                    if !response_tx.is_closed() {
+                        let _ = response_tx.send(
+                            tokenizer
+                                .decode(&ids, skip_special_tokens)
+                                .map_err(TextEmbeddingsError::from),
+                        );
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Tokenize an input without truncation
+fn tokenize_input(
+    inputs: String,
+    add_special_tokens: bool,
+    tokenizer: &mut Tokenizer,
+) -> Result<tokenizers::Encoding, TextEmbeddingsError> {
+    Ok(tokenizer
+        .with_truncation(None)?
+        .encode_char_offsets(inputs, add_special_tokens)?)
+}
+
+/// Encode `inputs` without truncation and keep the tokenizer encoding for its offsets
+fn encode_with_offsets(
+    inputs: String,
+    max_input_length: usize,
+    position_offset: usize,
+    tokenizer: &mut Tokenizer,
+) -> Result<(Encoding, tokenizers::Encoding), TextEmbeddingsError> {
+    let encoding = tokenize_input(inputs, true, tokenizer)?;
+    let seq_len = encoding.len();
+
+    if seq_len > max_input_length {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`inputs` must have less than {max_input_length} tokens. Given: {seq_len}"
+        )));
+    }
+
+    metrics::histogram!("te_request_input_length", seq_len as f64);
+
+    Ok((
+        Encoding {
+            input_ids: encoding.get_ids().to_vec(),
+            token_type_ids: encoding.get_type_ids().to_vec(),
+            position_ids: (position_offset as u32..(seq_len + position_offset) as u32)
+                .collect::<Vec<_>>(),
+        },
+        encoding,
+    ))
+}
+
+/// Token budget of a request
+fn max_length(
+    truncation: Truncation,
+    max_input_length: usize,
+) -> Result<usize, TextEmbeddingsError> {
+    match truncation.max_tokens {
+        Some(max_tokens) if max_tokens == 0 || max_tokens > max_input_length => {
+            Err(TextEmbeddingsError::Validation(format!(
+                "`max_tokens` must be > 0 and <= {max_input_length}. Given: {max_tokens}"
+            )))
+        }
+        Some(max_tokens) => Ok(max_tokens),
+        None => Ok(max_input_length),
     }
 }
 
 /// Get input length and optionally truncate it
 fn encode_input(
     inputs: EncodingInput,
-    truncate: bool,
+    truncation: Truncation,
     max_input_length: usize,
     position_offset: usize,
     tokenizer: &mut Tokenizer,
 ) -> Result<Encoding, TextEmbeddingsError> {
-    // Default truncation params
-    let truncate_params = truncate.then_some(TruncationParams {
-        direction: TruncationDirection::Right,
-        max_length: max_input_length,
-        strategy: TruncationStrategy::LongestFirst,
+    let max_length = max_length(truncation, max_input_length)?;
+
+    if truncation.strategy == TruncationStrategy::OnlySecond
+        && matches!(inputs, EncodingInput::Single(_))
+    {
+        return Err(TextEmbeddingsError::Validation(
+            "`only_second` truncation strategy requires a pair of inputs".to_string(),
+        ));
+    }
+
+    let truncate_params = truncation.truncate.then_some(TruncationParams {
+        direction: truncation.direction.into(),
+        max_length,
+        strategy: truncation.strategy.into(),
         stride: 0,
     });
 
     let inputs: EncodeInput = match inputs {
         EncodingInput::Single(s) => s.into(),
         EncodingInput::Dual(s1, s2) => (s1, s2).into(),
+        EncodingInput::Ids(_) => {
+            unreachable!("Pre-tokenized inputs are encoded by `Tokenization::encode`")
+        }
     };
 
     let encoding = tokenizer
@@ -136,9 +443,9 @@ fn encode_input(
         .encode(inputs, true)?;
     let seq_len = encoding.len();
 
-    if seq_len > max_input_length {
+    if seq_len > max_length {
         return Err(TextEmbeddingsError::Validation(format!(
-            "`inputs` must have less than {max_input_length} tokens. Given: {seq_len}"
+            "`inputs` must have less than {max_length} tokens. Given: {seq_len}"
         )));
     }
 
@@ -152,6 +459,140 @@ fn encode_input(
     })
 }
 
+/// Validate pre-tokenized inputs and optionally truncate them. Ids are used as-is: they must
+/// contain the special tokens of the model
+fn encode_ids(
+    mut ids: Vec<u32>,
+    truncation: Truncation,
+    vocab_size: usize,
+    max_input_length: usize,
+    position_offset: usize,
+) -> Result<Encoding, TextEmbeddingsError> {
+    let max_length = max_length(truncation, max_input_length)?;
+
+    if truncation.strategy == TruncationStrategy::OnlySecond {
+        return Err(TextEmbeddingsError::Validation(
+            "`only_second` truncation strategy requires a pair of inputs".to_string(),
+        ));
+    }
+
+    if let Some(id) = ids.iter().find(|&&id| id as usize >= vocab_size) {
+        return Err(TextEmbeddingsError::InvalidInput(format!(
+            "`inputs` token ids must be < {vocab_size}. Given: {id}"
+        )));
+    }
+
+    if ids.len() > max_length && truncation.truncate {
+        match truncation.direction {
+            TruncationDirection::Right => ids.truncate(max_length),
+            TruncationDirection::Left => {
+                ids.drain(..ids.len() - max_length);
+            }
+        }
+    }
+    let seq_len = ids.len();
+
+    if seq_len > max_length {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`inputs` must have less than {max_length} tokens. Given: {seq_len}"
+        )));
+    }
+
+    metrics::histogram!("te_request_input_length", seq_len as f64);
+
+    Ok(Encoding {
+        input_ids: ids,
+        token_type_ids: vec![0; seq_len],
+        position_ids: (position_offset as u32..(seq_len + position_offset) as u32)
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Split an input in overlapping windows. Every window has its own special tokens
+fn encode_chunks(
+    inputs: String,
+    chunk_size: usize,
+    stride: usize,
+    max_chunks: usize,
+    max_input_length: usize,
+    position_offset: usize,
+    tokenizer: &mut Tokenizer,
+) -> Result<Vec<ChunkEncoding>, TextEmbeddingsError> {
+    if chunk_size == 0 || chunk_size > max_input_length {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`chunk_size` must be > 0 and <= {max_input_length}. Given: {chunk_size}"
+        )));
+    }
+    // Special tokens are part of each window
+    let added = tokenizer
+        .get_post_processor()
+        .map(|post_processor| post_processor.added_tokens(false))
+        .unwrap_or(0);
+    if chunk_size <= added {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`chunk_size` must be > {added} to leave room for the special tokens. Given: {chunk_size}"
+        )));
+    }
+    if stride >= chunk_size - added {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`stride` must be < {}. Given: {stride}",
+            chunk_size - added
+        )));
+    }
+
+    // The remaining windows are returned as overflowing encodings
+    let truncate_params = TruncationParams {
+        direction: tokenizers::TruncationDirection::Right,
+        max_length: chunk_size,
+        strategy: tokenizers::TruncationStrategy::LongestFirst,
+        stride,
+    };
+
+    let mut encoding = tokenizer
+        .with_truncation(Some(truncate_params))?
+        .encode_char_offsets(inputs, true)?;
+    let overflowing = encoding.take_overflowing();
+    // Every window is a queue entry
+    let n_chunks = overflowing.len() + 1;
+    if n_chunks > max_chunks {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`inputs` is split in {n_chunks} chunks, more than the maximum of {max_chunks}"
+        )));
+    }
+
+    let chunks = std::iter::once(encoding)
+        .chain(overflowing)
+        .map(|encoding| {
+            let seq_len = encoding.len();
+            metrics::histogram!("te_request_input_length", seq_len as f64);
+
+            // Character span of the non special tokens
+            let offsets: Vec<(usize, usize)> = encoding
+                .get_offsets()
+                .iter()
+                .zip(encoding.get_special_tokens_mask())
+                .filter(|(_, special)| **special == 0)
+                .map(|(offsets, _)| *offsets)
+                .collect();
+            let start = offsets.iter().map(|o| o.0).min().unwrap_or(0);
+            let end = offsets.iter().map(|o| o.1).max().unwrap_or(0);
+
+            ChunkEncoding {
+                encoding: Encoding {
+                    input_ids: encoding.get_ids().to_vec(),
+                    token_type_ids: encoding.get_type_ids().to_vec(),
+                    position_ids: (position_offset as u32..(seq_len + position_offset) as u32)
+                        .collect::<Vec<_>>(),
+                },
+                start,
+                end,
+            }
+        })
+        .collect();
+
+    Ok(chunks)
+}
+
 #[derive(Debug)]
 pub struct Encoding {
     pub input_ids: Vec<u32>,
@@ -159,10 +600,68 @@ pub struct Encoding {
     pub position_ids: Vec<u32>,
 }
 
+/// Window of a long input
 #[derive(Debug)]
+pub struct ChunkEncoding {
+    pub encoding: Encoding,
+    /// Character span of the window in the input
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Side of the inputs that is removed when truncating
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum TruncationDirection {
+    Left,
+    #[default]
+    Right,
+}
+
+impl From<TruncationDirection> for tokenizers::TruncationDirection {
+    fn from(value: TruncationDirection) -> Self {
+        match value {
+            TruncationDirection::Left => Self::Left,
+            TruncationDirection::Right => Self::Right,
+        }
+    }
+}
+
+/// Sequence of a pair of inputs that is truncated
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    #[default]
+    LongestFirst,
+    OnlyFirst,
+    OnlySecond,
+}
+
+impl From<TruncationStrategy> for tokenizers::TruncationStrategy {
+    fn from(value: TruncationStrategy) -> Self {
+        match value {
+            TruncationStrategy::LongestFirst => Self::LongestFirst,
+            TruncationStrategy::OnlyFirst => Self::OnlyFirst,
+            TruncationStrategy::OnlySecond => Self::OnlySecond,
+        }
+    }
+}
+
+/// Per request truncation parameters
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct Truncation {
+    /// Truncate the inputs instead of returning an error when they are too long
+    pub truncate: bool,
+    pub direction: TruncationDirection,
+    pub strategy: TruncationStrategy,
+    /// Token budget of the request. Defaults to the model max input length
+    pub max_tokens: Option<usize>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum EncodingInput {
     Single(String),
     Dual(String, String),
+    /// Pre-tokenized input
+    Ids(Vec<u32>),
 }
 
 impl EncodingInput {
@@ -170,6 +669,7 @@ impl EncodingInput {
         match self {
             EncodingInput::Single(s) => s.is_empty(),
             EncodingInput::Dual(s1, s2) => s1.is_empty() && s2.is_empty(),
+            EncodingInput::Ids(ids) => ids.is_empty(),
         }
     }
 }
@@ -186,9 +686,101 @@ impl From<(String, String)> for EncodingInput {
     }
 }
 
-type TokenizerRequest = (
-    EncodingInput,
-    bool,
-    oneshot::Sender<Result<Encoding, TextEmbeddingsError>>,
-    Span,
-);
+impl From<Vec<u32>> for EncodingInput {
+    fn from(value: Vec<u32>) -> Self {
+        Self::Ids(value)
+    }
+}
+
+enum TokenizerRequest {
+    Encode(
+        EncodingInput,
+        Truncation,
+        oneshot::Sender<Result<Encoding, TextEmbeddingsError>>,
+        Span,
+    ),
+    EncodeChunks(
+        String,
+        usize,
+        usize,
+        usize,
+        oneshot::Sender<Result<Vec<ChunkEncoding>, TextEmbeddingsError>>,
+        Span,
+    ),
+    EncodeWithOffsets(
+        String,
+        oneshot::Sender<Result<(Encoding, tokenizers::Encoding), TextEmbeddingsError>>,
+        Span,
+    ),
+    Tokenize(
+        String,
+        bool,
+        oneshot::Sender<Result<tokenizers::Encoding, TextEmbeddingsError>>,
+        Span,
+    ),
+    IdToTokens(Vec<u32>, oneshot::Sender<Vec<String>>, Span),
+    Decode(
+        Vec<u32>,
+        bool,
+        oneshot::Sender<Result<String, TextEmbeddingsError>>,
+        Span,
+    ),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Word level tokenizer adding `[CLS]` and `[SEP]`
+    fn tokenizer() -> Tokenizer {
+        Tokenizer::from_str(
+            r#"{
+                "version": "1.0",
+                "truncation": null,
+                "padding": null,
+                "added_tokens": [],
+                "normalizer": null,
+                "pre_tokenizer": {"type": "Whitespace"},
+                "post_processor": {"type": "BertProcessing", "sep": ["[SEP]", 1], "cls": ["[CLS]", 0]},
+                "decoder": null,
+                "model": {
+                    "type": "WordLevel",
+                    "vocab": {"[CLS]": 0, "[SEP]": 1, "[UNK]": 2, "a": 3, "b": 4, "c": 5, "d": 6},
+                    "unk_token": "[UNK]"
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn chunks_leave_room_for_special_tokens() {
+        let mut tokenizer = tokenizer();
+
+        let chunks = encode_chunks("a b c d".to_string(), 4, 1, 8, 512, 0, &mut tokenizer).unwrap();
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert_eq!(chunk.encoding.input_ids[0], 0);
+            assert_eq!(*chunk.encoding.input_ids.last().unwrap(), 1);
+        }
+
+        let result = encode_chunks("a b c d".to_string(), 2, 0, 8, 512, 0, &mut tokenizer);
+        assert!(matches!(result, Err(TextEmbeddingsError::Validation(_))));
+
+        let result = encode_chunks("a b c d".to_string(), 4, 2, 8, 512, 0, &mut tokenizer);
+        assert!(matches!(result, Err(TextEmbeddingsError::Validation(_))));
+    }
+
+    #[test]
+    fn chunks_are_capped() {
+        let mut tokenizer = tokenizer();
+
+        // One word per window
+        let chunks = encode_chunks("a b c d".to_string(), 3, 0, 4, 512, 0, &mut tokenizer).unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        let result = encode_chunks("a b c d".to_string(), 3, 0, 3, 512, 0, &mut tokenizer);
+        assert!(matches!(result, Err(TextEmbeddingsError::Validation(_))));
+    }
+}
@@ -0,0 +1,330 @@
+/// API key authentication and per key rate limiting
+use crate::{ErrorResponse, ErrorType};
+use ::http::{HeaderMap, HeaderValue};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// An API key and its limits, as stored in the keys file or in Consul
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApiKey {
+    key: String,
+    /// Used in logs and metrics instead of the key
+    name: String,
+    /// Maximum number of prompt tokens per minute. Unlimited if not set
+    tokens_per_minute: Option<u64>,
+    /// Maximum number of requests per second. Unlimited if not set
+    requests_per_second: Option<u64>,
+}
+
+/// Accepted API keys
+#[derive(Clone)]
+pub(crate) struct ApiKeys {
+    keys: Arc<HashMap<String, Arc<KeyLimiter>>>,
+}
+
+impl ApiKeys {
+    /// Parse a JSON list of `ApiKey`
+    pub(crate) fn from_json(json: &str) -> Result<Self> {
+        let keys: Vec<ApiKey> = serde_json::from_str(json).context("Failed to parse API keys")?;
+
+        let mut limiters = HashMap::with_capacity(keys.len());
+        for key in keys {
+            if key.tokens_per_minute == Some(0) || key.requests_per_second == Some(0) {
+                return Err(anyhow!("API key `{}` has a limit of 0", key.name));
+            }
+            let limiter = KeyLimiter {
+                name: key.name,
+                requests: key
+                    .requests_per_second
+                    .map(|limit| Mutex::new(Bucket::new(limit, Duration::from_secs(1)))),
+                tokens: key
+                    .tokens_per_minute
+                    .map(|limit| Mutex::new(Bucket::new(limit, Duration::from_secs(60)))),
+            };
+            if limiters.insert(key.key, Arc::new(limiter)).is_some() {
+                return Err(anyhow!("API keys must be unique"));
+            }
+        }
+
+        Ok(Self {
+            keys: Arc::new(limiters),
+        })
+    }
+
+    pub(crate) fn from_file(path: &str) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Could not read API keys file `{path}`"))?;
+        Self::from_json(&json)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Find the key of an `Authorization: Bearer <key>` header value
+    pub(crate) fn authorize(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<Arc<KeyLimiter>, ErrorResponse> {
+        let key = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|key| self.keys.get(key.trim()));
+
+        match key {
+            Some(key) => Ok(key.clone()),
+            None => {
+                metrics::increment_counter!("te_request_failure", "err" => "unauthorized");
+                let message = "missing or invalid API key".to_string();
+                tracing::error!("{message}");
+                Err(ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Unauthorized,
+                })
+            }
+        }
+    }
+}
+
+/// Rate limits of a single API key
+pub(crate) struct KeyLimiter {
+    name: String,
+    requests: Option<Mutex<Bucket>>,
+    tokens: Option<Mutex<Bucket>>,
+}
+
+impl KeyLimiter {
+    /// Count a new request against the limits of the key. On failure, returns the time to wait
+    /// before retrying.
+    ///
+    /// Prompt tokens are only known once the request is tokenized: a request is accepted as long
+    /// as the token budget is not exhausted and its tokens are charged with `consume_tokens`.
+    pub(crate) fn acquire(&self) -> Result<(), Duration> {
+        let mut requests = self.requests.as_ref().map(lock);
+        let mut tokens = self.tokens.as_ref().map(lock);
+
+        // Time until both budgets allow a new request
+        let mut retry_after = Duration::ZERO;
+        if let Some(requests) = requests.as_mut() {
+            requests.refill();
+            retry_after = retry_after.max(requests.wait_time(1.0));
+        }
+        if let Some(tokens) = tokens.as_mut() {
+            tokens.refill();
+            // Only wait for the budget to be positive again
+            retry_after = retry_after.max(tokens.wait_time(f64::MIN_POSITIVE));
+        }
+
+        if retry_after > Duration::ZERO {
+            return Err(retry_after);
+        }
+
+        if let Some(requests) = requests.as_mut() {
+            requests.available -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Error and headers of a request rejected by `acquire`
+    pub(crate) fn rate_limited(&self, retry_after: Duration) -> (ErrorResponse, HeaderMap) {
+        metrics::increment_counter!("te_request_failure", "err" => "rate_limited");
+        metrics::increment_counter!("te_rate_limited", "key" => self.name.clone());
+        let message = format!("rate limit reached for API key `{}`", self.name);
+        tracing::error!("{message}");
+
+        let mut headers = self.headers();
+        // Retry-After is expressed in whole seconds
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        headers.insert("retry-after", HeaderValue::from(seconds));
+        (
+            ErrorResponse {
+                error: message,
+                error_type: ErrorType::Overloaded,
+            },
+            headers,
+        )
+    }
+
+    /// Charge the prompt tokens of a served request. Failed requests do not report their tokens
+    /// and are not charged
+    pub(crate) fn consume_tokens(&self, tokens: usize) {
+        if let Some(bucket) = &self.tokens {
+            let mut bucket = lock(bucket);
+            bucket.refill();
+            // The budget can become negative: following requests wait until it is repaid
+            bucket.available -= tokens as f64;
+        }
+    }
+
+    /// `x-ratelimit-*` headers with the current state of the limits
+    pub(crate) fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let limits = [
+            (
+                &self.requests,
+                [
+                    "x-ratelimit-limit-requests",
+                    "x-ratelimit-remaining-requests",
+                    "x-ratelimit-reset-requests",
+                ],
+            ),
+            (
+                &self.tokens,
+                [
+                    "x-ratelimit-limit-tokens",
+                    "x-ratelimit-remaining-tokens",
+                    "x-ratelimit-reset-tokens",
+                ],
+            ),
+        ];
+        for (bucket, [limit, remaining, reset]) in limits {
+            if let Some(bucket) = bucket {
+                let mut bucket = lock(bucket);
+                bucket.refill();
+                headers.insert(limit, HeaderValue::from(bucket.capacity as u64));
+                headers.insert(
+                    remaining,
+                    HeaderValue::from(bucket.available.max(0.0) as u64),
+                );
+                // Milliseconds until the budget is full again
+                headers.insert(
+                    reset,
+                    HeaderValue::from(bucket.wait_time(bucket.capacity).as_millis() as u64),
+                );
+            }
+        }
+        headers
+    }
+}
+
+fn lock(bucket: &Mutex<Bucket>) -> std::sync::MutexGuard<'_, Bucket> {
+    bucket.lock().expect("Lock was poisoned. This is a bug.")
+}
+
+/// Token bucket refilled continuously at `capacity` per `period`
+struct Bucket {
+    capacity: f64,
+    /// Refill rate per second
+    rate: f64,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: u64, period: Duration) -> Self {
+        let capacity = capacity as f64;
+        Self {
+            capacity,
+            rate: capacity / period.as_secs_f64(),
+            available: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until `amount` is available
+    fn wait_time(&self, amount: f64) -> Duration {
+        if self.available >= amount {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((amount - self.available) / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: &str = r#"[
+        {"key": "sk-a", "name": "a", "requests_per_second": 1},
+        {"key": "sk-b", "name": "b", "tokens_per_minute": 100}
+    ]"#;
+
+    fn ago(duration: Duration) -> Instant {
+        Instant::now().checked_sub(duration).unwrap()
+    }
+
+    #[test]
+    fn bucket_refills_up_to_capacity() {
+        let mut bucket = Bucket::new(10, Duration::from_secs(1));
+        bucket.available = 0.0;
+        bucket.last_refill = ago(Duration::from_millis(500));
+        bucket.refill();
+        assert!(bucket.available >= 5.0 && bucket.available < 6.0);
+
+        bucket.last_refill = ago(Duration::from_secs(10));
+        bucket.refill();
+        assert_eq!(bucket.available, 10.0);
+    }
+
+    #[test]
+    fn bucket_wait_time() {
+        let mut bucket = Bucket::new(60, Duration::from_secs(60));
+        assert_eq!(bucket.wait_time(60.0), Duration::ZERO);
+
+        bucket.available = -30.0;
+        let wait_time = bucket.wait_time(1.0);
+        assert!(wait_time > Duration::from_secs(30) && wait_time <= Duration::from_secs(31));
+    }
+
+    #[test]
+    fn from_json_rejections() {
+        assert_eq!(ApiKeys::from_json(KEYS).unwrap().len(), 2);
+
+        for json in [
+            "not json",
+            r#"[{"key": "sk-a"}]"#,
+            r#"[{"key": "sk-a", "name": "a", "scope": "all"}]"#,
+            r#"[{"key": "sk-a", "name": "a", "tokens_per_minute": 0}]"#,
+            r#"[{"key": "sk-a", "name": "a", "requests_per_second": 0}]"#,
+            r#"[{"key": "sk-a", "name": "a"}, {"key": "sk-a", "name": "b"}]"#,
+        ] {
+            assert!(ApiKeys::from_json(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn authorize_parses_bearer_keys() {
+        let keys = ApiKeys::from_json(KEYS).unwrap();
+
+        assert_eq!(keys.authorize(Some("Bearer sk-a")).ok().unwrap().name, "a");
+        assert_eq!(
+            keys.authorize(Some("Bearer  sk-b ")).ok().unwrap().name,
+            "b"
+        );
+
+        for authorization in [None, Some("sk-a"), Some("Basic sk-a"), Some("Bearer sk-c")] {
+            let err = keys.authorize(authorization).err().unwrap();
+            assert!(matches!(err.error_type, ErrorType::Unauthorized));
+        }
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let keys = ApiKeys::from_json(KEYS).unwrap();
+
+        let requests = keys.authorize(Some("Bearer sk-a")).ok().unwrap();
+        assert!(requests.acquire().is_ok());
+        let retry_after = requests.acquire().unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(1));
+
+        // Requests are accepted until the token budget is exhausted
+        let tokens = keys.authorize(Some("Bearer sk-b")).ok().unwrap();
+        assert!(tokens.acquire().is_ok());
+        tokens.consume_tokens(150);
+        let retry_after = tokens.acquire().unwrap_err();
+        assert!(retry_after > Duration::from_secs(29));
+        assert_eq!(tokens.headers()["x-ratelimit-remaining-tokens"], "0");
+    }
+}
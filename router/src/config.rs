@@ -0,0 +1,515 @@
+/// Configuration file and runtime limits
+use crate::{Info, Models};
+use ::http::HeaderValue;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use text_embeddings_backend::{DType, Pool};
+use text_embeddings_core::quantization::QuantizationRanges;
+use veil::Redact;
+
+/// Interval at which the configuration file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Router configuration file, in TOML or YAML.
+///
+/// Every field mirrors the command line argument of the same name. Values set on the command
+/// line or through environment variables take precedence over the file.
+#[derive(Clone, Default, PartialEq, Deserialize, Redact)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[redact(partial)]
+    pub model_id: Option<String>,
+    pub revision: Option<String>,
+    #[redact(partial)]
+    pub additional_model_ids: Option<Vec<String>>,
+    pub tokenization_workers: Option<usize>,
+    pub dtype: Option<String>,
+    pub pooling: Option<String>,
+    pub max_input_length: Option<usize>,
+    pub max_concurrent_requests: Option<usize>,
+    pub max_batch_tokens: Option<usize>,
+    pub max_batch_requests: Option<usize>,
+    pub max_client_batch_size: Option<usize>,
+    pub embedding_cache_size_mb: Option<usize>,
+    #[redact(partial)]
+    pub hf_api_token: Option<String>,
+    pub hostname: Option<String>,
+    pub port: Option<u16>,
+    pub uds_path: Option<String>,
+    pub huggingface_hub_cache: Option<String>,
+    pub json_output: Option<bool>,
+    pub otlp_endpoint: Option<String>,
+    pub api_keys_file: Option<String>,
+    pub cors_allow_origin: Option<Vec<String>>,
+    /// Per endpoint limits
+    #[serde(default)]
+    pub endpoints: EndpointsConfig,
+    /// Calibration ranges of the `int8` and `uint8` embeddings, indexed by model
+    #[serde(default)]
+    pub quantization_ranges: HashMap<String, QuantizationRangesConfig>,
+    /// Prompts by name, indexed by model. Added to the prompts of
+    /// `config_sentence_transformers.json`
+    #[serde(default)]
+    pub prompts: HashMap<String, HashMap<String, String>>,
+    /// Additional models with their own settings, served next to `model_id` and
+    /// `additional_model_ids`
+    #[serde(default)]
+    pub models: Vec<ModelSettings>,
+}
+
+impl Config {
+    /// Load a configuration file. The format is chosen from the file extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read configuration file `{}`", path.display()))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content).context("Failed to parse TOML configuration"),
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).context("Failed to parse YAML configuration")
+            }
+            _ => Err(anyhow!(
+                "Configuration file `{}` must have a `.toml`, `.yaml` or `.yml` extension",
+                path.display()
+            )),
+        }
+    }
+
+    /// Same configuration without the values that can be updated at runtime
+    fn without_runtime_limits(&self) -> Self {
+        Self {
+            max_concurrent_requests: None,
+            max_batch_tokens: None,
+            max_client_batch_size: None,
+            cors_allow_origin: None,
+            endpoints: EndpointsConfig::default(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Router settings: the command line arguments merged with the configuration file
+#[derive(Redact)]
+pub struct Settings {
+    #[redact(partial)]
+    pub model_id: String,
+    pub revision: Option<String>,
+    #[redact(partial)]
+    pub additional_model_ids: Vec<String>,
+    pub tokenization_workers: Option<usize>,
+    pub dtype: Option<DType>,
+    pub pooling: Option<Pool>,
+    pub max_input_length: Option<usize>,
+    pub max_concurrent_requests: usize,
+    pub max_batch_tokens: usize,
+    pub max_batch_requests: Option<usize>,
+    pub max_client_batch_size: usize,
+    pub embedding_cache_size_mb: usize,
+    #[redact(partial)]
+    pub hf_api_token: Option<String>,
+    pub hostname: String,
+    pub port: u16,
+    pub uds_path: String,
+    pub huggingface_hub_cache: Option<String>,
+    pub json_output: bool,
+    pub otlp_endpoint: Option<String>,
+    pub api_keys_file: Option<String>,
+    /// `None` allows any origin
+    pub cors_allow_origin: Option<Vec<String>>,
+    /// Watched for changes of the runtime limits when set
+    pub config_path: Option<PathBuf>,
+    /// Content of `config_path`, loaded once at startup
+    pub config: Config,
+    /// Arguments set on the command line or through environment variables. The configuration
+    /// file does not override them, even when it is reloaded
+    pub fixed_args: HashSet<String>,
+}
+
+/// Settings of an additional model. `revision`, `pooling` and `max_input_length` default to the
+/// model own configuration, the limits to the global ones
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelSettings {
+    pub model_id: String,
+    pub revision: Option<String>,
+    pub pooling: Option<String>,
+    pub max_input_length: Option<usize>,
+    pub max_concurrent_requests: Option<usize>,
+    pub max_batch_tokens: Option<usize>,
+    pub max_batch_requests: Option<usize>,
+}
+
+/// Limits a model was given in its `[[models]]` settings. They are not reloaded and take
+/// precedence over the global limits
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ModelLimits {
+    pub(crate) max_concurrent_requests: Option<usize>,
+    pub(crate) max_batch_tokens: Option<usize>,
+}
+
+/// Minimum and maximum value of each embedding dimension, usually computed on a calibration
+/// dataset
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuantizationRangesConfig {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl From<QuantizationRangesConfig> for QuantizationRanges {
+    fn from(value: QuantizationRangesConfig) -> Self {
+        Self {
+            min: value.min,
+            max: value.max,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointsConfig {
+    #[serde(default)]
+    pub embed: EndpointConfig,
+    /// OpenAI compatible route
+    #[serde(default)]
+    pub embeddings: EndpointConfig,
+    #[serde(default)]
+    pub embed_sparse: EndpointConfig,
+    #[serde(default)]
+    pub embed_all: EndpointConfig,
+    #[serde(default)]
+    pub embed_chunks: EndpointConfig,
+    #[serde(default)]
+    pub similarity: EndpointConfig,
+    #[serde(default)]
+    pub predict: EndpointConfig,
+    #[serde(default)]
+    pub token_classify: EndpointConfig,
+    #[serde(default)]
+    pub rerank: EndpointConfig,
+    #[serde(default)]
+    pub tokenize: EndpointConfig,
+    #[serde(default)]
+    pub decode: EndpointConfig,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    /// Overrides the global `max_client_batch_size` for this endpoint
+    pub max_client_batch_size: Option<usize>,
+}
+
+/// gRPC only checks the batch size of `Rerank` and `Similarity`
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "grpc", allow(dead_code))]
+pub(crate) enum Endpoint {
+    Embed,
+    Embeddings,
+    EmbedSparse,
+    EmbedAll,
+    EmbedChunks,
+    Similarity,
+    Predict,
+    TokenClassify,
+    Rerank,
+    Tokenize,
+    Decode,
+}
+
+/// Limits that can be updated without restarting the router
+#[derive(Debug)]
+pub(crate) struct RuntimeLimits {
+    max_concurrent_requests: AtomicUsize,
+    max_batch_tokens: AtomicUsize,
+    max_client_batch_size: AtomicUsize,
+    endpoints: RwLock<EndpointsConfig>,
+    /// `None` allows any origin
+    cors_allow_origin: RwLock<Option<Vec<HeaderValue>>>,
+}
+
+impl RuntimeLimits {
+    pub(crate) fn new(
+        max_concurrent_requests: usize,
+        max_batch_tokens: usize,
+        max_client_batch_size: usize,
+        endpoints: EndpointsConfig,
+        cors_allow_origin: Option<Vec<String>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            max_concurrent_requests: AtomicUsize::new(max_concurrent_requests),
+            max_batch_tokens: AtomicUsize::new(max_batch_tokens),
+            max_client_batch_size: AtomicUsize::new(max_client_batch_size),
+            endpoints: RwLock::new(endpoints),
+            cors_allow_origin: RwLock::new(parse_origins(cors_allow_origin)?),
+        })
+    }
+
+    /// Maximum number of inputs a client can send in a single request to this endpoint
+    pub(crate) fn max_client_batch_size(&self, endpoint: Endpoint) -> usize {
+        let endpoints = self
+            .endpoints
+            .read()
+            .expect("Lock was poisoned. This is a bug.");
+        let endpoint = match endpoint {
+            Endpoint::Embed => &endpoints.embed,
+            Endpoint::Embeddings => &endpoints.embeddings,
+            Endpoint::EmbedSparse => &endpoints.embed_sparse,
+            Endpoint::EmbedAll => &endpoints.embed_all,
+            Endpoint::EmbedChunks => &endpoints.embed_chunks,
+            Endpoint::Similarity => &endpoints.similarity,
+            Endpoint::Predict => &endpoints.predict,
+            Endpoint::TokenClassify => &endpoints.token_classify,
+            Endpoint::Rerank => &endpoints.rerank,
+            Endpoint::Tokenize => &endpoints.tokenize,
+            Endpoint::Decode => &endpoints.decode,
+        };
+        endpoint
+            .max_client_batch_size
+            .unwrap_or_else(|| self.max_client_batch_size.load(Ordering::Relaxed))
+    }
+
+    #[cfg_attr(feature = "grpc", allow(dead_code))]
+    pub(crate) fn allow_origin(&self, origin: &HeaderValue) -> bool {
+        match &*self
+            .cors_allow_origin
+            .read()
+            .expect("Lock was poisoned. This is a bug.")
+        {
+            None => true,
+            Some(origins) => origins.contains(origin),
+        }
+    }
+
+    /// Model info with the current limits
+    pub(crate) fn info(&self, info: &Info) -> Info {
+        Info {
+            max_concurrent_requests: info
+                .limits
+                .max_concurrent_requests
+                .unwrap_or_else(|| self.max_concurrent_requests.load(Ordering::Relaxed)),
+            max_batch_tokens: info
+                .limits
+                .max_batch_tokens
+                .unwrap_or_else(|| self.max_batch_tokens.load(Ordering::Relaxed)),
+            max_client_batch_size: self.max_client_batch_size.load(Ordering::Relaxed),
+            ..info.clone()
+        }
+    }
+
+    /// Apply the runtime limits of `config`. Missing global values and `fixed_args` are left
+    /// untouched while endpoint limits are replaced
+    fn update(&self, config: &Config, models: &Models, fixed_args: &HashSet<String>) -> Result<()> {
+        let unless_fixed = |arg: &str| !fixed_args.contains(arg);
+        let max_concurrent_requests = config
+            .max_concurrent_requests
+            .filter(|_| unless_fixed("max_concurrent_requests"));
+        let max_batch_tokens = config
+            .max_batch_tokens
+            .filter(|_| unless_fixed("max_batch_tokens"));
+        let max_client_batch_size = config
+            .max_client_batch_size
+            .filter(|_| unless_fixed("max_client_batch_size"));
+        let cors_allow_origin = config
+            .cors_allow_origin
+            .clone()
+            .filter(|_| unless_fixed("cors_allow_origin"));
+
+        // Validate before applying anything
+        if max_concurrent_requests == Some(0) {
+            return Err(anyhow!("`max_concurrent_requests` must be > 0"));
+        }
+        if let Some(max_batch_tokens) = max_batch_tokens {
+            for (_, info) in models.iter() {
+                if info.limits.max_batch_tokens.is_none()
+                    && max_batch_tokens < info.max_input_length
+                {
+                    return Err(anyhow!(
+                        "`max_batch_tokens` must be >= {} (`max_input_length` of model `{}`)",
+                        info.max_input_length,
+                        info.model_id
+                    ));
+                }
+            }
+        }
+        let parsed_cors_allow_origin = parse_origins(cors_allow_origin.clone())?;
+
+        if let Some(max_concurrent_requests) = max_concurrent_requests {
+            for (infer, info) in models.iter() {
+                if info.limits.max_concurrent_requests.is_none() {
+                    infer.set_max_concurrent_requests(max_concurrent_requests);
+                }
+            }
+            self.max_concurrent_requests
+                .store(max_concurrent_requests, Ordering::Relaxed);
+        }
+        if let Some(max_batch_tokens) = max_batch_tokens {
+            for (infer, info) in models.iter() {
+                if info.limits.max_batch_tokens.is_none() {
+                    infer.set_max_batch_tokens(max_batch_tokens);
+                }
+            }
+            self.max_batch_tokens
+                .store(max_batch_tokens, Ordering::Relaxed);
+        }
+        if let Some(max_client_batch_size) = max_client_batch_size {
+            self.max_client_batch_size
+                .store(max_client_batch_size, Ordering::Relaxed);
+        }
+        *self
+            .endpoints
+            .write()
+            .expect("Lock was poisoned. This is a bug.") = config.endpoints.clone();
+        if cors_allow_origin.is_some() {
+            *self
+                .cors_allow_origin
+                .write()
+                .expect("Lock was poisoned. This is a bug.") = parsed_cors_allow_origin;
+        }
+        Ok(())
+    }
+}
+
+fn parse_origins(origins: Option<Vec<String>>) -> Result<Option<Vec<HeaderValue>>> {
+    origins
+        .map(|origins| {
+            origins
+                .into_iter()
+                .map(|origin| {
+                    origin
+                        .parse::<HeaderValue>()
+                        .with_context(|| format!("Invalid CORS origin `{origin}`"))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+/// Watch the configuration file and apply changes to the runtime limits
+pub(crate) async fn watch(
+    path: PathBuf,
+    mut config: Config,
+    models: Models,
+    limits: Arc<RuntimeLimits>,
+    fixed_args: HashSet<String>,
+) {
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let new_config = match Config::load(&path) {
+            Ok(new_config) => new_config,
+            Err(err) => {
+                tracing::warn!("Could not reload configuration: {err:#}");
+                continue;
+            }
+        };
+        if new_config == config {
+            continue;
+        }
+
+        if new_config.without_runtime_limits() != config.without_runtime_limits() {
+            tracing::warn!(
+                "Configuration file changed: only `max_concurrent_requests`, `max_batch_tokens`, \
+                `max_client_batch_size`, `cors_allow_origin` and `endpoints` are applied without a restart"
+            );
+        }
+
+        match limits.update(&new_config, &models, &fixed_args) {
+            Ok(()) => {
+                metrics::increment_counter!("te_config_reload", "status" => "success");
+                tracing::info!("Configuration reloaded");
+            }
+            Err(err) => {
+                metrics::increment_counter!("te_config_reload", "status" => "failure");
+                tracing::error!("Invalid configuration, keeping the current limits: {err:#}");
+            }
+        }
+        config = new_config;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_models() -> Models {
+        Models {
+            default: String::new(),
+            models: Arc::new(HashMap::new()),
+        }
+    }
+
+    fn limits() -> RuntimeLimits {
+        RuntimeLimits::new(
+            512,
+            16384,
+            32,
+            EndpointsConfig::default(),
+            Some(vec!["https://a.com".to_string()]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reload_skips_fixed_args() {
+        let limits = limits();
+        let fixed_args = HashSet::from([
+            "max_batch_tokens".to_string(),
+            "cors_allow_origin".to_string(),
+        ]);
+        let config = Config {
+            max_concurrent_requests: Some(8),
+            max_batch_tokens: Some(1024),
+            max_client_batch_size: Some(4),
+            cors_allow_origin: Some(vec!["https://b.com".to_string()]),
+            ..Default::default()
+        };
+
+        limits.update(&config, &no_models(), &fixed_args).unwrap();
+        assert_eq!(limits.max_concurrent_requests.load(Ordering::Relaxed), 8);
+        assert_eq!(limits.max_batch_tokens.load(Ordering::Relaxed), 16384);
+        assert_eq!(limits.max_client_batch_size(Endpoint::Embed), 4);
+        assert!(limits.allow_origin(&HeaderValue::from_static("https://a.com")));
+        assert!(!limits.allow_origin(&HeaderValue::from_static("https://b.com")));
+    }
+
+    #[test]
+    fn reload_applies_endpoints_and_origins() {
+        let limits = limits();
+        let mut config = Config {
+            cors_allow_origin: Some(vec!["https://b.com".to_string()]),
+            ..Default::default()
+        };
+        config.endpoints.rerank.max_client_batch_size = Some(64);
+
+        limits
+            .update(&config, &no_models(), &HashSet::new())
+            .unwrap();
+        assert_eq!(limits.max_client_batch_size(Endpoint::Rerank), 64);
+        assert_eq!(limits.max_client_batch_size(Endpoint::Embed), 32);
+        assert!(limits.allow_origin(&HeaderValue::from_static("https://b.com")));
+        assert!(!limits.allow_origin(&HeaderValue::from_static("https://a.com")));
+    }
+
+    #[test]
+    fn reload_rejects_invalid_limits() {
+        let limits = limits();
+        let config = Config {
+            max_concurrent_requests: Some(0),
+            max_client_batch_size: Some(4),
+            ..Default::default()
+        };
+
+        assert!(limits
+            .update(&config, &no_models(), &HashSet::new())
+            .is_err());
+        assert_eq!(limits.max_client_batch_size(Endpoint::Embed), 32);
+    }
+}
@@ -3,5 +3,6 @@ pub(crate) mod server;
 
 use pb::tei::v1::{
     embed_server::EmbedServer, info_server::InfoServer, predict_server::PredictServer,
-    rerank_server::RerankServer, *,
+    rerank_server::RerankServer, token_classify_server::TokenClassifyServer,
+    tokenize_server::TokenizeServer, *,
 };
@@ -1,21 +1,38 @@
+use crate::auth::{ApiKeys, KeyLimiter};
+use crate::config::{Endpoint, RuntimeLimits};
 use crate::grpc::pb::tei::v1::RerankStreamRequest;
 use crate::grpc::{
-    EmbedRequest, EmbedResponse, InfoRequest, InfoResponse, PredictRequest, PredictResponse,
-    Prediction, Rank, RerankRequest, RerankResponse,
+    DecodeRequest, DecodeResponse, EmbedAllRequest, EmbedAllResponse, EmbedRequest, EmbedResponse,
+    EmbedSparseRequest, EmbedSparseResponse, EncodeRequest, EncodeResponse, InfoRequest,
+    InfoResponse, PredictRequest, PredictResponse, Prediction, Rank, RerankRequest, RerankResponse,
+    SimilarityRequest, SimilarityResponse, SimpleToken, SparseValue, TokenClassifyRequest,
+    TokenClassifyResponse, TokenEmbedding,
 };
 use crate::ResponseMetadata;
-use crate::{grpc, shutdown, ErrorResponse, ErrorType, Info, ModelType};
+use crate::{
+    function_to_apply, grpc, parse_priority, resolve_prompt, shutdown, similarity_scores,
+    token_classify_entities, validate_dimensions, validate_input_type, validate_layer, with_prompt,
+    ErrorResponse, ErrorType, Info, MaxInputLengthSource, ModelType, Models, MODEL_HEADER,
+    PRIORITY_HEADER,
+};
 use futures::future::join_all;
 use metrics_exporter_prometheus::PrometheusBuilder;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use text_embeddings_core::classification::FunctionToApply;
 use text_embeddings_core::infer::Infer;
+use text_embeddings_core::queue::Priority;
+use text_embeddings_core::token_classification::{AggregationStrategy, Entity};
+use text_embeddings_core::tokenization::{self, EncodingInput, Truncation};
+use text_embeddings_core::{colbert, quantization, similarity};
 use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_stream::StreamExt;
 use tonic::codegen::http::HeaderMap;
 use tonic::metadata::MetadataMap;
 use tonic::server::NamedService;
+use tonic::service::Interceptor;
 use tonic::transport::Server;
 use tonic::{Code, Extensions, Request, Response, Status, Streaming};
 use tonic_health::ServingStatus;
@@ -30,26 +47,225 @@ impl From<&ResponseMetadata> for grpc::Metadata {
             tokenization_time_ns: value.tokenization_time.as_nanos() as u64,
             queue_time_ns: value.queue_time.as_nanos() as u64,
             inference_time_ns: value.inference_time.as_nanos() as u64,
+            cache_hit: value.cache_hit,
+        }
+    }
+}
+
+impl From<grpc::Precision> for quantization::Precision {
+    fn from(value: grpc::Precision) -> Self {
+        match value {
+            grpc::Precision::Float32 => quantization::Precision::Float32,
+            grpc::Precision::Int8 => quantization::Precision::Int8,
+            grpc::Precision::Uint8 => quantization::Precision::Uint8,
+            grpc::Precision::Binary => quantization::Precision::Binary,
+            grpc::Precision::Ubinary => quantization::Precision::Ubinary,
         }
     }
 }
 
+impl From<grpc::SimilarityMetric> for similarity::SimilarityMetric {
+    fn from(value: grpc::SimilarityMetric) -> Self {
+        match value {
+            grpc::SimilarityMetric::Cosine => similarity::SimilarityMetric::Cosine,
+            grpc::SimilarityMetric::Dot => similarity::SimilarityMetric::Dot,
+        }
+    }
+}
+
+impl From<grpc::InputType> for colbert::InputType {
+    fn from(value: grpc::InputType) -> Self {
+        match value {
+            grpc::InputType::Query => colbert::InputType::Query,
+            grpc::InputType::Document => colbert::InputType::Document,
+        }
+    }
+}
+
+impl From<grpc::FunctionToApply> for FunctionToApply {
+    fn from(value: grpc::FunctionToApply) -> Self {
+        match value {
+            grpc::FunctionToApply::Softmax => FunctionToApply::Softmax,
+            grpc::FunctionToApply::Sigmoid => FunctionToApply::Sigmoid,
+            grpc::FunctionToApply::None => FunctionToApply::None,
+        }
+    }
+}
+
+impl From<grpc::AggregationStrategy> for AggregationStrategy {
+    fn from(value: grpc::AggregationStrategy) -> Self {
+        match value {
+            grpc::AggregationStrategy::Simple => AggregationStrategy::Simple,
+            grpc::AggregationStrategy::First => AggregationStrategy::First,
+            grpc::AggregationStrategy::Max => AggregationStrategy::Max,
+        }
+    }
+}
+
+impl From<Entity> for grpc::Entity {
+    fn from(value: Entity) -> Self {
+        Self {
+            label: value.label,
+            score: value.score,
+            start: value.start as u32,
+            end: value.end as u32,
+            text: value.text,
+        }
+    }
+}
+
+impl From<grpc::TruncationDirection> for tokenization::TruncationDirection {
+    fn from(value: grpc::TruncationDirection) -> Self {
+        match value {
+            grpc::TruncationDirection::Right => tokenization::TruncationDirection::Right,
+            grpc::TruncationDirection::Left => tokenization::TruncationDirection::Left,
+        }
+    }
+}
+
+impl From<grpc::TruncationStrategy> for tokenization::TruncationStrategy {
+    fn from(value: grpc::TruncationStrategy) -> Self {
+        match value {
+            grpc::TruncationStrategy::LongestFirst => {
+                tokenization::TruncationStrategy::LongestFirst
+            }
+            grpc::TruncationStrategy::OnlyFirst => tokenization::TruncationStrategy::OnlyFirst,
+            grpc::TruncationStrategy::OnlySecond => tokenization::TruncationStrategy::OnlySecond,
+        }
+    }
+}
+
+fn truncation(
+    truncate: bool,
+    direction: grpc::TruncationDirection,
+    strategy: grpc::TruncationStrategy,
+    max_tokens: Option<u32>,
+) -> Truncation {
+    Truncation {
+        truncate,
+        direction: direction.into(),
+        strategy: strategy.into(),
+        max_tokens: max_tokens.map(|m| m as usize),
+    }
+}
+
+/// Select a model from the request message or, if absent, from the model metadata key
+fn requested_model<'a>(model: Option<&'a str>, metadata: &'a MetadataMap) -> Option<&'a str> {
+    model.or_else(|| {
+        metadata
+            .get(MODEL_HEADER)
+            .and_then(|value| value.to_str().ok())
+    })
+}
+
+/// Priority class of a request, from the priority metadata key
+fn requested_priority(metadata: &MetadataMap) -> Result<Priority, ErrorResponse> {
+    parse_priority(
+        metadata
+            .get(PRIORITY_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// Input of a request and its number of characters. Pre-tokenized `input_ids` replace `inputs`
+/// and have no characters
+fn request_input(
+    inputs: String,
+    input_ids: Vec<u32>,
+    prompt: Option<&str>,
+) -> Result<(EncodingInput, usize), ErrorResponse> {
+    match (inputs.is_empty(), input_ids.is_empty()) {
+        (false, false) => {
+            let message = "`inputs` and `input_ids` cannot be set at the same time".to_string();
+            metrics::increment_counter!("te_request_failure", "err" => "validation");
+            tracing::error!("{message}");
+            Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            })
+        }
+        (true, false) => Ok((EncodingInput::Ids(input_ids), 0)),
+        _ => {
+            let inputs = with_prompt(prompt, inputs);
+            let compute_chars = inputs.chars().count();
+            Ok((EncodingInput::Single(inputs), compute_chars))
+        }
+    }
+}
+
+/// Deadline of a request from its `grpc-timeout` metadata value.
+/// Invalid values are ignored, as tonic does
+fn request_deadline(metadata: &MetadataMap, start_time: Instant) -> Option<Instant> {
+    let value = metadata.get("grpc-timeout")?.to_str().ok()?;
+    // At most 8 digits followed by a unit
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    let amount: u64 = amount.parse().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(amount * 60 * 60),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    };
+    Some(start_time + timeout)
+}
+
+/// Authenticate requests and enforce the rate limits of their API key
+#[derive(Clone)]
+struct AuthInterceptor {
+    api_keys: Option<ApiKeys>,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(api_keys) = &self.api_keys {
+            let authorization = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok());
+            let key = api_keys.authorize(authorization)?;
+            key.acquire().map_err(|retry_after| {
+                let (err, headers) = key.rate_limited(retry_after);
+                let mut status = Status::from(err);
+                *status.metadata_mut() = MetadataMap::from_headers(headers);
+                status
+            })?;
+            // Used to charge the prompt tokens once the request is served
+            request.extensions_mut().insert(key);
+        }
+        Ok(request)
+    }
+}
+
+/// Charge the prompt tokens of a request to its API key and add the rate limit headers
+fn charge_api_key(key: Option<&KeyLimiter>, compute_tokens: usize, headers: &mut HeaderMap) {
+    if let Some(key) = key {
+        key.consume_tokens(compute_tokens);
+        headers.extend(key.headers());
+    }
+}
+
 #[derive(Debug, Clone)]
 struct TextEmbeddingsService {
-    infer: Infer,
-    info: Info,
+    models: Models,
+    limits: Arc<RuntimeLimits>,
     max_parallel_stream_requests: usize,
 }
 
 impl TextEmbeddingsService {
-    fn new(infer: Infer, info: Info) -> Self {
+    fn new(models: Models, limits: Arc<RuntimeLimits>) -> Self {
         let max_parallel_stream_requests = std::env::var("GRPC_MAX_PARALLEL_STREAM_REQUESTS")
             .ok()
             .and_then(|s| s.parse::<usize>().ok())
             .unwrap_or(1024);
         Self {
-            infer,
-            info,
+            models,
+            limits,
             max_parallel_stream_requests,
         }
     }
@@ -67,16 +283,43 @@ impl TextEmbeddingsService {
     )]
     async fn embed_inner(
         &self,
+        infer: &Infer,
+        info: &Info,
         request: EmbedRequest,
+        priority: Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedResponse, ResponseMetadata), Status> {
         let span = Span::current();
         let start_time = Instant::now();
 
-        let compute_chars = request.inputs.chars().count();
-        let response = self
-            .infer
-            .embed(request.inputs, request.truncate, request.normalize, permit)
+        let dimensions = request.dimensions.map(|d| d as usize);
+        validate_dimensions(dimensions, info)?;
+        let precision = quantization::Precision::from(request.precision());
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
+        let prompt = resolve_prompt(
+            request.prompt_name.as_deref(),
+            request.prompt.as_deref(),
+            info,
+        )?;
+        let (inputs, compute_chars) = request_input(request.inputs, request.input_ids, prompt)?;
+
+        let response = infer
+            .embed(
+                inputs,
+                truncation,
+                request.normalize,
+                dimensions,
+                precision,
+                priority,
+                deadline,
+                permit,
+            )
             .await
             .map_err(ErrorResponse::from)?;
 
@@ -87,6 +330,7 @@ impl TextEmbeddingsService {
             response.tokenization,
             response.queue,
             response.inference,
+            response.cached,
         );
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
@@ -94,8 +338,109 @@ impl TextEmbeddingsService {
         tracing::info!("Success");
 
         Ok((
-            EmbedResponse {
-                embeddings: response.results,
+            match precision {
+                quantization::Precision::Float32 => EmbedResponse {
+                    embeddings: response.results,
+                    metadata: Some(grpc::Metadata::from(&response_metadata)),
+                    quantized_embeddings: Vec::new(),
+                },
+                _ => EmbedResponse {
+                    embeddings: Vec::new(),
+                    metadata: Some(grpc::Metadata::from(&response_metadata)),
+                    quantized_embeddings: response
+                        .results
+                        .into_iter()
+                        .map(|v| match precision.is_signed() {
+                            true => v as i8 as u8,
+                            false => v as u8,
+                        })
+                        .collect(),
+                },
+            },
+            response_metadata,
+        ))
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn embed_sparse_inner(
+        &self,
+        infer: &Infer,
+        info: &Info,
+        request: EmbedSparseRequest,
+        priority: Priority,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<(EmbedSparseResponse, ResponseMetadata), Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
+        let prompt = resolve_prompt(
+            request.prompt_name.as_deref(),
+            request.prompt.as_deref(),
+            info,
+        )?;
+        let inputs = with_prompt(prompt, request.inputs);
+
+        let compute_chars = inputs.chars().count();
+        let response = infer
+            .embed_sparse(inputs, truncation, priority, deadline, permit)
+            .await
+            .map_err(ErrorResponse::from)?;
+
+        // Only keep the activated vocabulary entries
+        let mut sparse_embedding: Vec<SparseValue> = response
+            .results
+            .into_iter()
+            .enumerate()
+            .filter(|(_, value)| *value > request.threshold)
+            .map(|(index, value)| SparseValue {
+                index: index as u32,
+                value,
+                token: None,
+            })
+            .collect();
+
+        if request.return_tokens {
+            let ids = sparse_embedding.iter().map(|v| v.index).collect();
+            let tokens = infer.id_to_tokens(ids).await;
+            for (sparse_value, token) in sparse_embedding.iter_mut().zip(tokens) {
+                sparse_value.token = Some(token);
+            }
+        }
+
+        let response_metadata = ResponseMetadata::new(
+            compute_chars,
+            response.prompt_tokens,
+            start_time,
+            response.tokenization,
+            response.queue,
+            response.inference,
+            response.cached,
+        );
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        tracing::info!("Success");
+
+        Ok((
+            EmbedSparseResponse {
+                sparse_embedding,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
             },
             response_metadata,
@@ -115,20 +460,45 @@ impl TextEmbeddingsService {
     )]
     async fn predict_inner(
         &self,
+        infer: &Infer,
+        info: &Info,
         request: PredictRequest,
+        priority: Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(PredictResponse, ResponseMetadata), Status> {
         let span = Span::current();
         let start_time = Instant::now();
 
-        let compute_chars = request.inputs.chars().count();
-        let response = self
-            .infer
-            .predict(request.inputs, request.truncate, request.raw_scores, permit)
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
+
+        let function_to_apply = function_to_apply(
+            request
+                .function_to_apply
+                .map(|_| request.function_to_apply().into()),
+            request.raw_scores,
+            info,
+        );
+
+        let (inputs, compute_chars) = request_input(request.inputs, request.input_ids, None)?;
+        let response = infer
+            .predict(
+                inputs,
+                truncation,
+                function_to_apply,
+                priority,
+                deadline,
+                permit,
+            )
             .await
             .map_err(ErrorResponse::from)?;
 
-        let id2label = match &self.info.model_type {
+        let id2label = match &info.model_type {
             ModelType::Classifier(classifier) => &classifier.id2label,
             ModelType::Reranker(classifier) => &classifier.id2label,
             _ => panic!(),
@@ -141,6 +511,7 @@ impl TextEmbeddingsService {
             response.tokenization,
             response.queue,
             response.inference,
+            response.cached,
         );
 
         let mut predictions: Vec<Prediction> = {
@@ -158,6 +529,9 @@ impl TextEmbeddingsService {
         // Reverse sort
         predictions.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
         predictions.reverse();
+        if let Some(top_k) = request.top_k {
+            predictions.truncate(top_k as usize);
+        }
 
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
@@ -176,27 +550,46 @@ impl TextEmbeddingsService {
 
 #[tonic::async_trait]
 impl grpc::info_server::Info for TextEmbeddingsService {
-    async fn info(&self, _request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
-        let model_type = match self.info.model_type {
+    async fn info(&self, request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
+        let (_, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let info = self.limits.info(info);
+
+        let model_type = match info.model_type {
             ModelType::Classifier(_) => grpc::ModelType::Classifier,
             ModelType::Embedding(_) => grpc::ModelType::Embedding,
             ModelType::Reranker(_) => grpc::ModelType::Reranker,
+            ModelType::TokenClassifier(_) => grpc::ModelType::TokenClassifier,
+        };
+
+        let max_input_length_source = match info.max_input_length_source {
+            MaxInputLengthSource::Argument => grpc::MaxInputLengthSource::Argument,
+            MaxInputLengthSource::SentenceBertConfig => {
+                grpc::MaxInputLengthSource::SentenceBertConfig
+            }
+            MaxInputLengthSource::TokenizerConfig => grpc::MaxInputLengthSource::TokenizerConfig,
+            MaxInputLengthSource::ModelConfig => grpc::MaxInputLengthSource::ModelConfig,
         };
 
         Ok(Response::new(InfoResponse {
-            version: self.info.version.to_string(),
-            sha: self.info.sha.map(|s| s.to_string()),
-            docker_label: self.info.docker_label.map(|s| s.to_string()),
-            model_id: self.info.model_id.clone(),
-            model_sha: self.info.model_sha.clone(),
-            model_dtype: self.info.model_dtype.clone(),
+            version: info.version.to_string(),
+            sha: info.sha.map(|s| s.to_string()),
+            docker_label: info.docker_label.map(|s| s.to_string()),
+            model_id: info.model_id.clone(),
+            model_sha: info.model_sha.clone(),
+            model_dtype: info.model_dtype.clone(),
             model_type: model_type.into(),
-            max_concurrent_requests: self.info.max_concurrent_requests as u32,
-            max_input_length: self.info.max_input_length as u32,
-            max_batch_tokens: self.info.max_batch_tokens as u32,
-            max_batch_requests: self.info.max_batch_requests.map(|v| v as u32),
-            max_client_batch_size: self.info.max_client_batch_size as u32,
-            tokenization_workers: self.info.tokenization_workers as u32,
+            prompts: info.prompts.clone(),
+            default_prompt_name: info.default_prompt_name.clone(),
+            max_concurrent_requests: info.max_concurrent_requests as u32,
+            max_input_length: info.max_input_length as u32,
+            max_input_length_source: max_input_length_source.into(),
+            max_batch_tokens: info.max_batch_tokens as u32,
+            max_batch_requests: info.max_batch_requests.map(|v| v as u32),
+            max_client_batch_size: info.max_client_batch_size as u32,
+            tokenization_workers: info.tokenization_workers as u32,
         }))
     }
 }
@@ -210,14 +603,23 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
     ) -> Result<Response<EmbedResponse>, Status> {
         metrics::increment_counter!("te_request_count", "method" => "single");
 
-        let permit = self
-            .infer
-            .try_acquire_permit()
-            .map_err(ErrorResponse::from)?;
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), Instant::now());
 
+        let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
         let request = request.into_inner();
-        let (response, metadata) = self.embed_inner(request, permit).await?;
-        let headers = HeaderMap::from(metadata);
+        let (response, metadata) = self
+            .embed_inner(infer, info, request, priority, deadline, permit)
+            .await?;
+        let compute_tokens = metadata.compute_tokens;
+        let mut headers = HeaderMap::from(metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
 
         metrics::increment_counter!("te_request_success", "method" => "single");
 
@@ -235,6 +637,11 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<Streaming<EmbedRequest>>,
     ) -> Result<Response<Self::EmbedStreamStream>, Status> {
+        // Used for the messages that do not select a model
+        let metadata = request.metadata().clone();
+        let priority = requested_priority(&metadata)?;
+        let deadline = request_deadline(&metadata, Instant::now());
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
         let mut request_stream = request.into_inner();
 
         // Create bounded channel to have an upper bound of spawned tasks
@@ -250,18 +657,36 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         // Background task that uses the bounded channel
         tokio::spawn(async move {
             while let Some((request, mut sender)) = embed_receiver.recv().await {
+                // Resolve the model of this specific input
+                let (infer, info) = match local
+                    .models
+                    .get(requested_model(request.model.as_deref(), &metadata))
+                {
+                    Ok((infer, info)) => (infer.clone(), info.clone()),
+                    Err(err) => {
+                        let _ = sender.send(Err(err.into()));
+                        continue;
+                    }
+                };
+
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
-                let permit = local.infer.acquire_permit().await;
+                let permit = infer.acquire_permit().await;
 
                 // Required for the async move below
                 let task_local = local.clone();
+                let task_key = key.clone();
 
                 // Create async task for this specific input
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    response = task_local.embed_inner(request, permit) => {
-                        let _ = sender.send(response.map(|(r, _m)| r));
+                    response = task_local.embed_inner(&infer, &info, request, priority, deadline, permit) => {
+                        let _ = sender.send(response.map(|(r, m)| {
+                            if let Some(key) = &task_key {
+                                key.consume_tokens(m.compute_tokens);
+                            }
+                            r
+                        }));
                     }
                     _ = sender.closed() => {}
                     }
@@ -315,6 +740,238 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
             response_receiver,
         )))
     }
+
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn embed_all(
+        &self,
+        request: Request<EmbedAllRequest>,
+    ) -> Result<Response<EmbedAllResponse>, Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_request_count", "method" => "single");
+
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), start_time);
+
+        let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
+        let layer = request.layer.map(|layer| layer as usize);
+        validate_layer(layer, info)?;
+        let input_type: Option<colbert::InputType> =
+            request.input_type.map(|_| request.input_type().into());
+        validate_input_type(input_type, layer)?;
+        let prompt = resolve_prompt(
+            request.prompt_name.as_deref(),
+            request.prompt.as_deref(),
+            info,
+        )?;
+        let inputs = with_prompt(prompt, request.inputs);
+
+        let compute_chars = inputs.chars().count();
+        let response = match input_type {
+            Some(input_type) => {
+                infer
+                    .embed_colbert(inputs, input_type, priority, deadline, permit)
+                    .await
+            }
+            None => {
+                infer
+                    .embed_all(inputs, truncation, layer, priority, deadline, permit)
+                    .await
+            }
+        }
+        .map_err(ErrorResponse::from)?;
+
+        let response_metadata = ResponseMetadata::new(
+            compute_chars,
+            response.prompt_tokens,
+            start_time,
+            response.tokenization,
+            response.queue,
+            response.inference,
+            false,
+        );
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        let message = EmbedAllResponse {
+            token_embeddings: response
+                .results
+                .into_iter()
+                .map(|embeddings| TokenEmbedding { embeddings })
+                .collect(),
+            metadata: Some(grpc::Metadata::from(&response_metadata)),
+        };
+
+        let compute_tokens = response_metadata.compute_tokens;
+        let mut headers = HeaderMap::from(response_metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
+
+        metrics::increment_counter!("te_request_success", "method" => "single");
+
+        tracing::info!("Success");
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            message,
+            Extensions::default(),
+        ))
+    }
+
+    #[instrument(skip_all)]
+    async fn embed_sparse(
+        &self,
+        request: Request<EmbedSparseRequest>,
+    ) -> Result<Response<EmbedSparseResponse>, Status> {
+        metrics::increment_counter!("te_request_count", "method" => "single");
+
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), Instant::now());
+
+        let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+        let (response, metadata) = self
+            .embed_sparse_inner(infer, info, request, priority, deadline, permit)
+            .await?;
+        let compute_tokens = metadata.compute_tokens;
+        let mut headers = HeaderMap::from(metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
+
+        metrics::increment_counter!("te_request_success", "method" => "single");
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            response,
+            Extensions::default(),
+        ))
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn similarity(
+        &self,
+        request: Request<SimilarityRequest>,
+    ) -> Result<Response<SimilarityResponse>, Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), start_time);
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
+        let metric = similarity::SimilarityMetric::from(request.metric());
+        let prompt = resolve_prompt(
+            request.prompt_name.as_deref(),
+            request.prompt.as_deref(),
+            info,
+        )?;
+
+        metrics::increment_counter!("te_request_count", "method" => "batch");
+
+        let batch_size = request.sentences.len() + 1;
+        let max_client_batch_size = self.limits.max_client_batch_size(Endpoint::Similarity);
+        if batch_size > max_client_batch_size {
+            let message = format!(
+                "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+            );
+            tracing::error!("{message}");
+            let err = ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            };
+            metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+            Err(err)?;
+        }
+
+        let sentences = request
+            .sentences
+            .into_iter()
+            .map(|input| with_prompt(prompt, input))
+            .collect();
+        let (scores, response_metadata) = similarity_scores(
+            infer,
+            with_prompt(prompt, request.source_sentence),
+            sentences,
+            truncation,
+            metric,
+            priority,
+            deadline,
+            start_time,
+        )
+        .await?;
+
+        metrics::increment_counter!("te_request_success", "method" => "batch");
+
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        let message = SimilarityResponse {
+            scores,
+            metadata: Some(grpc::Metadata::from(&response_metadata)),
+        };
+
+        let compute_tokens = response_metadata.compute_tokens;
+        let mut headers = HeaderMap::from(response_metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
+
+        tracing::info!("Success");
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            message,
+            Extensions::default(),
+        ))
+    }
 }
 
 #[tonic::async_trait]
@@ -326,14 +983,23 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     ) -> Result<Response<PredictResponse>, Status> {
         metrics::increment_counter!("te_request_count", "method" => "single");
 
-        let permit = self
-            .infer
-            .try_acquire_permit()
-            .map_err(ErrorResponse::from)?;
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), Instant::now());
 
+        let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
         let request = request.into_inner();
-        let (response, metadata) = self.predict_inner(request, permit).await?;
-        let headers = HeaderMap::from(metadata);
+        let (response, metadata) = self
+            .predict_inner(infer, info, request, priority, deadline, permit)
+            .await?;
+        let compute_tokens = metadata.compute_tokens;
+        let mut headers = HeaderMap::from(metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
 
         metrics::increment_counter!("te_request_success", "method" => "single");
 
@@ -351,6 +1017,11 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         &self,
         request: Request<Streaming<PredictRequest>>,
     ) -> Result<Response<Self::PredictStreamStream>, Status> {
+        // Used for the messages that do not select a model
+        let metadata = request.metadata().clone();
+        let priority = requested_priority(&metadata)?;
+        let deadline = request_deadline(&metadata, Instant::now());
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
         let mut request_stream = request.into_inner();
 
         // Create bounded channel to have an upper bound of spawned tasks
@@ -366,18 +1037,36 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         // Background task that uses the bounded channel
         tokio::spawn(async move {
             while let Some((request, mut sender)) = predict_receiver.recv().await {
+                // Resolve the model of this specific input
+                let (infer, info) = match local
+                    .models
+                    .get(requested_model(request.model.as_deref(), &metadata))
+                {
+                    Ok((infer, info)) => (infer.clone(), info.clone()),
+                    Err(err) => {
+                        let _ = sender.send(Err(err.into()));
+                        continue;
+                    }
+                };
+
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
-                let permit = local.infer.acquire_permit().await;
+                let permit = infer.acquire_permit().await;
 
                 // Required for the async move below
                 let task_local = local.clone();
+                let task_key = key.clone();
 
                 // Create async task for this specific input
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    response = task_local.predict_inner(request, permit) => {
-                        let _ = sender.send(response.map(|(r, _m)| r));
+                    response = task_local.predict_inner(&infer, &info, request, priority, deadline, permit) => {
+                        let _ = sender.send(response.map(|(r, m)| {
+                            if let Some(key) = &task_key {
+                                key.consume_tokens(m.compute_tokens);
+                            }
+                            r
+                        }));
                     }
                     _ = sender.closed() => {}
                     }
@@ -433,6 +1122,85 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
     }
 }
 
+#[tonic::async_trait]
+impl grpc::token_classify_server::TokenClassify for TextEmbeddingsService {
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn token_classify(
+        &self,
+        request: Request<TokenClassifyRequest>,
+    ) -> Result<Response<TokenClassifyResponse>, Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+        metrics::increment_counter!("te_request_count", "method" => "single");
+
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), start_time);
+
+        let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+        let strategy = request.aggregation_strategy().into();
+
+        let compute_chars = request.inputs.chars().count();
+        let (entities, response) = token_classify_entities(
+            infer,
+            info,
+            request.inputs,
+            strategy,
+            priority,
+            deadline,
+            permit,
+        )
+        .await?;
+
+        let response_metadata = ResponseMetadata::new(
+            compute_chars,
+            response.prompt_tokens,
+            start_time,
+            response.tokenization,
+            response.queue,
+            response.inference,
+            response.cached,
+        );
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        let message = TokenClassifyResponse {
+            entities: entities.into_iter().map(grpc::Entity::from).collect(),
+            metadata: Some(grpc::Metadata::from(&response_metadata)),
+        };
+
+        let compute_tokens = response_metadata.compute_tokens;
+        let mut headers = HeaderMap::from(response_metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
+
+        metrics::increment_counter!("te_request_success", "method" => "single");
+
+        tracing::info!("Success");
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            message,
+            Extensions::default(),
+        ))
+    }
+}
+
 #[tonic::async_trait]
 impl grpc::rerank_server::Rerank for TextEmbeddingsService {
     #[instrument(
@@ -453,55 +1221,73 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let span = Span::current();
         let start_time = Instant::now();
 
+        let (infer, info) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+        let priority = requested_priority(request.metadata())?;
+        let deadline = request_deadline(request.metadata(), start_time);
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
         let request = request.into_inner();
 
-        match &self.info.model_type {
-            ModelType::Classifier(_) => {
+        // Embedding models rank the texts by the cosine similarity of their embedding with the
+        // query embedding
+        let bi_encoder = match &info.model_type {
+            ModelType::Classifier(_) | ModelType::TokenClassifier(_) => {
                 metrics::increment_counter!("te_request_failure", "err" => "model_type");
                 let message = "model is not a re-ranker model".to_string();
                 tracing::error!("{message}");
                 Err(Status::new(Code::FailedPrecondition, message))
             }
-            ModelType::Reranker(_) => Ok(()),
-            ModelType::Embedding(_) => {
-                metrics::increment_counter!("te_request_failure", "err" => "model_type");
-                let message = "model is not a classifier model".to_string();
-                tracing::error!("{message}");
-                Err(Status::new(Code::FailedPrecondition, message))
-            }
+            ModelType::Reranker(_) => Ok(false),
+            ModelType::Embedding(_) => Ok(true),
         }?;
 
         // Closure for rerank
         let rerank_inner = move |query: String,
                                  text: String,
-                                 truncate: bool,
+                                 truncation: Truncation,
                                  raw_scores: bool,
                                  infer: Infer| async move {
             let permit = infer.acquire_permit().await;
 
+            let function_to_apply = if raw_scores {
+                FunctionToApply::None
+            } else {
+                FunctionToApply::Sigmoid
+            };
             let response = infer
-                .predict((query, text), truncate, raw_scores, permit)
+                .predict(
+                    (query, text),
+                    truncation,
+                    function_to_apply,
+                    priority,
+                    deadline,
+                    permit,
+                )
                 .await
                 .map_err(ErrorResponse::from)?;
 
             let score = response.results[0];
 
-            Ok::<(usize, Duration, Duration, Duration, f32), ErrorResponse>((
+            Ok::<(usize, Duration, Duration, Duration, f32, bool), ErrorResponse>((
                 response.prompt_tokens,
                 response.tokenization,
                 response.queue,
                 response.inference,
                 score,
+                response.cached,
             ))
         };
 
         metrics::increment_counter!("te_request_count", "method" => "batch");
 
         let batch_size = request.texts.len();
-        if batch_size > self.info.max_client_batch_size {
+        let max_client_batch_size = self.limits.max_client_batch_size(Endpoint::Rerank);
+        if batch_size > max_client_batch_size {
             let message = format!(
-                "batch size {batch_size} > maximum allowed batch size {}",
-                self.info.max_client_batch_size
+                "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
             );
             tracing::error!("{message}");
             let err = ErrorResponse {
@@ -512,66 +1298,100 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             Err(err)?;
         }
 
-        let mut futures = Vec::with_capacity(batch_size);
-        let query_chars = request.query.chars().count();
-        let mut total_compute_chars = query_chars * batch_size;
+        let truncation = truncation(
+            request.truncate,
+            request.truncation_direction(),
+            request.truncation_strategy(),
+            request.max_tokens,
+        );
 
-        for text in &request.texts {
-            total_compute_chars += text.chars().count();
-            let local_infer = self.infer.clone();
-            futures.push(rerank_inner(
+        let (scores, response_metadata) = if bi_encoder {
+            similarity_scores(
+                infer,
                 request.query.clone(),
-                text.clone(),
-                request.truncate,
-                request.raw_scores,
-                local_infer,
-            ))
-        }
-        let results = join_all(futures)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32)>, ErrorResponse>>()?;
-
-        let mut ranks = Vec::with_capacity(batch_size);
-        let mut total_tokenization_time = 0;
-        let mut total_queue_time = 0;
-        let mut total_inference_time = 0;
-        let mut total_compute_tokens = 0;
-
-        for (index, r) in results.into_iter().enumerate() {
-            total_compute_tokens += r.0;
-            total_tokenization_time += r.1.as_nanos() as u64;
-            total_queue_time += r.2.as_nanos() as u64;
-            total_inference_time += r.3.as_nanos() as u64;
-            let text = if request.return_text {
-                Some(request.texts[index].clone())
-            } else {
-                None
-            };
+                request.texts.clone(),
+                truncation,
+                similarity::SimilarityMetric::Cosine,
+                priority,
+                deadline,
+                start_time,
+            )
+            .await?
+        } else {
+            let mut futures = Vec::with_capacity(batch_size);
+            let query_chars = request.query.chars().count();
+            let mut total_compute_chars = query_chars * batch_size;
+
+            for text in &request.texts {
+                total_compute_chars += text.chars().count();
+                let local_infer = infer.clone();
+                futures.push(rerank_inner(
+                    request.query.clone(),
+                    text.clone(),
+                    truncation,
+                    request.raw_scores,
+                    local_infer,
+                ))
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32, bool)>, ErrorResponse>>()?;
+
+            let mut scores = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
+
+            for r in results {
+                total_compute_tokens += r.0;
+                cache_hit &= r.5;
+                total_tokenization_time += r.1.as_nanos() as u64;
+                total_queue_time += r.2.as_nanos() as u64;
+                total_inference_time += r.3.as_nanos() as u64;
+                scores.push(r.4);
+            }
+            let batch_size = batch_size as u64;
+
+            (
+                scores,
+                ResponseMetadata::new(
+                    total_compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
+                ),
+            )
+        };
 
-            ranks.push(Rank {
-                index: index as u32,
-                text,
-                score: r.4,
+        let mut ranks: Vec<Rank> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(index, score)| {
+                let text = if request.return_text {
+                    Some(request.texts[index].clone())
+                } else {
+                    None
+                };
+                Rank {
+                    index: index as u32,
+                    text,
+                    score,
+                }
             })
-        }
+            .collect();
 
         // Reverse sort
         ranks.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
         ranks.reverse();
 
-        let batch_size = batch_size as u64;
-
         metrics::increment_counter!("te_request_success", "method" => "batch");
 
-        let response_metadata = ResponseMetadata::new(
-            total_compute_chars,
-            total_compute_tokens,
-            start_time,
-            Duration::from_nanos(total_tokenization_time / batch_size),
-            Duration::from_nanos(total_queue_time / batch_size),
-            Duration::from_nanos(total_inference_time / batch_size),
-        );
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
 
@@ -580,7 +1400,9 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             metadata: Some(grpc::Metadata::from(&response_metadata)),
         };
 
-        let headers = HeaderMap::from(response_metadata);
+        let compute_tokens = response_metadata.compute_tokens;
+        let mut headers = HeaderMap::from(response_metadata);
+        charge_api_key(key.as_deref(), compute_tokens, &mut headers);
 
         tracing::info!("Success");
 
@@ -609,68 +1431,147 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let span = Span::current();
         let start_time = Instant::now();
 
-        // Check model type
-        match &self.info.model_type {
-            ModelType::Classifier(_) => {
+        // Used if the first message does not select a model
+        let metadata = request.metadata().clone();
+        let priority = requested_priority(&metadata)?;
+        let deadline = request_deadline(&metadata, start_time);
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let mut request_stream = request.into_inner();
+
+        // The model is selected by the first message
+        let first_request = request_stream.next().await;
+        let model = match &first_request {
+            Some(Ok(request)) => request.model.as_deref(),
+            _ => None,
+        };
+        let (infer, info) = self.models.get(requested_model(model, &metadata))?;
+
+        // Check model type. Embedding models rank the texts by the cosine similarity of their
+        // embedding with the query embedding
+        let bi_encoder = match &info.model_type {
+            ModelType::Classifier(_) | ModelType::TokenClassifier(_) => {
                 metrics::increment_counter!("te_request_failure", "err" => "model_type");
                 let message = "model is not a re-ranker model".to_string();
                 tracing::error!("{message}");
                 Err(Status::new(Code::FailedPrecondition, message))
             }
-            ModelType::Reranker(_) => Ok(()),
-            ModelType::Embedding(_) => {
-                metrics::increment_counter!("te_request_failure", "err" => "model_type");
-                let message = "model is not a classifier model".to_string();
-                tracing::error!("{message}");
-                Err(Status::new(Code::FailedPrecondition, message))
-            }
+            ModelType::Reranker(_) => Ok(false),
+            ModelType::Embedding(_) => Ok(true),
         }?;
 
         // Closure for rerank
         let rerank_inner = move |index: usize,
                                  query: String,
                                  text: String,
-                                 truncate: bool,
+                                 truncation: Truncation,
                                  raw_scores: bool,
                                  infer: Infer,
                                  permit: OwnedSemaphorePermit| async move {
-            let response = infer
-                .predict((query, text.clone()), truncate, raw_scores, permit)
-                .await
-                .map_err(ErrorResponse::from)?;
-
-            let score = response.results[0];
+            let (score, prompt_tokens, tokenization, queue, inference, cached) = if bi_encoder {
+                // `similarity_scores` acquires a permit for each of its inputs
+                drop(permit);
+                let (scores, metadata) = similarity_scores(
+                    &infer,
+                    query,
+                    vec![text.clone()],
+                    truncation,
+                    similarity::SimilarityMetric::Cosine,
+                    priority,
+                    deadline,
+                    Instant::now(),
+                )
+                .await?;
+                (
+                    scores[0],
+                    metadata.compute_tokens,
+                    metadata.tokenization_time,
+                    metadata.queue_time,
+                    metadata.inference_time,
+                    metadata.cache_hit,
+                )
+            } else {
+                let function_to_apply = if raw_scores {
+                    FunctionToApply::None
+                } else {
+                    FunctionToApply::Sigmoid
+                };
+                let response = infer
+                    .predict(
+                        (query, text.clone()),
+                        truncation,
+                        function_to_apply,
+                        priority,
+                        deadline,
+                        permit,
+                    )
+                    .await
+                    .map_err(ErrorResponse::from)?;
+                (
+                    response.results[0],
+                    response.prompt_tokens,
+                    response.tokenization,
+                    response.queue,
+                    response.inference,
+                    response.cached,
+                )
+            };
 
-            Ok::<(usize, usize, Duration, Duration, Duration, f32, String), ErrorResponse>((
+            Ok::<
+                (
+                    usize,
+                    usize,
+                    Duration,
+                    Duration,
+                    Duration,
+                    f32,
+                    String,
+                    bool,
+                ),
+                ErrorResponse,
+            >((
                 index,
-                response.prompt_tokens,
-                response.tokenization,
-                response.queue,
-                response.inference,
+                prompt_tokens,
+                tokenization,
+                queue,
+                inference,
                 score,
                 text,
+                cached,
             ))
         };
 
         metrics::increment_counter!("te_request_count", "method" => "batch");
 
-        let mut request_stream = request.into_inner();
+        // Put the first message back in front of the stream
+        let mut request_stream = tokio_stream::iter(first_request).chain(request_stream);
 
         // Create bounded channel to have an upper bound of spawned tasks
         // We will have at most `max_parallel_stream_requests` messages from this stream in the queue
         let (rerank_sender, mut rerank_receiver) = mpsc::channel::<(
-            (usize, String, String, bool, bool),
+            (usize, String, String, Truncation, bool),
             oneshot::Sender<
-                Result<(usize, usize, Duration, Duration, Duration, f32, String), ErrorResponse>,
+                Result<
+                    (
+                        usize,
+                        usize,
+                        Duration,
+                        Duration,
+                        Duration,
+                        f32,
+                        String,
+                        bool,
+                    ),
+                    ErrorResponse,
+                >,
             >,
         )>(self.max_parallel_stream_requests);
 
         // Required for the async move below
-        let local_infer = self.infer.clone();
+        let local_infer = infer.clone();
 
         // Background task that uses the bounded channel
         tokio::spawn(async move {
-            while let Some(((index, query, text, truncate, raw_scores), mut sender)) =
+            while let Some(((index, query, text, truncation, raw_scores), mut sender)) =
                 rerank_receiver.recv().await
             {
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
@@ -683,7 +1584,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    result = rerank_inner(index, query, text, truncate, raw_scores, task_infer, permit) => {
+                    result = rerank_inner(index, query, text, truncation, raw_scores, task_infer, permit) => {
                         let _ = sender.send(result);
                     }
                     _ = sender.closed() => {}
@@ -722,13 +1623,20 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             total_compute_chars += request.query.chars().count();
             total_compute_chars += request.text.chars().count();
 
+            let truncation = truncation(
+                request.truncate,
+                request.truncation_direction(),
+                request.truncation_strategy(),
+                request.max_tokens,
+            );
+
             rerank_sender
                 .send((
                     (
                         index,
                         request.query,
                         request.text,
-                        request.truncate,
+                        truncation,
                         raw_scores.unwrap(),
                     ),
                     result_sender,
@@ -749,6 +1657,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let mut total_queue_time = 0;
         let mut total_inference_time = 0;
         let mut total_compute_tokens = 0;
+        let mut cache_hit = true;
 
         // Iterate on result stream
         while let Some(result_receiver) = intermediate_receiver.recv().await {
@@ -757,6 +1666,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 .expect("`result_sender` was dropped. This is a bug.")?;
 
             total_compute_tokens += r.1;
+            cache_hit &= r.7;
             total_tokenization_time += r.2.as_nanos() as u64;
             total_queue_time += r.3.as_nanos() as u64;
             total_inference_time += r.4.as_nanos() as u64;
@@ -800,6 +1710,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             Duration::from_nanos(total_tokenization_time / batch_size),
             Duration::from_nanos(total_queue_time / batch_size),
             Duration::from_nanos(total_inference_time / batch_size),
+            cache_hit,
         );
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
@@ -809,7 +1720,8 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             metadata: Some(grpc::Metadata::from(&response_metadata)),
         };
 
-        let headers = HeaderMap::from(response_metadata);
+        let mut headers = HeaderMap::from(response_metadata);
+        charge_api_key(key.as_deref(), total_compute_tokens, &mut headers);
 
         tracing::info!("Success");
 
@@ -821,9 +1733,85 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
     }
 }
 
+#[tonic::async_trait]
+impl grpc::tokenize_server::Tokenize for TextEmbeddingsService {
+    #[instrument(skip_all)]
+    async fn tokenize(
+        &self,
+        request: Request<EncodeRequest>,
+    ) -> Result<Response<EncodeResponse>, Status> {
+        let (infer, _) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+        let encoding = infer
+            .tokenize(request.inputs, request.add_special_tokens)
+            .await
+            .map_err(ErrorResponse::from)?;
+
+        let tokens = encoding
+            .get_ids()
+            .iter()
+            .zip(encoding.get_tokens())
+            .zip(encoding.get_offsets())
+            .zip(encoding.get_special_tokens_mask())
+            .map(|(((&id, token), &(start, stop)), &special)| {
+                let special = special == 1;
+                SimpleToken {
+                    id,
+                    token: token.clone(),
+                    special,
+                    start: (!special).then_some(start as u32),
+                    stop: (!special).then_some(stop as u32),
+                }
+            })
+            .collect();
+
+        let mut headers = HeaderMap::new();
+        charge_api_key(key.as_deref(), 0, &mut headers);
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            EncodeResponse { tokens },
+            Extensions::default(),
+        ))
+    }
+
+    #[instrument(skip_all)]
+    async fn decode(
+        &self,
+        request: Request<DecodeRequest>,
+    ) -> Result<Response<DecodeResponse>, Status> {
+        let (infer, _) = self.models.get(requested_model(
+            request.get_ref().model.as_deref(),
+            request.metadata(),
+        ))?;
+
+        let key = request.extensions().get::<Arc<KeyLimiter>>().cloned();
+        let request = request.into_inner();
+        let text = infer
+            .decode(request.ids, request.skip_special_tokens)
+            .await
+            .map_err(ErrorResponse::from)?;
+
+        let mut headers = HeaderMap::new();
+        charge_api_key(key.as_deref(), 0, &mut headers);
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            DecodeResponse { text },
+            Extensions::default(),
+        ))
+    }
+}
+
 pub async fn run(
-    infer: Infer,
-    info: Info,
+    models: Models,
+    limits: Arc<RuntimeLimits>,
+    api_keys: Option<ApiKeys>,
     addr: SocketAddr,
     prom_builder: PrometheusBuilder,
 ) -> Result<(), anyhow::Error> {
@@ -836,6 +1824,10 @@ pub async fn run(
     health_reporter
         .set_serving::<grpc::InfoServer<TextEmbeddingsService>>()
         .await;
+    // Tokenization does not depend on the backend health
+    health_reporter
+        .set_serving::<grpc::TokenizeServer<TextEmbeddingsService>>()
+        .await;
     // Set all other services to not serving
     // Their health will be updated in the task below
     health_reporter
@@ -847,67 +1839,90 @@ pub async fn run(
     health_reporter
         .set_not_serving::<grpc::PredictServer<TextEmbeddingsService>>()
         .await;
+    health_reporter
+        .set_not_serving::<grpc::TokenClassifyServer<TextEmbeddingsService>>()
+        .await;
 
-    // Backend health watcher
-    let mut health_watcher = infer.health_watcher();
+    for (infer, info) in models.iter() {
+        // Backend health watcher
+        let mut health_watcher = infer.health_watcher();
 
-    // Clone model_type and move it to the task
-    let health_watcher_model_type = info.model_type.clone();
+        // Clone model_type and move it to the task
+        let health_watcher_model_type = info.model_type.clone();
 
-    // Update services health
-    tokio::spawn(async move {
-        while health_watcher.changed().await.is_ok() {
-            let health = *health_watcher.borrow_and_update();
-            let status = match health {
-                true => ServingStatus::Serving,
-                false => ServingStatus::NotServing,
-            };
+        // Required for the async move below
+        let mut health_reporter = health_reporter.clone();
 
-            // Match on model type and set the health of the correct service(s)
-            //
-            // If Reranker, we have both a predict and rerank service
-            //
-            // This logic hints back to the user that if they try using the wrong service
-            // given the model type, it will always return an error.
-            //
-            // For example if the model type is `Embedding`, sending requests to `Rerank` will
-            // always return an `UNIMPLEMENTED` Status and both the `Rerank` and `Predict` services
-            // will have a `NOT_SERVING` ServingStatus.
-            match health_watcher_model_type {
-                ModelType::Classifier(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Embedding(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Reranker(_) => {
-                    // Reranker has both a predict and rerank service
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
-                    health_reporter
-                        .set_service_status(
-                            <grpc::RerankServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
-                }
-            };
-        }
-    });
+        // Update services health
+        tokio::spawn(async move {
+            while health_watcher.changed().await.is_ok() {
+                let health = *health_watcher.borrow_and_update();
+                let status = match health {
+                    true => ServingStatus::Serving,
+                    false => ServingStatus::NotServing,
+                };
+
+                // Match on model type and set the health of the correct service(s)
+                //
+                // If Reranker, we have both a predict and rerank service
+                //
+                // This logic hints back to the user that if they try using the wrong service
+                // given the model type, it will always return an error.
+                //
+                // For example if the model type is `Embedding`, sending requests to `Predict` will
+                // always return an error and the `Predict` service will have a `NOT_SERVING`
+                // ServingStatus. Embedding models also serve `Rerank` as bi-encoders.
+                match health_watcher_model_type {
+                    ModelType::Classifier(_) => {
+                        health_reporter
+                            .set_service_status(
+                                <grpc::PredictServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await
+                    }
+                    ModelType::Embedding(_) => {
+                        // Embedding models rerank as bi-encoders
+                        health_reporter
+                            .set_service_status(
+                                <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await;
+                        health_reporter
+                            .set_service_status(
+                                <grpc::RerankServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await;
+                    }
+                    ModelType::TokenClassifier(_) => {
+                        health_reporter
+                            .set_service_status(
+                                <grpc::TokenClassifyServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await
+                    }
+                    ModelType::Reranker(_) => {
+                        // Reranker has both a predict and rerank service
+                        health_reporter
+                            .set_service_status(
+                                <grpc::PredictServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await;
+                        health_reporter
+                            .set_service_status(
+                                <grpc::RerankServer<TextEmbeddingsService>>::NAME,
+                                status,
+                            )
+                            .await;
+                    }
+                };
+            }
+        });
+    }
 
     // gRPC reflection
     let file_descriptor_set: &[u8] = tonic::include_file_descriptor_set!("descriptor");
@@ -916,7 +1931,9 @@ pub async fn run(
         .build()?;
 
     // Main service
-    let service = TextEmbeddingsService::new(infer, info);
+    let service = TextEmbeddingsService::new(models, limits);
+    // Inference services require an API key if keys are configured
+    let auth = AuthInterceptor { api_keys };
 
     // Create gRPC server
     tracing::info!("Starting gRPC server: {}", &addr);
@@ -924,9 +1941,23 @@ pub async fn run(
         .add_service(health_service)
         .add_service(reflection_service)
         .add_service(grpc::InfoServer::new(service.clone()))
-        .add_service(grpc::EmbedServer::new(service.clone()))
-        .add_service(grpc::PredictServer::new(service.clone()))
-        .add_service(grpc::RerankServer::new(service))
+        .add_service(grpc::EmbedServer::with_interceptor(
+            service.clone(),
+            auth.clone(),
+        ))
+        .add_service(grpc::PredictServer::with_interceptor(
+            service.clone(),
+            auth.clone(),
+        ))
+        .add_service(grpc::RerankServer::with_interceptor(
+            service.clone(),
+            auth.clone(),
+        ))
+        .add_service(grpc::TokenClassifyServer::with_interceptor(
+            service.clone(),
+            auth.clone(),
+        ))
+        .add_service(grpc::TokenizeServer::with_interceptor(service, auth))
         .serve_with_shutdown(addr, shutdown::shutdown_signal())
         .await?;
 
@@ -940,7 +1971,11 @@ impl From<ErrorResponse> for Status {
             ErrorType::Backend => Code::FailedPrecondition,
             ErrorType::Overloaded => Code::ResourceExhausted,
             ErrorType::Validation => Code::InvalidArgument,
+            ErrorType::InvalidInput => Code::InvalidArgument,
             ErrorType::Tokenizer => Code::FailedPrecondition,
+            ErrorType::ModelNotFound => Code::NotFound,
+            ErrorType::Unauthorized => Code::Unauthenticated,
+            ErrorType::DeadlineExceeded => Code::DeadlineExceeded,
         };
 
         Status::new(code, value.error)
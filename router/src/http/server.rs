@@ -1,28 +1,48 @@
+use crate::auth::ApiKeys;
+use crate::config::{Endpoint, RuntimeLimits};
 /// HTTP Server logic
 use crate::http::types::{
-    EmbedRequest, EmbedResponse, Input, OpenAICompatEmbedding, OpenAICompatErrorResponse,
-    OpenAICompatRequest, OpenAICompatResponse, OpenAICompatUsage, PredictInput, PredictRequest,
-    PredictResponse, Prediction, Rank, RerankRequest, RerankResponse, Sequence,
+    embedding_bytes, truncation, AggregationStrategy, ChunkAggregation, ChunkEmbedding,
+    CohereApiVersion, CohereBilledUnits, CohereDocument, CohereErrorResponse, CohereMeta,
+    CohereRerankRequest, CohereRerankResponse, CohereRerankResult, DecodeRequest, DecodeResponse,
+    DocumentEmbedding, EmbedAllRequest, EmbedAllResponse, EmbedChunksRequest, EmbedChunksResponse,
+    EmbedInput, EmbedRequest, EmbedResponse, EmbedSparseRequest, EmbedSparseResponse, Embedding,
+    EncodingFormat, Entity, FunctionToApply, Input, InputIds, InputType, InputValue,
+    OpenAICompatEmbedding, OpenAICompatErrorResponse, OpenAICompatRequest, OpenAICompatResponse,
+    OpenAICompatUsage, Precision, PredictInput, PredictRequest, PredictResponse, Prediction,
+    Priority, Rank, RerankRequest, RerankResponse, Sequence, SimilarityInput, SimilarityMetric,
+    SimilarityRequest, SimilarityResponse, SimpleToken, SparseValue, TokenClassifyRequest,
+    TokenClassifyResponse, TokenizeRequest, TokenizeResponse, TruncationDirection,
+    TruncationStrategy,
 };
 use crate::{
-    shutdown, ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, Info, ModelType,
-    ResponseMetadata,
+    function_to_apply, parse_priority, resolve_prompt, shutdown, similarity_scores,
+    token_classify_entities, validate_dimensions, validate_input_type, validate_layer, with_prompt,
+    ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, Info, MaxInputLengthSource,
+    ModelType, Models, ProblemType, ResponseMetadata, MODEL_HEADER, PRIORITY_HEADER,
 };
 use anyhow::Context;
-use axum::extract::Extension;
+use axum::body::Body;
+use axum::extract::{Extension, State};
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
 use axum::http::HeaderValue;
-use axum::http::{HeaderMap, Method, StatusCode};
+use axum::http::{HeaderMap, Method, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{http, Json, Router};
 use axum_tracing_opentelemetry::middleware::OtelAxumLayer;
 use futures::future::join_all;
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
-use std::env;
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use text_embeddings_backend::BackendError;
-use text_embeddings_core::infer::{Infer, InferResponse};
+use text_embeddings_core::infer::{Infer, InferAllResponse, InferResponse};
+use text_embeddings_core::tokenization::Truncation;
 use text_embeddings_core::TextEmbeddingsError;
+use text_embeddings_core::{chunking, classification, queue, tokenization};
 use tokio::sync::OwnedSemaphorePermit;
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::instrument;
@@ -34,11 +54,20 @@ use utoipa_swagger_ui::SwaggerUi;
 get,
 tag = "Text Embeddings Inference",
 path = "/info",
-responses((status = 200, description = "Served model info", body = Info))
+responses(
+(status = 200, description = "Served model info", body = Info),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+)
 )]
-#[instrument]
-async fn get_model_info(info: Extension<Info>) -> Json<Info> {
-    Json(info.0)
+#[instrument(skip_all)]
+async fn get_model_info(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+) -> Result<Json<Info>, (StatusCode, Json<ErrorResponse>)> {
+    let (_, info) = models.get(requested_model(None, &headers))?;
+    Ok(Json(limits.info(info)))
 }
 
 #[utoipa::path(
@@ -51,10 +80,11 @@ responses(
 example = json ! ({"error": "unhealthy", "error_type": "unhealthy"})),
 )
 )]
-#[instrument(skip(infer))]
-/// Health check method
-async fn health(infer: Extension<Infer>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
-    match infer.health().await {
+#[instrument(skip(models))]
+/// Health check method. Healthy only if all served models are healthy
+async fn health(models: Extension<Models>) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let health = join_all(models.iter().map(|(infer, _)| infer.health())).await;
+    match health.into_iter().all(|healthy| healthy) {
         true => Ok(()),
         false => Err(ErrorResponse {
             error: "unhealthy".to_string(),
@@ -79,6 +109,12 @@ example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"}))
 example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
 )
 )]
 #[instrument(
@@ -86,17 +122,38 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn predict(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
     Json(req): Json<PredictRequest>,
 ) -> Result<(HeaderMap, Json<PredictResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
 
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+    let function_to_apply = function_to_apply(
+        req.function_to_apply.map(|function| function.into()),
+        req.raw_scores,
+        info,
+    );
+    let top_k = req.top_k;
+
     // Closure for predict
     let predict_inner = move |inputs: Sequence,
-                              truncate: bool,
-                              raw_scores: bool,
+                              truncation: Truncation,
+                              function_to_apply: classification::FunctionToApply,
+                              priority: queue::Priority,
+                              deadline: Option<Instant>,
                               infer: Infer,
                               info: Info,
                               permit: Option<OwnedSemaphorePermit>| async move {
@@ -106,7 +163,14 @@ async fn predict(
         };
 
         let response = infer
-            .predict(inputs, truncate, raw_scores, permit)
+            .predict(
+                inputs,
+                truncation,
+                function_to_apply,
+                priority,
+                deadline,
+                permit,
+            )
             .await
             .map_err(ErrorResponse::from)?;
 
@@ -131,13 +195,17 @@ async fn predict(
         // Reverse sort
         predictions.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
         predictions.reverse();
+        if let Some(top_k) = top_k {
+            predictions.truncate(top_k);
+        }
 
-        Ok::<(usize, Duration, Duration, Duration, Vec<Prediction>), ErrorResponse>((
+        Ok::<(usize, Duration, Duration, Duration, Vec<Prediction>, bool), ErrorResponse>((
             response.prompt_tokens,
             response.tokenization,
             response.queue,
             response.inference,
             predictions,
+            response.cached,
         ))
     };
 
@@ -147,15 +215,18 @@ async fn predict(
 
             let compute_chars = inputs.count_chars();
             let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
-            let (prompt_tokens, tokenization, queue, inference, predictions) = predict_inner(
-                inputs,
-                req.truncate,
-                req.raw_scores,
-                infer.0,
-                info.0,
-                Some(permit),
-            )
-            .await?;
+            let (prompt_tokens, tokenization, queue, inference, predictions, cache_hit) =
+                predict_inner(
+                    inputs,
+                    truncation,
+                    function_to_apply,
+                    priority,
+                    deadline,
+                    infer.clone(),
+                    info.clone(),
+                    Some(permit),
+                )
+                .await?;
 
             metrics::increment_counter!("te_request_success", "method" => "single");
 
@@ -168,6 +239,7 @@ async fn predict(
                     tokenization,
                     queue,
                     inference,
+                    cache_hit,
                 ),
             )
         }
@@ -175,10 +247,10 @@ async fn predict(
             metrics::increment_counter!("te_request_count", "method" => "batch");
 
             let batch_size = inputs.len();
-            if batch_size > info.max_client_batch_size {
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::Predict);
+            if batch_size > max_client_batch_size {
                 let message = format!(
-                    "batch size {batch_size} > maximum allowed batch size {}",
-                    info.max_client_batch_size
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
                 );
                 tracing::error!("{message}");
                 let err = ErrorResponse {
@@ -198,15 +270,17 @@ async fn predict(
                 let local_info = info.clone();
                 futures.push(predict_inner(
                     input,
-                    req.truncate,
-                    req.raw_scores,
-                    local_infer.0,
-                    local_info.0,
+                    truncation,
+                    function_to_apply,
+                    priority,
+                    deadline,
+                    local_infer,
+                    local_info,
                     None,
                 ))
             }
             let results = join_all(futures).await.into_iter().collect::<Result<
-                Vec<(usize, Duration, Duration, Duration, Vec<Prediction>)>,
+                Vec<(usize, Duration, Duration, Duration, Vec<Prediction>, bool)>,
                 ErrorResponse,
             >>()?;
 
@@ -215,9 +289,11 @@ async fn predict(
             let mut total_queue_time = 0;
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
 
             for r in results {
                 total_compute_tokens += r.0;
+                cache_hit &= r.5;
                 total_tokenization_time += r.1.as_nanos() as u64;
                 total_queue_time += r.2.as_nanos() as u64;
                 total_inference_time += r.3.as_nanos() as u64;
@@ -236,6 +312,159 @@ async fn predict(
                     Duration::from_nanos(total_tokenization_time / batch_size),
                     Duration::from_nanos(total_queue_time / batch_size),
                     Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
+                ),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(response)))
+}
+
+/// Get Entities. Returns a 424 status code if the model is not a Token Classification model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/token_classify",
+request_body = TokenClassifyRequest,
+responses(
+(status = 200, description = "Entities", body = TokenClassifyResponse),
+(status = 424, description = "Token Classification Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn token_classify(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<TokenClassifyRequest>,
+) -> Result<(HeaderMap, Json<TokenClassifyResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let strategy = req.aggregation_strategy.into();
+
+    let (entities, metadata) = match req.inputs {
+        Input::Single(input) => {
+            metrics::increment_counter!("te_request_count", "method" => "single");
+
+            let compute_chars = input.chars().count();
+
+            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let (entities, response) =
+                token_classify_entities(infer, info, input, strategy, priority, deadline, permit)
+                    .await?;
+
+            metrics::increment_counter!("te_request_success", "method" => "single");
+
+            (
+                vec![entities],
+                ResponseMetadata::new(
+                    compute_chars,
+                    response.prompt_tokens,
+                    start_time,
+                    response.tokenization,
+                    response.queue,
+                    response.inference,
+                    response.cached,
+                ),
+            )
+        }
+        Input::Batch(inputs) => {
+            metrics::increment_counter!("te_request_count", "method" => "batch");
+
+            let batch_size = inputs.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::TokenClassify);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
+
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
+
+            for input in inputs {
+                compute_chars += input.chars().count();
+
+                let local_infer = infer.clone();
+                futures.push(async move {
+                    let permit = local_infer.acquire_permit().await;
+                    token_classify_entities(
+                        &local_infer,
+                        info,
+                        input,
+                        strategy,
+                        priority,
+                        deadline,
+                        permit,
+                    )
+                    .await
+                })
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, ErrorResponse>>()?;
+
+            let mut entities = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
+
+            for (e, r) in results {
+                cache_hit &= r.cached;
+                total_tokenization_time += r.tokenization.as_nanos() as u64;
+                total_queue_time += r.queue.as_nanos() as u64;
+                total_inference_time += r.inference.as_nanos() as u64;
+                total_compute_tokens += r.prompt_tokens;
+                entities.push(e);
+            }
+            let batch_size = batch_size as u64;
+
+            metrics::increment_counter!("te_request_success", "method" => "batch");
+
+            (
+                entities,
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
                 ),
             )
         }
@@ -248,11 +477,17 @@ async fn predict(
 
     tracing::info!("Success");
 
+    let response = TokenClassifyResponse(
+        entities
+            .into_iter()
+            .map(|entities| entities.into_iter().map(Entity::from).collect())
+            .collect(),
+    );
     Ok((headers, Json(response)))
 }
 
-/// Get Ranks. Returns a 424 status code if the model is not a Sequence Classification model with
-/// a single class.
+/// Get Ranks. Returns a 424 status code if the model is neither a Sequence Classification model
+/// with a single class nor an embedding model.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
@@ -268,6 +503,12 @@ example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"}))
 example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
 )
 )]
 #[instrument(
@@ -275,140 +516,1065 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn rerank(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
     Json(req): Json<RerankRequest>,
 ) -> Result<(HeaderMap, Json<RerankResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
 
-    match &info.model_type {
-        ModelType::Classifier(_) => {
-            metrics::increment_counter!("te_request_failure", "err" => "model_type");
-            let message = "model is not a re-ranker model".to_string();
-            Err(TextEmbeddingsError::Backend(BackendError::Inference(
-                message,
-            )))
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+
+    let bi_encoder = bi_encoder(&info.model_type)?;
+
+    let (response, metadata) = {
+        metrics::increment_counter!("te_request_count", "method" => "batch");
+
+        let batch_size = req.texts.len();
+        let max_client_batch_size = limits.max_client_batch_size(Endpoint::Rerank);
+        if batch_size > max_client_batch_size {
+            let message = format!(
+                "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+            );
+            tracing::error!("{message}");
+            let err = ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            };
+            metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+            Err(err)?;
         }
-        ModelType::Reranker(_) => Ok(()),
-        ModelType::Embedding(_) => {
+
+        let (scores, metadata) = if bi_encoder {
+            similarity_scores(
+                infer,
+                req.query.clone(),
+                req.texts.clone(),
+                truncation,
+                SimilarityMetric::Cosine.into(),
+                priority,
+                deadline,
+                start_time,
+            )
+            .await?
+        } else {
+            cross_encoder_scores(
+                infer,
+                &req.query,
+                &req.texts,
+                truncation,
+                req.raw_scores,
+                priority,
+                deadline,
+                start_time,
+            )
+            .await?
+        };
+
+        let mut ranks: Vec<Rank> = scores
+            .into_iter()
+            .enumerate()
+            .map(|(index, score)| {
+                let text = if req.return_text {
+                    Some(req.texts[index].clone())
+                } else {
+                    None
+                };
+                Rank { index, text, score }
+            })
+            .collect();
+
+        // Reverse sort
+        ranks.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
+        ranks.reverse();
+
+        metrics::increment_counter!("te_request_success", "method" => "batch");
+
+        (RerankResponse(ranks), metadata)
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(response)))
+}
+
+/// Embedding models rank the texts by the cosine similarity of their embedding with the query
+/// embedding
+fn bi_encoder(model_type: &ModelType) -> Result<bool, ErrorResponse> {
+    match model_type {
+        ModelType::Classifier(_) | ModelType::TokenClassifier(_) => {
             metrics::increment_counter!("te_request_failure", "err" => "model_type");
-            let message = "model is not a classifier model".to_string();
+            let message = "model is not a re-ranker model".to_string();
             Err(TextEmbeddingsError::Backend(BackendError::Inference(
                 message,
             )))
         }
+        ModelType::Reranker(_) => Ok(false),
+        ModelType::Embedding(_) => Ok(true),
     }
     .map_err(|err| {
         tracing::error!("{err}");
         ErrorResponse::from(err)
-    })?;
+    })
+}
 
+/// Score each `(query, text)` pair with a re-ranker model
+#[allow(clippy::too_many_arguments)]
+async fn cross_encoder_scores(
+    infer: &Infer,
+    query: &str,
+    texts: &[String],
+    truncation: Truncation,
+    raw_scores: bool,
+    priority: queue::Priority,
+    deadline: Option<Instant>,
+    start_time: Instant,
+) -> Result<(Vec<f32>, ResponseMetadata), ErrorResponse> {
     // Closure for rerank
     let rerank_inner = move |query: String,
                              text: String,
-                             truncate: bool,
+                             truncation: Truncation,
                              raw_scores: bool,
+                             priority: queue::Priority,
+                             deadline: Option<Instant>,
                              infer: Infer| async move {
         let permit = infer.acquire_permit().await;
 
+        let function_to_apply = if raw_scores {
+            classification::FunctionToApply::None
+        } else {
+            classification::FunctionToApply::Sigmoid
+        };
         let response = infer
-            .predict((query, text), truncate, raw_scores, permit)
+            .predict(
+                (query, text),
+                truncation,
+                function_to_apply,
+                priority,
+                deadline,
+                permit,
+            )
             .await
             .map_err(ErrorResponse::from)?;
 
         let score = response.results[0];
 
-        Ok::<(usize, Duration, Duration, Duration, f32), ErrorResponse>((
+        Ok::<(usize, Duration, Duration, Duration, f32, bool), ErrorResponse>((
             response.prompt_tokens,
             response.tokenization,
             response.queue,
             response.inference,
             score,
+            response.cached,
         ))
     };
 
-    let (response, metadata) = {
-        metrics::increment_counter!("te_request_count", "method" => "batch");
+    let batch_size = texts.len();
+    let mut futures = Vec::with_capacity(batch_size);
+    let query_chars = query.chars().count();
+    let mut compute_chars = query_chars * batch_size;
+
+    for text in texts {
+        compute_chars += text.chars().count();
+        let local_infer = infer.clone();
+        futures.push(rerank_inner(
+            query.to_string(),
+            text.clone(),
+            truncation,
+            raw_scores,
+            priority,
+            deadline,
+            local_infer,
+        ))
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32, bool)>, ErrorResponse>>(
+        )?;
+
+    let mut scores = Vec::with_capacity(batch_size);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+    let mut cache_hit = true;
+
+    for r in results {
+        total_compute_tokens += r.0;
+        cache_hit &= r.5;
+        total_tokenization_time += r.1.as_nanos() as u64;
+        total_queue_time += r.2.as_nanos() as u64;
+        total_inference_time += r.3.as_nanos() as u64;
+        scores.push(r.4);
+    }
+    let batch_size = batch_size as u64;
+
+    Ok((
+        scores,
+        ResponseMetadata::new(
+            compute_chars,
+            total_compute_tokens,
+            start_time,
+            Duration::from_nanos(total_tokenization_time / batch_size),
+            Duration::from_nanos(total_queue_time / batch_size),
+            Duration::from_nanos(total_inference_time / batch_size),
+            cache_hit,
+        ),
+    ))
+}
 
-        let batch_size = req.texts.len();
-        if batch_size > info.max_client_batch_size {
-            let message = format!(
-                "batch size {batch_size} > maximum allowed batch size {}",
-                info.max_client_batch_size
-            );
-            tracing::error!("{message}");
-            let err = ErrorResponse {
-                error: message,
-                error_type: ErrorType::Validation,
-            };
-            metrics::increment_counter!("te_request_failure", "err" => "batch_size");
-            Err(err)?;
+/// Split `text` in at most `max_chunks` chunks of `chunk_size` tokens. The special tokens added
+/// by the tokenizer are not counted
+async fn document_chunks(
+    infer: &Infer,
+    text: String,
+    chunk_size: usize,
+    max_chunks: usize,
+) -> Result<Vec<String>, ErrorResponse> {
+    if text.is_empty() {
+        return Ok(vec![text]);
+    }
+
+    let encoding = infer
+        .tokenize(text.clone(), false)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let offsets = encoding.get_offsets();
+    if offsets.len() <= chunk_size {
+        return Ok(vec![text]);
+    }
+
+    let chunks = offsets
+        .chunks(chunk_size)
+        .take(max_chunks)
+        .map(|offsets| {
+            let start = offsets[0].0;
+            let stop = offsets[offsets.len() - 1].1;
+            text.chars().skip(start).take(stop - start).collect()
+        })
+        .collect();
+    Ok(chunks)
+}
+
+/// Cohere compatible rerank route. Documents longer than the model max input length are split in
+/// at most `max_chunks_per_doc` chunks and scored with their best chunk.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/v1/rerank",
+request_body = CohereRerankRequest,
+responses(
+(status = 200, description = "Ranks", body = CohereRerankResponse),
+(status = 424, description = "Rerank Error", body = CohereErrorResponse,
+example = json ! ({"message": "Inference failed"})),
+(status = 429, description = "Model is overloaded", body = CohereErrorResponse,
+example = json ! ({"message": "Model is overloaded"})),
+(status = 422, description = "Tokenization error", body = CohereErrorResponse,
+example = json ! ({"message": "Tokenization error"})),
+(status = 413, description = "Batch size error", body = CohereErrorResponse,
+example = json ! ({"message": "Batch size error"})),
+(status = 404, description = "Model not found", body = CohereErrorResponse,
+example = json ! ({"message": "model `x` is not served by this router"})),
+(status = 401, description = "Missing or invalid API key", body = CohereErrorResponse,
+example = json ! ({"message": "missing or invalid API key"})),
+(status = 504, description = "Request deadline exceeded", body = CohereErrorResponse,
+example = json ! ({"message": "Request deadline exceeded"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn cohere_rerank(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<CohereRerankRequest>,
+) -> Result<(HeaderMap, Json<CohereRerankResponse>), (StatusCode, Json<CohereErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let bi_encoder = bi_encoder(&info.model_type)?;
+
+    metrics::increment_counter!("te_request_count", "method" => "batch");
+
+    let batch_size = req.documents.len();
+    let max_client_batch_size = limits.max_client_batch_size(Endpoint::Rerank);
+    if batch_size > max_client_batch_size {
+        let message =
+            format!("batch size {batch_size} > maximum allowed batch size {max_client_batch_size}");
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+        Err(err)?;
+    }
+
+    if req.max_chunks_per_doc == 0 {
+        let message = "`max_chunks_per_doc` must be greater than 0".to_string();
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        metrics::increment_counter!("te_request_failure", "err" => "validation");
+        Err(err)?;
+    }
+
+    // Objects are ranked on their `rank_fields`, rendered as `field: value` lines when there are
+    // several of them
+    let rank_fields = req.rank_fields.unwrap_or_else(|| vec!["text".to_string()]);
+    let mut texts = Vec::with_capacity(batch_size);
+    for (index, document) in req.documents.iter().enumerate() {
+        let text = match document {
+            CohereDocument::Text(text) => text.clone(),
+            CohereDocument::Object(fields) => {
+                let mut values = Vec::with_capacity(rank_fields.len());
+                for field in &rank_fields {
+                    match fields.get(field) {
+                        Some(value) if rank_fields.len() == 1 => values.push(value.clone()),
+                        Some(value) => values.push(format!("{field}: {value}")),
+                        None => {
+                            let message = format!("document {index} has no `{field}` field");
+                            tracing::error!("{message}");
+                            let err = ErrorResponse {
+                                error: message,
+                                error_type: ErrorType::Validation,
+                            };
+                            metrics::increment_counter!("te_request_failure", "err" => "validation");
+                            Err(err)?;
+                        }
+                    }
+                }
+                values.join("\n")
+            }
+        };
+        texts.push(text);
+    }
+
+    // Re-rankers see the query and the document in the same sequence
+    let chunk_size = if bi_encoder {
+        info.max_input_length
+    } else {
+        let query_tokens = infer
+            .tokenize(req.query.clone(), true)
+            .await
+            .map_err(ErrorResponse::from)?
+            .len();
+        info.max_input_length.saturating_sub(query_tokens + 1)
+    };
+    if chunk_size == 0 {
+        let message = format!(
+            "`query` must have less than {} tokens",
+            info.max_input_length
+        );
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        metrics::increment_counter!("te_request_failure", "err" => "validation");
+        Err(err)?;
+    }
+
+    let chunks = join_all(
+        texts
+            .into_iter()
+            .map(|text| document_chunks(infer, text, chunk_size, req.max_chunks_per_doc)),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<Vec<String>>, ErrorResponse>>()?;
+
+    let mut chunk_documents = Vec::new();
+    let mut chunk_texts = Vec::new();
+    for (index, document_chunks) in chunks.into_iter().enumerate() {
+        for chunk in document_chunks {
+            chunk_documents.push(index);
+            chunk_texts.push(chunk);
+        }
+    }
+
+    // Chunks can still exceed the model max input length by a few special tokens
+    let (scores, metadata) = if bi_encoder {
+        let truncation = Truncation {
+            truncate: true,
+            ..Default::default()
+        };
+        similarity_scores(
+            infer,
+            req.query.clone(),
+            chunk_texts.clone(),
+            truncation,
+            SimilarityMetric::Cosine.into(),
+            priority,
+            deadline,
+            start_time,
+        )
+        .await?
+    } else {
+        let truncation = Truncation {
+            truncate: true,
+            strategy: tokenization::TruncationStrategy::OnlySecond,
+            ..Default::default()
+        };
+        cross_encoder_scores(
+            infer,
+            &req.query,
+            &chunk_texts,
+            truncation,
+            false,
+            priority,
+            deadline,
+            start_time,
+        )
+        .await?
+    };
+
+    // Documents are scored with their best chunk
+    let mut document_scores = vec![f32::NEG_INFINITY; batch_size];
+    for (index, score) in chunk_documents.into_iter().zip(scores) {
+        document_scores[index] = document_scores[index].max(score);
+    }
+
+    let mut results: Vec<CohereRerankResult> = document_scores
+        .into_iter()
+        .enumerate()
+        .map(|(index, relevance_score)| CohereRerankResult {
+            index,
+            relevance_score,
+            document: None,
+        })
+        .collect();
+
+    // Reverse sort
+    results.sort_by(|x, y| x.relevance_score.partial_cmp(&y.relevance_score).unwrap());
+    results.reverse();
+    if let Some(top_n) = req.top_n {
+        results.truncate(top_n);
+    }
+
+    if req.return_documents {
+        let mut documents: Vec<Option<CohereDocument>> =
+            req.documents.into_iter().map(Some).collect();
+        for result in results.iter_mut() {
+            result.document = documents[result.index]
+                .take()
+                .map(|document| match document {
+                    CohereDocument::Text(text) => BTreeMap::from([("text".to_string(), text)]),
+                    CohereDocument::Object(fields) => fields,
+                });
+        }
+    }
+
+    metrics::increment_counter!("te_request_success", "method" => "batch");
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let compute_tokens = metadata.compute_tokens;
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    // A search unit covers a query with up to 100 documents, chunks included
+    let response = CohereRerankResponse {
+        results,
+        meta: CohereMeta {
+            api_version: CohereApiVersion { version: "1" },
+            billed_units: CohereBilledUnits {
+                search_units: chunk_texts.len().div_ceil(100),
+                input_tokens: compute_tokens,
+            },
+        },
+    };
+    Ok((headers, Json(response)))
+}
+
+/// Get Embeddings. Returns a 424 status code if the model is not an embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed",
+request_body = EmbedRequest,
+responses(
+(status = 200, description = "Embeddings. The raw little-endian values are returned instead when the request \
+accepts `application/octet-stream`", body = EmbedResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<EmbedRequest>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    let octet_stream = accepts_octet_stream(&headers);
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+    let prompt = resolve_prompt(req.prompt_name.as_deref(), req.prompt.as_deref(), info)?;
+    validate_dimensions(req.dimensions, info)?;
+
+    let (embeddings, metadata) = match req.inputs {
+        EmbedInput::Single(input) => {
+            metrics::increment_counter!("te_request_count", "method" => "single");
+
+            let input = input.with_prompt(prompt);
+            let compute_chars = input.count_chars();
+
+            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let response = infer
+                .embed(
+                    input,
+                    truncation,
+                    req.normalize,
+                    req.dimensions,
+                    req.precision.into(),
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+                .map_err(ErrorResponse::from)?;
+
+            metrics::increment_counter!("te_request_success", "method" => "single");
+
+            (
+                vec![response.results],
+                ResponseMetadata::new(
+                    compute_chars,
+                    response.prompt_tokens,
+                    start_time,
+                    response.tokenization,
+                    response.queue,
+                    response.inference,
+                    response.cached,
+                ),
+            )
+        }
+        EmbedInput::Batch(inputs) => {
+            metrics::increment_counter!("te_request_count", "method" => "batch");
+
+            let batch_size = inputs.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::Embed);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
+
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
+
+            for input in inputs {
+                let input = input.with_prompt(prompt);
+                compute_chars += input.count_chars();
+
+                let local_infer = infer.clone();
+                futures.push(async move {
+                    let permit = local_infer.acquire_permit().await;
+                    local_infer
+                        .embed(
+                            input,
+                            truncation,
+                            req.normalize,
+                            req.dimensions,
+                            req.precision.into(),
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                })
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<InferResponse>, TextEmbeddingsError>>()
+                .map_err(ErrorResponse::from)?;
+
+            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
+
+            for r in results {
+                cache_hit &= r.cached;
+                total_tokenization_time += r.tokenization.as_nanos() as u64;
+                total_queue_time += r.queue.as_nanos() as u64;
+                total_inference_time += r.inference.as_nanos() as u64;
+                total_compute_tokens += r.prompt_tokens;
+                embeddings.push(r.results);
+            }
+            let batch_size = batch_size as u64;
+
+            metrics::increment_counter!("te_request_success", "method" => "batch");
+
+            (
+                embeddings,
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
+                ),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    if octet_stream {
+        // Bytes of all the embeddings, one after the other
+        let body: Vec<u8> = embeddings
+            .iter()
+            .flat_map(|e| embedding_bytes(e, req.precision))
+            .collect();
+        return Ok((headers, [(CONTENT_TYPE, OCTET_STREAM)], body).into_response());
+    }
+
+    let response = EmbedResponse(
+        embeddings
+            .into_iter()
+            .map(|e| Embedding::new(e, req.precision, req.encoding_format))
+            .collect(),
+    );
+    Ok((headers, Json(response)).into_response())
+}
+
+/// Get the hidden states of every token. Returns a 424 status code if the model is not an
+/// embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed_all",
+request_body = EmbedAllRequest,
+responses(
+(status = 200, description = "Token Embeddings", body = EmbedAllResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed_all(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<EmbedAllRequest>,
+) -> Result<(HeaderMap, Json<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+    let prompt = resolve_prompt(req.prompt_name.as_deref(), req.prompt.as_deref(), info)?;
+    validate_layer(req.layer, info)?;
+    validate_input_type(req.input_type.map(Into::into), req.layer)?;
+    let input_type = req.input_type;
+
+    let (embeddings, metadata) = match req.inputs {
+        EmbedInput::Single(input) => {
+            metrics::increment_counter!("te_request_count", "method" => "single");
+
+            let input = input.with_prompt(prompt);
+            let compute_chars = input.count_chars();
+
+            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let response = match input_type {
+                Some(input_type) => {
+                    infer
+                        .embed_colbert(input, input_type.into(), priority, deadline, permit)
+                        .await
+                }
+                None => {
+                    infer
+                        .embed_all(input, truncation, req.layer, priority, deadline, permit)
+                        .await
+                }
+            }
+            .map_err(ErrorResponse::from)?;
+
+            metrics::increment_counter!("te_request_success", "method" => "single");
+
+            (
+                vec![response.results],
+                ResponseMetadata::new(
+                    compute_chars,
+                    response.prompt_tokens,
+                    start_time,
+                    response.tokenization,
+                    response.queue,
+                    response.inference,
+                    false,
+                ),
+            )
+        }
+        EmbedInput::Batch(inputs) => {
+            metrics::increment_counter!("te_request_count", "method" => "batch");
+
+            let batch_size = inputs.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::EmbedAll);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
+
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
+
+            for input in inputs {
+                let input = input.with_prompt(prompt);
+                compute_chars += input.count_chars();
+
+                let local_infer = infer.clone();
+                futures.push(async move {
+                    let permit = local_infer.acquire_permit().await;
+                    match input_type {
+                        Some(input_type) => {
+                            local_infer
+                                .embed_colbert(input, input_type.into(), priority, deadline, permit)
+                                .await
+                        }
+                        None => {
+                            local_infer
+                                .embed_all(input, truncation, req.layer, priority, deadline, permit)
+                                .await
+                        }
+                    }
+                })
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<InferAllResponse>, TextEmbeddingsError>>()
+                .map_err(ErrorResponse::from)?;
+
+            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+
+            for r in results {
+                total_tokenization_time += r.tokenization.as_nanos() as u64;
+                total_queue_time += r.queue.as_nanos() as u64;
+                total_inference_time += r.inference.as_nanos() as u64;
+                total_compute_tokens += r.prompt_tokens;
+                embeddings.push(r.results);
+            }
+            let batch_size = batch_size as u64;
+
+            metrics::increment_counter!("te_request_success", "method" => "batch");
+
+            (
+                embeddings,
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                    false,
+                ),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(EmbedAllResponse(embeddings))))
+}
+
+/// Get Sparse Embeddings. Returns a 424 status code if the model is not a SPLADE model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed_sparse",
+request_body = EmbedSparseRequest,
+responses(
+(status = 200, description = "Sparse Embeddings", body = EmbedSparseResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed_sparse(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<EmbedSparseRequest>,
+) -> Result<(HeaderMap, Json<EmbedSparseResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+    let prompt = resolve_prompt(req.prompt_name.as_deref(), req.prompt.as_deref(), info)?;
+
+    // Closure for embed_sparse
+    let embed_sparse_inner =
+        move |input: InputValue, infer: Infer, permit: Option<OwnedSemaphorePermit>| async move {
+            let permit = match permit {
+                None => infer.acquire_permit().await,
+                Some(permit) => permit,
+            };
+
+            let response = infer
+                .embed_sparse(input, truncation, priority, deadline, permit)
+                .await
+                .map_err(ErrorResponse::from)?;
+
+            // Only keep the activated vocabulary entries
+            let mut sparse_values: Vec<SparseValue> = response
+                .results
+                .into_iter()
+                .enumerate()
+                .filter(|(_, value)| *value > req.threshold)
+                .map(|(index, value)| SparseValue {
+                    index,
+                    value,
+                    token: None,
+                })
+                .collect();
+
+            if req.return_tokens {
+                let ids = sparse_values.iter().map(|v| v.index as u32).collect();
+                let tokens = infer.id_to_tokens(ids).await;
+                for (sparse_value, token) in sparse_values.iter_mut().zip(tokens) {
+                    sparse_value.token = Some(token);
+                }
+            }
+
+            Ok::<(usize, Duration, Duration, Duration, Vec<SparseValue>, bool), ErrorResponse>((
+                response.prompt_tokens,
+                response.tokenization,
+                response.queue,
+                response.inference,
+                sparse_values,
+                response.cached,
+            ))
+        };
+
+    let (response, metadata) = match req.inputs {
+        EmbedInput::Single(input) => {
+            metrics::increment_counter!("te_request_count", "method" => "single");
+
+            let input = input.with_prompt(prompt);
+            let compute_chars = input.count_chars();
+            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let (prompt_tokens, tokenization, queue, inference, sparse_values, cache_hit) =
+                embed_sparse_inner(input, infer.clone(), Some(permit)).await?;
+
+            metrics::increment_counter!("te_request_success", "method" => "single");
+
+            (
+                EmbedSparseResponse(vec![sparse_values]),
+                ResponseMetadata::new(
+                    compute_chars,
+                    prompt_tokens,
+                    start_time,
+                    tokenization,
+                    queue,
+                    inference,
+                    cache_hit,
+                ),
+            )
         }
+        EmbedInput::Batch(inputs) => {
+            metrics::increment_counter!("te_request_count", "method" => "batch");
 
-        let mut futures = Vec::with_capacity(batch_size);
-        let query_chars = req.query.chars().count();
-        let mut compute_chars = query_chars * batch_size;
+            let batch_size = inputs.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::EmbedSparse);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
 
-        for text in &req.texts {
-            compute_chars += text.chars().count();
-            let local_infer = infer.clone();
-            futures.push(rerank_inner(
-                req.query.clone(),
-                text.clone(),
-                req.truncate,
-                req.raw_scores,
-                local_infer.0,
-            ))
-        }
-        let results = join_all(futures)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32)>, ErrorResponse>>()?;
-
-        let mut ranks = Vec::with_capacity(batch_size);
-        let mut total_tokenization_time = 0;
-        let mut total_queue_time = 0;
-        let mut total_inference_time = 0;
-        let mut total_compute_tokens = 0;
-
-        for (index, r) in results.into_iter().enumerate() {
-            total_compute_tokens += r.0;
-            total_tokenization_time += r.1.as_nanos() as u64;
-            total_queue_time += r.2.as_nanos() as u64;
-            total_inference_time += r.3.as_nanos() as u64;
-            let text = if req.return_text {
-                Some(req.texts[index].clone())
-            } else {
-                None
-            };
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
 
-            ranks.push(Rank {
-                index,
-                text,
-                score: r.4,
-            })
-        }
+            for input in inputs {
+                let input = input.with_prompt(prompt);
+                compute_chars += input.count_chars();
+                futures.push(embed_sparse_inner(input, infer.clone(), None))
+            }
+            let results = join_all(futures).await.into_iter().collect::<Result<
+                Vec<(usize, Duration, Duration, Duration, Vec<SparseValue>, bool)>,
+                ErrorResponse,
+            >>()?;
 
-        // Reverse sort
-        ranks.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
-        ranks.reverse();
+            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
 
-        let batch_size = batch_size as u64;
+            for r in results {
+                total_compute_tokens += r.0;
+                cache_hit &= r.5;
+                total_tokenization_time += r.1.as_nanos() as u64;
+                total_queue_time += r.2.as_nanos() as u64;
+                total_inference_time += r.3.as_nanos() as u64;
+                embeddings.push(r.4);
+            }
+            let batch_size = batch_size as u64;
 
-        metrics::increment_counter!("te_request_success", "method" => "batch");
+            metrics::increment_counter!("te_request_success", "method" => "batch");
 
-        (
-            RerankResponse(ranks),
-            ResponseMetadata::new(
-                compute_chars,
-                total_compute_tokens,
-                start_time,
-                Duration::from_nanos(total_tokenization_time / batch_size),
-                Duration::from_nanos(total_queue_time / batch_size),
-                Duration::from_nanos(total_inference_time / batch_size),
-            ),
-        )
+            (
+                EmbedSparseResponse(embeddings),
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
+                ),
+            )
+        }
     };
 
     metadata.record_span(&span);
@@ -421,14 +1587,16 @@ async fn rerank(
     Ok((headers, Json(response)))
 }
 
-/// Get Embeddings. Returns a 424 status code if the model is not an embedding model.
+/// Get Embeddings of long inputs split in overlapping windows. Returns a 424 status code if the
+/// model is not an embedding model.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
-path = "/embed",
-request_body = EmbedRequest,
+path = "/embed_chunks",
+request_body = EmbedChunksRequest,
 responses(
-(status = 200, description = "Embeddings", body = EmbedResponse),
+(status = 200, description = "Chunk Embeddings, or one aggregated embedding per input if \
+`aggregation` is set", body = EmbedChunksResponse),
 (status = 424, description = "Embedding Error", body = ErrorResponse,
 example = json ! ({"error": "Inference failed", "error_type": "backend"})),
 (status = 429, description = "Model is overloaded", body = ErrorResponse,
@@ -437,43 +1605,113 @@ example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"}))
 example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
 )
 )]
 #[instrument(
     skip_all,
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
-async fn embed(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
-    Json(req): Json<EmbedRequest>,
-) -> Result<(HeaderMap, Json<EmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
+async fn embed_chunks(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<EmbedChunksRequest>,
+) -> Result<(HeaderMap, Json<EmbedChunksResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
 
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let chunk_size = req.chunk_size.unwrap_or(info.max_input_length);
+    // Each chunk is queued like an input of a batch
+    let max_chunks = limits.max_client_batch_size(Endpoint::EmbedChunks);
+
+    // Closure for embed_chunks
+    let embed_chunks_inner =
+        move |input: String, infer: Infer, permit: Option<OwnedSemaphorePermit>| async move {
+            let permit = match permit {
+                None => infer.acquire_permit().await,
+                Some(permit) => permit,
+            };
+
+            let response = infer
+                .embed_chunks(
+                    input,
+                    chunk_size,
+                    req.stride,
+                    max_chunks,
+                    req.normalize,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+                .map_err(ErrorResponse::from)?;
+
+            let document = match req.aggregation {
+                Some(aggregation) => DocumentEmbedding {
+                    embedding: Some(chunking::aggregate(
+                        &response.chunks,
+                        aggregation.into(),
+                        req.normalize,
+                    )),
+                    chunks: None,
+                },
+                None => DocumentEmbedding {
+                    embedding: None,
+                    chunks: Some(
+                        response
+                            .chunks
+                            .into_iter()
+                            .map(|chunk| ChunkEmbedding {
+                                embedding: chunk.embedding,
+                                start: chunk.start,
+                                end: chunk.end,
+                            })
+                            .collect(),
+                    ),
+                },
+            };
+
+            Ok::<(usize, Duration, Duration, Duration, DocumentEmbedding), ErrorResponse>((
+                response.prompt_tokens,
+                response.tokenization,
+                response.queue,
+                response.inference,
+                document,
+            ))
+        };
+
     let (response, metadata) = match req.inputs {
         Input::Single(input) => {
             metrics::increment_counter!("te_request_count", "method" => "single");
 
             let compute_chars = input.chars().count();
-
             let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
-            let response = infer
-                .embed(input, req.truncate, req.normalize, permit)
-                .await
-                .map_err(ErrorResponse::from)?;
+            let (prompt_tokens, tokenization, queue, inference, document) =
+                embed_chunks_inner(input, infer.clone(), Some(permit)).await?;
 
             metrics::increment_counter!("te_request_success", "method" => "single");
 
             (
-                EmbedResponse(vec![response.results]),
+                EmbedChunksResponse(vec![document]),
                 ResponseMetadata::new(
                     compute_chars,
-                    response.prompt_tokens,
+                    prompt_tokens,
                     start_time,
-                    response.tokenization,
-                    response.queue,
-                    response.inference,
+                    tokenization,
+                    queue,
+                    inference,
+                    false,
                 ),
             )
         }
@@ -481,10 +1719,10 @@ async fn embed(
             metrics::increment_counter!("te_request_count", "method" => "batch");
 
             let batch_size = inputs.len();
-            if batch_size > info.max_client_batch_size {
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::EmbedChunks);
+            if batch_size > max_client_batch_size {
                 let message = format!(
-                    "batch size {batch_size} > maximum allowed batch size {}",
-                    info.max_client_batch_size
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
                 );
                 tracing::error!("{message}");
                 let err = ErrorResponse {
@@ -500,40 +1738,32 @@ async fn embed(
 
             for input in inputs {
                 compute_chars += input.chars().count();
-
-                let local_infer = infer.clone();
-                futures.push(async move {
-                    let permit = local_infer.acquire_permit().await;
-                    local_infer
-                        .embed(input, req.truncate, req.normalize, permit)
-                        .await
-                })
+                futures.push(embed_chunks_inner(input, infer.clone(), None))
             }
-            let results = join_all(futures)
-                .await
-                .into_iter()
-                .collect::<Result<Vec<InferResponse>, TextEmbeddingsError>>()
-                .map_err(ErrorResponse::from)?;
+            let results = join_all(futures).await.into_iter().collect::<Result<
+                Vec<(usize, Duration, Duration, Duration, DocumentEmbedding)>,
+                ErrorResponse,
+            >>()?;
 
-            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut documents = Vec::with_capacity(batch_size);
             let mut total_tokenization_time = 0;
             let mut total_queue_time = 0;
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
 
             for r in results {
-                total_tokenization_time += r.tokenization.as_nanos() as u64;
-                total_queue_time += r.queue.as_nanos() as u64;
-                total_inference_time += r.inference.as_nanos() as u64;
-                total_compute_tokens += r.prompt_tokens;
-                embeddings.push(r.results);
+                total_compute_tokens += r.0;
+                total_tokenization_time += r.1.as_nanos() as u64;
+                total_queue_time += r.2.as_nanos() as u64;
+                total_inference_time += r.3.as_nanos() as u64;
+                documents.push(r.4);
             }
             let batch_size = batch_size as u64;
 
             metrics::increment_counter!("te_request_success", "method" => "batch");
 
             (
-                EmbedResponse(embeddings),
+                EmbedChunksResponse(documents),
                 ResponseMetadata::new(
                     compute_chars,
                     total_compute_tokens,
@@ -541,6 +1771,7 @@ async fn embed(
                     Duration::from_nanos(total_tokenization_time / batch_size),
                     Duration::from_nanos(total_queue_time / batch_size),
                     Duration::from_nanos(total_inference_time / batch_size),
+                    false,
                 ),
             )
         }
@@ -556,6 +1787,103 @@ async fn embed(
     Ok((headers, Json(response)))
 }
 
+/// Get Sentence Similarity scores between a source sentence and candidate sentences. Returns a 424
+/// status code if the model is not an embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/similarity",
+request_body = SimilarityRequest,
+responses(
+(status = 200, description = "Sentence Similarity scores", body = SimilarityResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = ErrorResponse,
+example = json ! ({"error": "Request deadline exceeded", "error_type": "deadline_exceeded"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn similarity(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<SimilarityRequest>,
+) -> Result<(HeaderMap, Json<SimilarityResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    let truncation = truncation(
+        req.truncate,
+        req.truncation_direction,
+        req.truncation_strategy,
+        req.max_tokens,
+    );
+    let prompt = resolve_prompt(req.prompt_name.as_deref(), req.prompt.as_deref(), info)?;
+
+    metrics::increment_counter!("te_request_count", "method" => "batch");
+
+    let batch_size = req.inputs.sentences.len() + 1;
+    let max_client_batch_size = limits.max_client_batch_size(Endpoint::Similarity);
+    if batch_size > max_client_batch_size {
+        let message =
+            format!("batch size {batch_size} > maximum allowed batch size {max_client_batch_size}");
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+        Err(err)?;
+    }
+
+    let sentences = req
+        .inputs
+        .sentences
+        .into_iter()
+        .map(|input| with_prompt(prompt, input))
+        .collect();
+    let (scores, metadata) = similarity_scores(
+        infer,
+        with_prompt(prompt, req.inputs.source_sentence),
+        sentences,
+        truncation,
+        req.metric.into(),
+        priority,
+        deadline,
+        start_time,
+    )
+    .await?;
+
+    metrics::increment_counter!("te_request_success", "method" => "batch");
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(SimilarityResponse(scores))))
+}
+
 /// OpenAI compatible route. Returns a 424 status code if the model is not an embedding model.
 #[utoipa::path(
 post,
@@ -572,6 +1900,12 @@ example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
 example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
 (status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
 example = json ! ({"message": "Batch size error", "type": "validation"})),
+(status = 404, description = "Model not found", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "model `x` is not served by this router", "type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "missing or invalid API key", "type": "unauthorized"})),
+(status = 504, description = "Request deadline exceeded", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Request deadline exceeded", "type": "deadline_exceeded"})),
 )
 )]
 #[instrument(
@@ -579,23 +1913,40 @@ example = json ! ({"message": "Batch size error", "type": "validation"})),
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
 async fn openai_embed(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
     Json(req): Json<OpenAICompatRequest>,
 ) -> Result<(HeaderMap, Json<OpenAICompatResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
 {
     let span = tracing::Span::current();
     let start_time = Instant::now();
 
+    let (infer, info) = models.get(requested_model(req.model.as_deref(), &headers))?;
+    let priority = requested_priority(req.priority, &headers)?;
+    let deadline = req
+        .timeout_ms
+        .map(|timeout_ms| start_time + Duration::from_millis(timeout_ms));
+    validate_dimensions(req.dimensions, info)?;
+
     let (embeddings, metadata) = match req.input {
-        Input::Single(input) => {
+        EmbedInput::Single(input) => {
             metrics::increment_counter!("te_request_count", "method" => "single");
 
-            let compute_chars = input.chars().count();
+            let compute_chars = input.count_chars();
 
             let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
             let response = infer
-                .embed(input, false, true, permit)
+                .embed(
+                    input,
+                    Truncation::default(),
+                    true,
+                    req.dimensions,
+                    req.precision.into(),
+                    priority,
+                    deadline,
+                    permit,
+                )
                 .await
                 .map_err(ErrorResponse::from)?;
 
@@ -604,7 +1955,7 @@ async fn openai_embed(
             (
                 vec![OpenAICompatEmbedding {
                     object: "embedding",
-                    embedding: response.results,
+                    embedding: Embedding::new(response.results, req.precision, req.encoding_format),
                     index: 0,
                 }],
                 ResponseMetadata::new(
@@ -614,17 +1965,18 @@ async fn openai_embed(
                     response.tokenization,
                     response.queue,
                     response.inference,
+                    response.cached,
                 ),
             )
         }
-        Input::Batch(inputs) => {
+        EmbedInput::Batch(inputs) => {
             metrics::increment_counter!("te_request_count", "method" => "batch");
 
             let batch_size = inputs.len();
-            if batch_size > info.max_client_batch_size {
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::Embeddings);
+            if batch_size > max_client_batch_size {
                 let message = format!(
-                    "batch size {batch_size} > maximum allowed batch size {}",
-                    info.max_client_batch_size
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
                 );
                 tracing::error!("{message}");
                 let err = ErrorResponse {
@@ -639,12 +1991,23 @@ async fn openai_embed(
             let mut compute_chars = 0;
 
             for input in inputs {
-                compute_chars += input.chars().count();
+                compute_chars += input.count_chars();
 
                 let local_infer = infer.clone();
                 futures.push(async move {
                     let permit = local_infer.acquire_permit().await;
-                    local_infer.embed(input, false, true, permit).await
+                    local_infer
+                        .embed(
+                            input,
+                            Truncation::default(),
+                            true,
+                            req.dimensions,
+                            req.precision.into(),
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
                 })
             }
             let results = join_all(futures)
@@ -658,15 +2021,17 @@ async fn openai_embed(
             let mut total_queue_time = 0;
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
+            let mut cache_hit = true;
 
             for (i, r) in results.into_iter().enumerate() {
+                cache_hit &= r.cached;
                 total_tokenization_time += r.tokenization.as_nanos() as u64;
                 total_queue_time += r.queue.as_nanos() as u64;
                 total_inference_time += r.inference.as_nanos() as u64;
                 total_compute_tokens += r.prompt_tokens;
                 embeddings.push(OpenAICompatEmbedding {
                     object: "embedding",
-                    embedding: r.results,
+                    embedding: Embedding::new(r.results, req.precision, req.encoding_format),
                     index: i,
                 });
             }
@@ -683,6 +2048,7 @@ async fn openai_embed(
                     Duration::from_nanos(total_tokenization_time / batch_size),
                     Duration::from_nanos(total_queue_time / batch_size),
                     Duration::from_nanos(total_inference_time / batch_size),
+                    cache_hit,
                 ),
             )
         }
@@ -708,6 +2074,205 @@ async fn openai_embed(
     Ok((headers, Json(response)))
 }
 
+/// Authenticate inference requests and enforce the rate limits of their API key
+async fn auth(
+    State(api_keys): State<Option<ApiKeys>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    // Health, info, metrics and documentation routes stay public
+    let api_keys = match api_keys {
+        Some(api_keys) if request.method() == Method::POST => api_keys,
+        _ => return next.run(request).await,
+    };
+
+    // Use the error format of the route
+    let openai = request.uri().path() == "/embeddings";
+    let cohere = request.uri().path() == "/v1/rerank";
+    let error_response = |err: ErrorResponse, headers: HeaderMap| {
+        let mut response = if openai {
+            <(StatusCode, Json<OpenAICompatErrorResponse>)>::from(err).into_response()
+        } else if cohere {
+            <(StatusCode, Json<CohereErrorResponse>)>::from(err).into_response()
+        } else {
+            <(StatusCode, Json<ErrorResponse>)>::from(err).into_response()
+        };
+        response.headers_mut().extend(headers);
+        response
+    };
+
+    let authorization = request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    let key = match api_keys.authorize(authorization) {
+        Ok(key) => key,
+        Err(err) => return error_response(err, HeaderMap::new()),
+    };
+    if let Err(retry_after) = key.acquire() {
+        let (err, headers) = key.rate_limited(retry_after);
+        return error_response(err, headers);
+    }
+
+    let mut response = next.run(request).await;
+
+    // Charge the prompt tokens of the request
+    let compute_tokens = response
+        .headers()
+        .get("x-compute-tokens")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if let Some(compute_tokens) = compute_tokens {
+        key.consume_tokens(compute_tokens);
+    }
+    response.headers_mut().extend(key.headers());
+
+    response
+}
+
+/// Tokenize inputs with the tokenizer of the model
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/tokenize",
+request_body = TokenizeRequest,
+responses(
+(status = 200, description = "Tokenized ids", body = TokenizeResponse),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+)
+)]
+#[instrument(skip_all)]
+async fn tokenize(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<TokenizeRequest>,
+) -> Result<Json<TokenizeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (infer, _) = models.get(requested_model(req.model.as_deref(), &headers))?;
+
+    let inputs = match req.inputs {
+        Input::Single(input) => vec![input],
+        Input::Batch(inputs) => {
+            let batch_size = inputs.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::Tokenize);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
+            inputs
+        }
+    };
+
+    let futures = inputs
+        .into_iter()
+        .map(|input| infer.tokenize(input, req.add_special_tokens));
+    let encodings = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let tokens = encodings
+        .into_iter()
+        .map(|encoding| {
+            encoding
+                .get_ids()
+                .iter()
+                .zip(encoding.get_tokens())
+                .zip(encoding.get_offsets())
+                .zip(encoding.get_special_tokens_mask())
+                .map(|(((&id, token), &(start, stop)), &special)| {
+                    let special = special == 1;
+                    SimpleToken {
+                        id,
+                        token: token.clone(),
+                        special,
+                        start: (!special).then_some(start),
+                        stop: (!special).then_some(stop),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(Json(TokenizeResponse(tokens)))
+}
+
+/// Decode token ids with the tokenizer of the model
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/decode",
+request_body = DecodeRequest,
+responses(
+(status = 200, description = "Decoded ids", body = DecodeResponse),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 404, description = "Model not found", body = ErrorResponse,
+example = json ! ({"error": "model `x` is not served by this router", "error_type": "model_not_found"})),
+(status = 401, description = "Missing or invalid API key", body = ErrorResponse,
+example = json ! ({"error": "missing or invalid API key", "error_type": "unauthorized"})),
+)
+)]
+#[instrument(skip_all)]
+async fn decode(
+    models: Extension<Models>,
+    limits: Extension<Arc<RuntimeLimits>>,
+    headers: HeaderMap,
+    Json(req): Json<DecodeRequest>,
+) -> Result<Json<DecodeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let (infer, _) = models.get(requested_model(req.model.as_deref(), &headers))?;
+
+    let ids = match req.ids {
+        InputIds::Single(ids) => vec![ids],
+        InputIds::Batch(ids) => {
+            let batch_size = ids.len();
+            let max_client_batch_size = limits.max_client_batch_size(Endpoint::Decode);
+            if batch_size > max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {max_client_batch_size}"
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                metrics::increment_counter!("te_request_failure", "err" => "batch_size");
+                Err(err)?;
+            }
+            ids
+        }
+    };
+
+    let futures = ids
+        .into_iter()
+        .map(|ids| infer.decode(ids, req.skip_special_tokens));
+    let texts = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    Ok(Json(DecodeResponse(texts)))
+}
+
 /// Prometheus metrics scrape endpoint
 #[utoipa::path(
 get,
@@ -719,10 +2284,49 @@ async fn metrics(prom_handle: Extension<PrometheusHandle>) -> String {
     prom_handle.render()
 }
 
+/// Select a model from the request body or, if absent, from the model header
+fn requested_model<'a>(model: Option<&'a str>, headers: &'a HeaderMap) -> Option<&'a str> {
+    model.or_else(|| {
+        headers
+            .get(MODEL_HEADER)
+            .and_then(|value| value.to_str().ok())
+    })
+}
+
+/// Media type of the raw embedding responses
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Whether the client asked for raw embedding bytes with the `Accept` header
+fn accepts_octet_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|media_type| media_type.trim().starts_with(OCTET_STREAM))
+        })
+}
+
+fn requested_priority(
+    priority: Option<Priority>,
+    headers: &HeaderMap,
+) -> Result<queue::Priority, ErrorResponse> {
+    match priority {
+        Some(priority) => Ok(priority.into()),
+        None => parse_priority(
+            headers
+                .get(PRIORITY_HEADER)
+                .and_then(|value| value.to_str().ok()),
+        ),
+    }
+}
+
 /// Serving method
 pub async fn run(
-    infer: Infer,
-    info: Info,
+    models: Models,
+    limits: Arc<RuntimeLimits>,
+    api_keys: Option<ApiKeys>,
     addr: SocketAddr,
     prom_builder: PrometheusBuilder,
 ) -> Result<(), anyhow::Error> {
@@ -733,33 +2337,85 @@ pub async fn run(
     get_model_info,
     health,
     predict,
+    token_classify,
     rerank,
+    cohere_rerank,
     embed,
+    embed_sparse,
+    embed_all,
+    embed_chunks,
+    similarity,
     openai_embed,
+    tokenize,
+    decode,
     metrics,
     ),
     components(
     schemas(
     PredictInput,
     Input,
+    InputValue,
+    EmbedInput,
     Info,
+    MaxInputLengthSource,
     ModelType,
     ClassifierModel,
+    ProblemType,
     EmbeddingModel,
     PredictRequest,
     Prediction,
+    Priority,
+    TruncationDirection,
+    TruncationStrategy,
+    FunctionToApply,
+    AggregationStrategy,
+    TokenClassifyRequest,
+    Entity,
+    TokenClassifyResponse,
     PredictResponse,
     OpenAICompatRequest,
+    EncodingFormat,
+    Precision,
+    Embedding,
     OpenAICompatEmbedding,
     OpenAICompatUsage,
     OpenAICompatResponse,
     RerankRequest,
     Rank,
     RerankResponse,
+    CohereDocument,
+    CohereRerankRequest,
+    CohereRerankResult,
+    CohereApiVersion,
+    CohereBilledUnits,
+    CohereMeta,
+    CohereRerankResponse,
     EmbedRequest,
     EmbedResponse,
+    EmbedSparseRequest,
+    SparseValue,
+    EmbedSparseResponse,
+    InputType,
+    EmbedAllRequest,
+    EmbedAllResponse,
+    EmbedChunksRequest,
+    ChunkAggregation,
+    ChunkEmbedding,
+    DocumentEmbedding,
+    EmbedChunksResponse,
+    SimilarityInput,
+    SimilarityMetric,
+    SimilarityRequest,
+    SimilarityResponse,
+    TokenizeRequest,
+    SimpleToken,
+    TokenizeResponse,
+    InputIds,
+    DecodeRequest,
+    DecodeResponse,
     ErrorResponse,
     OpenAICompatErrorResponse,
+    CohereErrorResponse,
     ErrorType,
     )
     ),
@@ -775,26 +2431,23 @@ pub async fn run(
     )]
     struct ApiDoc;
 
-    // CORS allowed origins
-    // map to go inside the option and then map to parse from String to HeaderValue
-    // Finally, convert to AllowOrigin
-    let allow_origin: Option<AllowOrigin> =
-        env::var("CORS_ALLOW_ORIGIN").ok().map(|cors_allow_origin| {
-            let cors_allow_origin = cors_allow_origin.split(',');
-            AllowOrigin::list(
-                cors_allow_origin.map(|origin| origin.parse::<HeaderValue>().unwrap()),
-            )
-        });
-
     let prom_handle = prom_builder
         .install_recorder()
         .context("failed to install metrics recorder")?;
 
     // CORS layer
-    let allow_origin = allow_origin.unwrap_or(AllowOrigin::any());
+    // Allowed origins can be updated at runtime
+    let cors_limits = limits.clone();
+    let allow_origin =
+        AllowOrigin::predicate(move |origin: &HeaderValue, _| cors_limits.allow_origin(origin));
     let cors_layer = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST])
-        .allow_headers([http::header::CONTENT_TYPE])
+        .allow_headers([
+            http::header::CONTENT_TYPE,
+            http::header::AUTHORIZATION,
+            http::HeaderName::from_static(MODEL_HEADER),
+            http::HeaderName::from_static(PRIORITY_HEADER),
+        ])
         .allow_origin(allow_origin);
 
     // Create router
@@ -803,10 +2456,18 @@ pub async fn run(
         // Base routes
         .route("/info", get(get_model_info))
         .route("/embed", post(embed))
+        .route("/embed_sparse", post(embed_sparse))
+        .route("/embed_all", post(embed_all))
+        .route("/embed_chunks", post(embed_chunks))
+        .route("/similarity", post(similarity))
         .route("/predict", post(predict))
+        .route("/token_classify", post(token_classify))
         .route("/rerank", post(rerank))
+        .route("/v1/rerank", post(cohere_rerank))
         // OpenAI compat route
         .route("/embeddings", post(openai_embed))
+        .route("/tokenize", post(tokenize))
+        .route("/decode", post(decode))
         // Base Health route
         .route("/health", get(health))
         // Inference API health route
@@ -816,7 +2477,8 @@ pub async fn run(
         // Prometheus metrics route
         .route("/metrics", get(metrics));
 
-    // Set default routes
+    // Set default routes from the default model type
+    let (_, info) = models.default_model();
     let app = match &info.model_type {
         ModelType::Classifier(_) => {
             app.route("/", post(predict))
@@ -828,6 +2490,16 @@ pub async fn run(
                 // AWS Sagemaker route
                 .route("/invocations", post(rerank))
         }
+        ModelType::TokenClassifier(_) => {
+            app.route("/", post(token_classify))
+                // AWS Sagemaker route
+                .route("/invocations", post(token_classify))
+        }
+        ModelType::Embedding(model) if model.pooling == "splade" => {
+            app.route("/", post(embed_sparse))
+                // AWS Sagemaker route
+                .route("/invocations", post(embed_sparse))
+        }
         ModelType::Embedding(_) => {
             app.route("/", post(embed))
                 // AWS Sagemaker route
@@ -836,8 +2508,9 @@ pub async fn run(
     };
 
     let app = app
-        .layer(Extension(infer))
-        .layer(Extension(info))
+        .layer(Extension(models))
+        .layer(Extension(limits))
+        .layer(middleware::from_fn_with_state(api_keys, auth))
         .layer(Extension(prom_handle.clone()))
         .layer(OtelAxumLayer::default())
         .layer(cors_layer);
@@ -860,6 +2533,10 @@ impl From<&ErrorType> for StatusCode {
             ErrorType::Overloaded => StatusCode::TOO_MANY_REQUESTS,
             ErrorType::Tokenizer => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorType::Validation => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorType::InvalidInput => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorType::ModelNotFound => StatusCode::NOT_FOUND,
+            ErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorType::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
         }
     }
 }
@@ -874,6 +2551,14 @@ impl From<ErrorResponse> for OpenAICompatErrorResponse {
     }
 }
 
+impl From<ErrorResponse> for CohereErrorResponse {
+    fn from(value: ErrorResponse) -> Self {
+        CohereErrorResponse {
+            message: value.error,
+        }
+    }
+}
+
 /// Convert to Axum supported formats
 impl From<ErrorResponse> for (StatusCode, Json<ErrorResponse>) {
     fn from(err: ErrorResponse) -> Self {
@@ -886,3 +2571,9 @@ impl From<ErrorResponse> for (StatusCode, Json<OpenAICompatErrorResponse>) {
         (StatusCode::from(&err.error_type), Json(err.into()))
     }
 }
+
+impl From<ErrorResponse> for (StatusCode, Json<CohereErrorResponse>) {
+    fn from(err: ErrorResponse) -> Self {
+        (StatusCode::from(&err.error_type), Json(err.into()))
+    }
+}
@@ -1,9 +1,15 @@
-use crate::ErrorType;
+use crate::{with_prompt, ErrorType};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use serde::de::{SeqAccess, Visitor};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::fmt::Formatter;
-use text_embeddings_core::tokenization::EncodingInput;
+use text_embeddings_core::tokenization::{self, EncodingInput};
+use text_embeddings_core::{
+    chunking, classification, colbert, quantization, queue, similarity, token_classification,
+};
 use utoipa::openapi::{RefOr, Schema};
 use utoipa::ToSchema;
 
@@ -11,6 +17,8 @@ use utoipa::ToSchema;
 pub(crate) enum Sequence {
     Single(String),
     Pair(String, String),
+    /// Pre-tokenized sequence
+    Ids(Vec<u32>),
 }
 
 impl Sequence {
@@ -18,6 +26,7 @@ impl Sequence {
         match self {
             Sequence::Single(s) => s.chars().count(),
             Sequence::Pair(s1, s2) => s1.chars().count() + s2.chars().count(),
+            Sequence::Ids(_) => 0,
         }
     }
 }
@@ -27,6 +36,7 @@ impl From<Sequence> for EncodingInput {
         match value {
             Sequence::Single(s) => Self::Single(s),
             Sequence::Pair(s1, s2) => Self::Dual(s1, s2),
+            Sequence::Ids(ids) => Self::Ids(ids),
         }
     }
 }
@@ -46,7 +56,9 @@ impl<'de> Deserialize<'de> for PredictInput {
         #[serde(untagged)]
         enum Internal {
             Single(String),
+            Id(u32),
             Multiple(Vec<String>),
+            Ids(Vec<u32>),
         }
 
         struct PredictInputVisitor;
@@ -57,8 +69,10 @@ impl<'de> Deserialize<'de> for PredictInput {
             fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
                 formatter.write_str(
                     "a string, \
-                    a pair of strings [string, string] \
-                    or a batch of mixed strings and pairs [[string], [string, string], ...]",
+                    a pair of strings [string, string], \
+                    token ids [int, int, ...] \
+                    or a batch of mixed strings, pairs and token ids \
+                    [[string], [string, string], [int, ...], ...]",
                 )
             }
 
@@ -114,8 +128,18 @@ impl<'de> Deserialize<'de> for PredictInput {
                             return Ok(PredictInput::Single(Sequence::Single(value)));
                         }
                     }
+                    // Input is pre-tokenized
+                    // Return early
+                    Internal::Id(id) => {
+                        let mut ids = vec![id];
+                        while let Some(id) = seq.next_element()? {
+                            ids.push(id);
+                        }
+                        return Ok(PredictInput::Single(Sequence::Ids(ids)));
+                    }
                     // Input is a batch
                     Internal::Multiple(value) => sequence_from_vec(value),
+                    Internal::Ids(ids) => Ok(Sequence::Ids(ids)),
                 }?;
 
                 let mut batch = Vec::with_capacity(32);
@@ -123,9 +147,21 @@ impl<'de> Deserialize<'de> for PredictInput {
                 batch.push(s);
 
                 // Iterate on all sequences
-                while let Some(value) = seq.next_element::<Vec<String>>()? {
+                while let Some(value) = seq.next_element::<Internal>()? {
                     // Validate sequence
-                    let s = sequence_from_vec(value)?;
+                    let s = match value {
+                        Internal::Multiple(value) => sequence_from_vec(value)?,
+                        Internal::Ids(ids) => Sequence::Ids(ids),
+                        Internal::Single(value) => {
+                            return Err(de::Error::invalid_type(de::Unexpected::Str(&value), &self))
+                        }
+                        Internal::Id(id) => {
+                            return Err(de::Error::invalid_type(
+                                de::Unexpected::Unsigned(id as u64),
+                                &self,
+                            ))
+                        }
+                    };
                     // Push to batch
                     batch.push(s);
                 }
@@ -157,6 +193,15 @@ impl<'__s> ToSchema<'__s> for PredictInput {
                         .min_items(Some(2))
                         .max_items(Some(2)),
                 )
+                .item(
+                    utoipa::openapi::ArrayBuilder::new()
+                        .items(
+                            utoipa::openapi::ObjectBuilder::new()
+                                .schema_type(utoipa::openapi::SchemaType::Integer),
+                        )
+                        .description(Some("Token ids"))
+                        .min_items(Some(1)),
+                )
                 .item(
                     utoipa::openapi::ArrayBuilder::new().items(
                         utoipa::openapi::OneOfBuilder::new()
@@ -180,12 +225,21 @@ impl<'__s> ToSchema<'__s> for PredictInput {
                                     .min_items(Some(2))
                                     .max_items(Some(2)),
                             )
+                            .item(
+                                utoipa::openapi::ArrayBuilder::new()
+                                    .items(
+                                        utoipa::openapi::ObjectBuilder::new()
+                                            .schema_type(utoipa::openapi::SchemaType::Integer),
+                                    )
+                                    .description(Some("Token ids"))
+                                    .min_items(Some(1)),
+                            )
                     ).description(Some("A batch")),
                 )
                 .description(Some(
                     "Model input. \
-                Can be either a single string, a pair of strings or a batch of mixed single and pairs \
-                of strings.",
+                Can be either a single string, a pair of strings, token ids or a batch of mixed single \
+                strings, pairs of strings and token ids. Token ids are used as-is.",
                 ))
                 .example(Some(json!("What is Deep Learning?")))
                 .into(),
@@ -193,6 +247,92 @@ impl<'__s> ToSchema<'__s> for PredictInput {
     }
 }
 
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl From<Priority> for queue::Priority {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::Low => queue::Priority::Low,
+            Priority::Normal => queue::Priority::Normal,
+            Priority::High => queue::Priority::High,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TruncationDirection {
+    Left,
+    #[default]
+    Right,
+}
+
+impl From<TruncationDirection> for tokenization::TruncationDirection {
+    fn from(value: TruncationDirection) -> Self {
+        match value {
+            TruncationDirection::Left => tokenization::TruncationDirection::Left,
+            TruncationDirection::Right => tokenization::TruncationDirection::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TruncationStrategy {
+    #[default]
+    LongestFirst,
+    OnlyFirst,
+    OnlySecond,
+}
+
+impl From<TruncationStrategy> for tokenization::TruncationStrategy {
+    fn from(value: TruncationStrategy) -> Self {
+        match value {
+            TruncationStrategy::LongestFirst => tokenization::TruncationStrategy::LongestFirst,
+            TruncationStrategy::OnlyFirst => tokenization::TruncationStrategy::OnlyFirst,
+            TruncationStrategy::OnlySecond => tokenization::TruncationStrategy::OnlySecond,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FunctionToApply {
+    Softmax,
+    Sigmoid,
+    None,
+}
+
+impl From<FunctionToApply> for classification::FunctionToApply {
+    fn from(value: FunctionToApply) -> Self {
+        match value {
+            FunctionToApply::Softmax => classification::FunctionToApply::Softmax,
+            FunctionToApply::Sigmoid => classification::FunctionToApply::Sigmoid,
+            FunctionToApply::None => classification::FunctionToApply::None,
+        }
+    }
+}
+
+pub(crate) fn truncation(
+    truncate: bool,
+    direction: TruncationDirection,
+    strategy: TruncationStrategy,
+    max_tokens: Option<usize>,
+) -> tokenization::Truncation {
+    tokenization::Truncation {
+        truncate,
+        direction: direction.into(),
+        strategy: strategy.into(),
+        max_tokens,
+    }
+}
+
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct PredictRequest {
     pub inputs: PredictInput,
@@ -200,8 +340,29 @@ pub(crate) struct PredictRequest {
     #[schema(default = "false", example = "false")]
     pub truncate: bool,
     #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub raw_scores: bool,
+    /// Overrides `raw_scores`. Defaults to `sigmoid` for multi-label models and models with a
+    /// single label, `none` for regression models and `softmax` otherwise
+    #[schema(nullable = true, example = "null")]
+    pub function_to_apply: Option<FunctionToApply>,
+    /// Only return the `top_k` labels with the highest scores
+    #[schema(nullable = true, example = "null")]
+    pub top_k: Option<usize>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -229,11 +390,25 @@ pub(crate) struct RerankRequest {
     #[schema(default = "false", example = "false")]
     pub truncate: bool,
     #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub raw_scores: bool,
     #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub return_text: bool,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -250,6 +425,78 @@ pub(crate) struct Rank {
 #[derive(Serialize, ToSchema)]
 pub(crate) struct RerankResponse(pub Vec<Rank>);
 
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum CohereDocument {
+    Text(String),
+    Object(BTreeMap<String, String>),
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CohereRerankRequest {
+    #[schema(example = "What is Deep Learning?")]
+    pub query: String,
+    #[schema(example = json!(["Deep Learning is ..."]))]
+    pub documents: Vec<CohereDocument>,
+    #[schema(nullable = true, example = "null")]
+    pub top_n: Option<usize>,
+    #[schema(nullable = true, example = json!(["text"]))]
+    pub rank_fields: Option<Vec<String>>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_documents: bool,
+    #[serde(default = "default_max_chunks_per_doc")]
+    #[schema(default = "10", example = "10")]
+    pub max_chunks_per_doc: usize,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+fn default_max_chunks_per_doc() -> usize {
+    10
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereRerankResult {
+    #[schema(example = "0")]
+    pub index: usize,
+    #[schema(example = "1.0")]
+    pub relevance_score: f32,
+    #[schema(nullable = true, example = json!({"text": "Deep Learning is ..."}), default = "null")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereApiVersion {
+    #[schema(example = "1")]
+    pub version: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereBilledUnits {
+    #[schema(example = "1")]
+    pub search_units: usize,
+    #[schema(example = "512")]
+    pub input_tokens: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereMeta {
+    pub api_version: CohereApiVersion,
+    pub billed_units: CohereBilledUnits,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereRerankResponse {
+    pub results: Vec<CohereRerankResult>,
+    pub meta: CohereMeta,
+}
+
 #[derive(Deserialize, ToSchema)]
 #[serde(untagged)]
 pub(crate) enum Input {
@@ -257,12 +504,138 @@ pub(crate) enum Input {
     Batch(Vec<String>),
 }
 
+/// Text or token ids. Token ids are used as-is: they must contain the special tokens of the
+/// model and prompts are not prepended to them
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum InputValue {
+    Text(String),
+    Ids(Vec<u32>),
+}
+
+impl InputValue {
+    pub(crate) fn count_chars(&self) -> usize {
+        match self {
+            InputValue::Text(text) => text.chars().count(),
+            InputValue::Ids(_) => 0,
+        }
+    }
+
+    pub(crate) fn with_prompt(self, prompt: Option<&str>) -> Self {
+        match self {
+            InputValue::Text(text) => InputValue::Text(with_prompt(prompt, text)),
+            InputValue::Ids(ids) => InputValue::Ids(ids),
+        }
+    }
+}
+
+impl From<InputValue> for EncodingInput {
+    fn from(value: InputValue) -> Self {
+        match value {
+            InputValue::Text(text) => Self::Single(text),
+            InputValue::Ids(ids) => Self::Ids(ids),
+        }
+    }
+}
+
+/// Input of the embedding routes, which also accept token ids.
+/// `Batch` comes first so that `[]` stays an empty batch
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum EmbedInput {
+    Batch(Vec<InputValue>),
+    Single(InputValue),
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EncodingFormat {
+    #[default]
+    Float,
+    Base64,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Precision {
+    #[default]
+    Float32,
+    Int8,
+    Uint8,
+    Binary,
+    Ubinary,
+}
+
+impl From<Precision> for quantization::Precision {
+    fn from(value: Precision) -> Self {
+        match value {
+            Precision::Float32 => quantization::Precision::Float32,
+            Precision::Int8 => quantization::Precision::Int8,
+            Precision::Uint8 => quantization::Precision::Uint8,
+            Precision::Binary => quantization::Precision::Binary,
+            Precision::Ubinary => quantization::Precision::Ubinary,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum Embedding {
+    Float(Vec<f32>),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+    Base64(String),
+}
+
+impl Embedding {
+    /// Encode an embedding. `base64` encodes the bytes of the values, see `embedding_bytes`
+    pub(crate) fn new(
+        values: Vec<f32>,
+        precision: Precision,
+        encoding_format: EncodingFormat,
+    ) -> Self {
+        match (encoding_format, precision) {
+            (EncodingFormat::Base64, _) => {
+                Self::Base64(STANDARD.encode(embedding_bytes(&values, precision)))
+            }
+            (EncodingFormat::Float, Precision::Float32) => Self::Float(values),
+            (EncodingFormat::Float, Precision::Int8 | Precision::Binary) => {
+                Self::Int8(values.into_iter().map(|v| v as i8).collect())
+            }
+            (EncodingFormat::Float, Precision::Uint8 | Precision::Ubinary) => {
+                Self::Uint8(values.into_iter().map(|v| v as u8).collect())
+            }
+        }
+    }
+}
+
+/// Raw bytes of an embedding: little-endian `f32` values, or one byte per value for quantized
+/// embeddings
+pub(crate) fn embedding_bytes(values: &[f32], precision: Precision) -> Vec<u8> {
+    match precision {
+        Precision::Float32 => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Precision::Int8 | Precision::Binary => values.iter().map(|v| *v as i8 as u8).collect(),
+        Precision::Uint8 | Precision::Ubinary => values.iter().map(|v| *v as u8).collect(),
+    }
+}
+
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct OpenAICompatRequest {
-    pub input: Input,
-    #[allow(dead_code)]
+    pub input: EmbedInput,
     #[schema(nullable = true, example = "null")]
     pub model: Option<String>,
+    #[serde(default)]
+    #[schema(default = "float", example = "float")]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    #[schema(default = "float32", example = "float32")]
+    pub precision: Precision,
+    #[schema(nullable = true, example = "null")]
+    pub dimensions: Option<usize>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
     #[allow(dead_code)]
     #[schema(nullable = true, example = "null")]
     pub user: Option<String>,
@@ -273,7 +646,7 @@ pub(crate) struct OpenAICompatEmbedding {
     #[schema(example = "embedding")]
     pub object: &'static str,
     #[schema(example = json!([0.0, 1.0, 2.0]))]
-    pub embedding: Vec<f32>,
+    pub embedding: Embedding,
     #[schema(example = "0")]
     pub index: usize,
 }
@@ -298,22 +671,390 @@ pub(crate) struct OpenAICompatResponse {
 
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct EmbedRequest {
-    pub inputs: Input,
+    pub inputs: EmbedInput,
     #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub truncate: bool,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
     #[serde(default = "default_normalize")]
     #[schema(default = "true", example = "true")]
     pub normalize: bool,
+    #[schema(nullable = true, example = "null")]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "float", example = "float")]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    #[schema(default = "float32", example = "float32")]
+    pub precision: Precision,
+    #[schema(nullable = true, example = "null")]
+    pub prompt_name: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
 }
 
 fn default_normalize() -> bool {
     true
 }
 
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedSparseRequest {
+    pub inputs: EmbedInput,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub truncate: bool,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "0.0", example = "0.0")]
+    pub threshold: f32,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_tokens: bool,
+    #[schema(nullable = true, example = "null")]
+    pub prompt_name: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum InputType {
+    Query,
+    Document,
+}
+
+impl From<InputType> for colbert::InputType {
+    fn from(value: InputType) -> Self {
+        match value {
+            InputType::Query => colbert::InputType::Query,
+            InputType::Document => colbert::InputType::Document,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedAllRequest {
+    pub inputs: EmbedInput,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub truncate: bool,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
+    /// Number of layers run before reading the hidden states. `0` returns the token
+    /// embeddings and the default is the last layer
+    #[schema(nullable = true, example = "null")]
+    pub layer: Option<usize>,
+    /// Encode the inputs as ColBERT queries or documents. The vectors are projected and
+    /// normalized, and the truncation parameters are replaced by the lengths of the model
+    #[schema(nullable = true, example = "null")]
+    pub input_type: Option<InputType>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt_name: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!([[[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]]]))]
+pub(crate) struct EmbedAllResponse(pub Vec<Vec<Vec<f32>>>);
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SparseValue {
+    #[schema(example = "1012")]
+    pub index: usize,
+    #[schema(example = "0.9")]
+    pub value: f32,
+    #[schema(nullable = true, example = "test", default = "null")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct EmbedSparseResponse(pub Vec<Vec<SparseValue>>);
+
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChunkAggregation {
+    Mean,
+    WeightedMean,
+}
+
+impl From<ChunkAggregation> for chunking::ChunkAggregation {
+    fn from(value: ChunkAggregation) -> Self {
+        match value {
+            ChunkAggregation::Mean => chunking::ChunkAggregation::Mean,
+            ChunkAggregation::WeightedMean => chunking::ChunkAggregation::WeightedMean,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedChunksRequest {
+    pub inputs: Input,
+    #[schema(nullable = true, example = "null")]
+    pub chunk_size: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "0", example = "32")]
+    pub stride: usize,
+    #[schema(nullable = true, example = "null")]
+    pub aggregation: Option<ChunkAggregation>,
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ChunkEmbedding {
+    #[schema(example = json!([0.0, 1.0, 2.0]))]
+    pub embedding: Vec<f32>,
+    #[schema(example = "0")]
+    pub start: usize,
+    #[schema(example = "1200")]
+    pub end: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DocumentEmbedding {
+    #[schema(nullable = true, example = json!([0.0, 1.0, 2.0]), default = "null")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+    #[schema(nullable = true, default = "null")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Vec<ChunkEmbedding>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct EmbedChunksResponse(pub Vec<DocumentEmbedding>);
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SimilarityInput {
+    #[schema(example = "What is Deep Learning?")]
+    pub source_sentence: String,
+    #[schema(example = json!(["Deep Learning is ...", "Cheese is made from milk"]))]
+    pub sentences: Vec<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SimilarityMetric {
+    #[default]
+    Cosine,
+    Dot,
+}
+
+impl From<SimilarityMetric> for similarity::SimilarityMetric {
+    fn from(value: SimilarityMetric) -> Self {
+        match value {
+            SimilarityMetric::Cosine => similarity::SimilarityMetric::Cosine,
+            SimilarityMetric::Dot => similarity::SimilarityMetric::Dot,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SimilarityRequest {
+    pub inputs: SimilarityInput,
+    #[serde(default)]
+    #[schema(default = "cosine", example = "cosine")]
+    pub metric: SimilarityMetric,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub truncate: bool,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "longest_first", example = "longest_first")]
+    pub truncation_strategy: TruncationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub max_tokens: Option<usize>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt_name: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub prompt: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!([0.9, 0.1]))]
+pub(crate) struct SimilarityResponse(pub Vec<f32>);
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AggregationStrategy {
+    #[default]
+    Simple,
+    First,
+    Max,
+}
+
+impl From<AggregationStrategy> for token_classification::AggregationStrategy {
+    fn from(value: AggregationStrategy) -> Self {
+        match value {
+            AggregationStrategy::Simple => token_classification::AggregationStrategy::Simple,
+            AggregationStrategy::First => token_classification::AggregationStrategy::First,
+            AggregationStrategy::Max => token_classification::AggregationStrategy::Max,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct TokenClassifyRequest {
+    pub inputs: Input,
+    #[serde(default)]
+    #[schema(default = "simple", example = "simple")]
+    pub aggregation_strategy: AggregationStrategy,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "null")]
+    pub priority: Option<Priority>,
+    #[schema(nullable = true, example = "null")]
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Entity {
+    #[schema(example = "PER")]
+    pub label: String,
+    #[schema(example = "0.99")]
+    pub score: f32,
+    #[schema(example = "11")]
+    pub start: usize,
+    #[schema(example = "19")]
+    pub end: usize,
+    #[schema(example = "Wolfgang")]
+    pub text: String,
+}
+
+impl From<token_classification::Entity> for Entity {
+    fn from(value: token_classification::Entity) -> Self {
+        Entity {
+            label: value.label,
+            score: value.score,
+            start: value.start,
+            end: value.end,
+            text: value.text,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct TokenClassifyResponse(pub Vec<Vec<Entity>>);
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct TokenizeRequest {
+    pub inputs: Input,
+    #[serde(default = "default_add_special_tokens")]
+    #[schema(default = "true", example = "true")]
+    pub add_special_tokens: bool,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+}
+
+fn default_add_special_tokens() -> bool {
+    true
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SimpleToken {
+    #[schema(example = "0")]
+    pub id: u32,
+    #[schema(example = "test")]
+    pub token: String,
+    #[schema(example = "false")]
+    pub special: bool,
+    #[schema(nullable = true, example = "0")]
+    pub start: Option<usize>,
+    #[schema(nullable = true, example = "4")]
+    pub stop: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!([[{"id": 0, "token": "test", "special": false, "start": 0, "stop": 4}]]))]
+pub(crate) struct TokenizeResponse(pub Vec<Vec<SimpleToken>>);
+
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum InputIds {
+    Single(Vec<u32>),
+    Batch(Vec<Vec<u32>>),
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct DecodeRequest {
+    pub ids: InputIds,
+    #[serde(default = "default_skip_special_tokens")]
+    #[schema(default = "true", example = "true")]
+    pub skip_special_tokens: bool,
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+}
+
+fn default_skip_special_tokens() -> bool {
+    true
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!(["test"]))]
+pub(crate) struct DecodeResponse(pub Vec<String>);
+
 #[derive(Serialize, ToSchema)]
 #[schema(example = json!([[0.0, 1.0, 2.0]]))]
-pub(crate) struct EmbedResponse(pub Vec<Vec<f32>>);
+pub(crate) struct EmbedResponse(pub Vec<Embedding>);
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereErrorResponse {
+    pub message: String,
+}
 
 #[derive(Serialize, ToSchema)]
 pub(crate) struct OpenAICompatErrorResponse {
@@ -1,4 +1,6 @@
 /// Text Embedding Inference Webserver
+mod auth;
+mod config;
 mod logging;
 mod prometheus;
 
@@ -19,62 +21,340 @@ mod shutdown;
 
 use ::http::HeaderMap;
 use anyhow::{anyhow, Context, Result};
+use auth::ApiKeys;
+use clap::ValueEnum;
+use config::{ModelLimits, RuntimeLimits};
+use futures::future::join_all;
 use hf_hub::api::tokio::ApiBuilder;
 use hf_hub::{Repo, RepoType};
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use text_embeddings_backend::DType;
-use text_embeddings_core::download::{download_artifacts, download_pool_config};
-use text_embeddings_core::infer::Infer;
-use text_embeddings_core::queue::Queue;
-use text_embeddings_core::tokenization::Tokenization;
+use std::{fmt, fs};
+use text_embeddings_backend::{BackendError, DType};
+use text_embeddings_core::cache::Cache;
+use text_embeddings_core::classification::FunctionToApply;
+use text_embeddings_core::colbert::{ColbertConfig, InputType};
+use text_embeddings_core::download::{
+    download_artifacts, download_colbert_metadata, download_dense_module,
+    download_max_length_configs, download_pool_config, download_prompts_config,
+    download_st_modules,
+};
+use text_embeddings_core::infer::{Infer, InferAllResponse, InferResponse};
+use text_embeddings_core::quantization::{self, QuantizationRanges};
+use text_embeddings_core::queue::{Priority, Queue};
+use text_embeddings_core::similarity::{self, SimilarityMetric};
+use text_embeddings_core::token_classification::{self, AggregationStrategy, Entity};
+use text_embeddings_core::tokenization::{Tokenization, Truncation};
 use text_embeddings_core::TextEmbeddingsError;
 use tokenizers::decoders::metaspace::PrependScheme;
 use tokenizers::pre_tokenizers::sequence::Sequence;
 use tokenizers::{PreTokenizerWrapper, Tokenizer};
+use tokio::sync::OwnedSemaphorePermit;
 use tracing::Span;
 
+pub use config::{Config, EndpointConfig, EndpointsConfig, ModelSettings, Settings};
 pub use logging::init_logging;
 
 /// Create entrypoint
+pub async fn run(settings: Settings) -> Result<()> {
+    let Settings {
+        model_id,
+        revision,
+        additional_model_ids,
+        tokenization_workers,
+        dtype,
+        pooling,
+        max_input_length,
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_batch_requests,
+        max_client_batch_size,
+        embedding_cache_size_mb,
+        hf_api_token,
+        hostname,
+        port,
+        uds_path,
+        huggingface_hub_cache,
+        otlp_endpoint,
+        api_keys_file,
+        cors_allow_origin,
+        config_path,
+        config,
+        fixed_args,
+        ..
+    } = settings;
+
+    // The first model is the default one. `revision`, `pooling` and `max_input_length` only
+    // apply to it, additional models get their own from the `models` of the configuration file.
+    let mut model_args = vec![ModelArgs {
+        model_id,
+        revision,
+        pooling,
+        max_input_length,
+        limits: ModelLimits::default(),
+        max_batch_requests,
+    }];
+    model_args.extend(additional_model_ids.into_iter().map(|model_id| ModelArgs {
+        model_id,
+        revision: None,
+        pooling: None,
+        max_input_length: None,
+        limits: ModelLimits::default(),
+        max_batch_requests,
+    }));
+    for settings in &config.models {
+        model_args.push(ModelArgs::from_settings(settings, max_batch_requests)?);
+    }
+
+    // Models are selected by name
+    let mut names = HashSet::with_capacity(model_args.len());
+    for args in &model_args {
+        if !names.insert(args.model_id.as_str()) {
+            return Err(anyhow!(
+                "Model `{}` is served more than once",
+                args.model_id
+            ));
+        }
+    }
+
+    // Where each model is loaded from
+    let mut model_paths: Vec<String> = model_args
+        .iter()
+        .map(|args| args.model_id.clone())
+        .collect();
+
+    // API keys stored in Consul
+    #[cfg(not(feature = "consul"))]
+    let consul_api_keys: Option<String> = None;
+    #[cfg(feature = "consul")]
+    let consul_api_keys = {
+        let models = model_args
+            .iter()
+            .map(|args| {
+                (
+                    args.model_id.clone(),
+                    args.revision.clone().unwrap_or("".to_string()),
+                )
+            })
+            .collect();
+        match starter::start_app(models).await {
+            Ok((new_paths, api_keys)) => {
+                model_paths.clear();
+                model_paths.extend(new_paths);
+                api_keys
+            }
+            Err(err) => {
+                tracing::warn!("Could not start app: {:?}", err);
+                return Err(anyhow!("Could not start app: {:?}", err));
+            }
+        }
+    };
+
+    // Authentication is only enabled if API keys are configured. The keys file takes precedence
+    let api_keys = match (api_keys_file, consul_api_keys) {
+        (Some(path), _) => Some(ApiKeys::from_file(&path)?),
+        (None, Some(json)) => {
+            Some(ApiKeys::from_json(&json).context("Invalid `apiKeys` in Consul")?)
+        }
+        (None, None) => None,
+    };
+    if let Some(api_keys) = &api_keys {
+        tracing::info!(
+            "API key authentication enabled with {} keys",
+            api_keys.len()
+        );
+    }
+
+    // Tokenization workers are split between the models, each one gets at least one
+    let tokenization_workers = tokenization_workers.unwrap_or_else(num_cpus::get_physical);
+    let n_models = model_args.len();
+    let model_tokenization_workers = |i: usize| {
+        (tokenization_workers / n_models + usize::from(i < tokenization_workers % n_models)).max(1)
+    };
+
+    // Get dtype
+    let dtype = dtype.unwrap_or({
+        #[cfg(any(feature = "accelerate", feature = "mkl", feature = "mkl-dynamic"))]
+        {
+            DType::Float32
+        }
+        #[cfg(not(any(feature = "accelerate", feature = "mkl", feature = "mkl-dynamic")))]
+        {
+            DType::Float16
+        }
+    });
+
+    // A single cache shared by all models
+    let cache = (embedding_cache_size_mb > 0).then(|| {
+        tracing::info!("Embedding cache enabled with {embedding_cache_size_mb} MB");
+        Cache::new(embedding_cache_size_mb * 1024 * 1024)
+    });
+
+    let mut models = Vec::with_capacity(model_args.len());
+    for (i, (args, model_path)) in model_args.into_iter().zip(model_paths).enumerate() {
+        let name = args.model_id;
+        // Backends communicating over a unix socket each need their own
+        let uds_path = match i {
+            0 => uds_path.clone(),
+            i => format!("{uds_path}-{i}"),
+        };
+
+        tracing::info!("Loading model `{name}`");
+        let (infer, info) = load_model(
+            model_path,
+            args.revision,
+            model_tokenization_workers(i),
+            dtype.clone(),
+            args.pooling,
+            args.max_input_length,
+            args.limits,
+            args.limits
+                .max_concurrent_requests
+                .unwrap_or(max_concurrent_requests),
+            args.limits.max_batch_tokens.unwrap_or(max_batch_tokens),
+            args.max_batch_requests,
+            max_client_batch_size,
+            hf_api_token.clone(),
+            uds_path,
+            huggingface_hub_cache.clone(),
+            otlp_endpoint.clone(),
+            cache.as_ref().map(|cache| cache.for_model(&name)),
+            config
+                .quantization_ranges
+                .get(&name)
+                .cloned()
+                .map(QuantizationRanges::from),
+            config.prompts.get(&name).cloned().unwrap_or_default(),
+        )
+        .await
+        .with_context(|| format!("Could not load model `{name}`"))?;
+        models.push((name, infer, info));
+    }
+    let models = Models::new(models);
+
+    // Limits that can be updated at runtime from the configuration file
+    let limits = Arc::new(RuntimeLimits::new(
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_client_batch_size,
+        config.endpoints.clone(),
+        cors_allow_origin,
+    )?);
+
+    if let Some(path) = config_path {
+        tokio::spawn(config::watch(
+            path,
+            config,
+            models.clone(),
+            limits.clone(),
+            fixed_args,
+        ));
+    }
+
+    let addr = match hostname.parse() {
+        Ok(ip) => SocketAddr::new(ip, port),
+        Err(_) => {
+            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)
+        }
+    };
+
+    let prom_builder = prometheus::prometheus_builer(models.max_input_length())?;
+
+    #[cfg(all(feature = "grpc", feature = "http"))]
+    compile_error!("Features `http` and `grpc` cannot be enabled at the same time.");
+
+    #[cfg(not(any(feature = "http", feature = "grpc")))]
+    compile_error!("Either feature `http` or `grpc` must be enabled.");
+
+    #[cfg(feature = "http")]
+    {
+        let server = tokio::spawn(async move {
+            http::server::run(models, limits, api_keys, addr, prom_builder).await
+        });
+        tracing::info!("Ready");
+        server.await??;
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        let server = tokio::spawn(async move {
+            grpc::server::run(models, limits, api_keys, addr, prom_builder).await
+        });
+        tracing::info!("Ready");
+        server.await??;
+    }
+
+    Ok(())
+}
+
+/// A model to load and the settings it was declared with
+struct ModelArgs {
+    model_id: String,
+    revision: Option<String>,
+    pooling: Option<text_embeddings_backend::Pool>,
+    max_input_length: Option<usize>,
+    limits: ModelLimits,
+    max_batch_requests: Option<usize>,
+}
+
+impl ModelArgs {
+    /// Unset `max_batch_requests` defaults to the global value
+    fn from_settings(settings: &ModelSettings, max_batch_requests: Option<usize>) -> Result<Self> {
+        let pooling = settings
+            .pooling
+            .as_ref()
+            .map(|pooling| text_embeddings_backend::Pool::from_str(pooling, true))
+            .transpose()
+            .map_err(|err| anyhow!("Invalid `pooling` of model `{}`: {err}", settings.model_id))?;
+        if settings.max_concurrent_requests == Some(0) {
+            return Err(anyhow!(
+                "`max_concurrent_requests` of model `{}` must be > 0",
+                settings.model_id
+            ));
+        }
+
+        Ok(Self {
+            model_id: settings.model_id.clone(),
+            revision: settings.revision.clone(),
+            pooling,
+            max_input_length: settings.max_input_length,
+            limits: ModelLimits {
+                max_concurrent_requests: settings.max_concurrent_requests,
+                max_batch_tokens: settings.max_batch_tokens,
+            },
+            max_batch_requests: settings.max_batch_requests.or(max_batch_requests),
+        })
+    }
+}
+
+/// Load a model and start its tokenization, queue and inference tasks
 #[allow(clippy::too_many_arguments)]
-pub async fn run(
-    mut model_id: String,
+async fn load_model(
+    model_id: String,
     revision: Option<String>,
-    tokenization_workers: Option<usize>,
-    dtype: Option<DType>,
+    tokenization_workers: usize,
+    dtype: DType,
     pooling: Option<text_embeddings_backend::Pool>,
+    max_input_length: Option<usize>,
+    limits: ModelLimits,
     max_concurrent_requests: usize,
     max_batch_tokens: usize,
     max_batch_requests: Option<usize>,
     max_client_batch_size: usize,
     hf_api_token: Option<String>,
-    hostname: Option<String>,
-    port: u16,
-    uds_path: Option<String>,
+    uds_path: String,
     huggingface_hub_cache: Option<String>,
     otlp_endpoint: Option<String>,
-) -> Result<()> {
-    #[cfg(feature = "consul")]
-    {
-        match starter::start_app(model_id.clone(), revision.clone().unwrap_or("".to_string())).await
-        {
-            Ok(new_model) => {
-                model_id.clear();
-                model_id.push_str(&new_model);
-            }
-            Err(err) => {
-                tracing::warn!("Could not start app: {:?}", err);
-                return Err(anyhow!("Could not start app: {:?}", err));
-            }
-        }
-    }
-
+    cache: Option<Cache>,
+    quantization_ranges: Option<QuantizationRanges>,
+    prompts: HashMap<String, String>,
+) -> Result<(Infer, Info)> {
     let model_id_path = Path::new(&model_id);
     let model_root = if model_id_path.exists() && model_id_path.is_dir() {
         // Using a local model
@@ -101,6 +381,24 @@ pub async fn run(
             let _ = download_pool_config(&api_repo).await;
         }
 
+        // Optionally download the configs setting the maximum sequence length
+        download_max_length_configs(&api_repo).await;
+
+        // Optionally download the config holding the prompts
+        download_prompts_config(&api_repo).await;
+
+        // Optionally download the metadata of ColBERT checkpoints
+        download_colbert_metadata(&api_repo).await;
+
+        // Optionally download the sentence-transformers modules
+        if let Ok(modules_path) = download_st_modules(&api_repo).await {
+            for path in dense_module_paths(&modules_path)? {
+                download_dense_module(&api_repo, &path)
+                    .await
+                    .context(format!("Could not download the `{path}` module"))?;
+            }
+        }
+
         // Download model from the Hub
         download_artifacts(&api_repo)
             .await
@@ -113,6 +411,18 @@ pub async fn run(
     let config: ModelConfig =
         serde_json::from_str(&config).context("Failed to parse `config.json`")?;
 
+    // Load ColBERT metadata
+    let colbert_metadata_path = model_root.join("artifact.metadata");
+    let colbert_metadata = if colbert_metadata_path.exists() {
+        let metadata = fs::read_to_string(colbert_metadata_path)
+            .context("Failed to read `artifact.metadata`")?;
+        let metadata: ColbertMetadata =
+            serde_json::from_str(&metadata).context("Failed to parse `artifact.metadata`")?;
+        Some(metadata)
+    } else {
+        None
+    };
+
     // Set model type from config
     let backend_model_type = {
         // Check if the model is a classifier
@@ -130,7 +440,15 @@ pub async fn run(
                     "`--pooling` arg is set but model is a classifier. Ignoring `--pooling` arg."
                 );
             }
-            text_embeddings_backend::ModelType::Classifier
+            let token_classifier = config
+                .architectures
+                .iter()
+                .any(|arch| arch.ends_with("ForTokenClassification"));
+            if token_classifier {
+                text_embeddings_backend::ModelType::TokenClassifier
+            } else {
+                text_embeddings_backend::ModelType::Classifier
+            }
         } else {
             // Set pooling
             let pool = match pooling {
@@ -138,15 +456,37 @@ pub async fn run(
                 None => {
                     // Load pooling config
                     let config_path = model_root.join("1_Pooling/config.json");
-                    let config = fs::read_to_string(config_path).context("The `--pooling` arg is not set and we could not find a pooling configuration (`1_Pooling/config.json`) for this model.")?;
-                    let config: PoolConfig = serde_json::from_str(&config)
-                        .context("Failed to parse `1_Pooling/config.json`")?;
-                    if config.pooling_mode_cls_token {
+                    let masked_lm = config
+                        .architectures
+                        .iter()
+                        .any(|arch| arch.ends_with("ForMaskedLM"));
+                    if !config_path.exists() && masked_lm {
+                        // Masked language models without pooling config are SPLADE models
+                        tracing::info!("Using `splade` pooling for this masked language model");
+                        text_embeddings_backend::Pool::Splade
+                    } else if !config_path.exists() && colbert_metadata.is_some() {
+                        // ColBERT models only return per-token vectors
+                        tracing::info!("Using `cls` pooling for this ColBERT model");
                         text_embeddings_backend::Pool::Cls
-                    } else if config.pooling_mode_mean_tokens {
-                        text_embeddings_backend::Pool::Mean
                     } else {
-                        return Err(anyhow!("Pooling config {config:?} is not supported"));
+                        let config = fs::read_to_string(config_path).context("The `--pooling` arg is not set and we could not find a pooling configuration (`1_Pooling/config.json`) for this model.")?;
+                        let config: PoolConfig = serde_json::from_str(&config)
+                            .context("Failed to parse `1_Pooling/config.json`")?;
+                        if config.pooling_mode_cls_token {
+                            text_embeddings_backend::Pool::Cls
+                        } else if config.pooling_mode_mean_tokens {
+                            text_embeddings_backend::Pool::Mean
+                        } else if config.pooling_mode_mean_sqrt_len_tokens {
+                            text_embeddings_backend::Pool::MeanSqrtLen
+                        } else if config.pooling_mode_weightedmean_tokens {
+                            text_embeddings_backend::Pool::WeightedMean
+                        } else if config.pooling_mode_max_tokens {
+                            text_embeddings_backend::Pool::Max
+                        } else if config.pooling_mode_lasttoken {
+                            text_embeddings_backend::Pool::LastToken
+                        } else {
+                            return Err(anyhow!("Pooling config {config:?} is not supported"));
+                        }
                     }
                 }
             };
@@ -156,6 +496,17 @@ pub async fn run(
 
     // Info model type
     let model_type = match &backend_model_type {
+        text_embeddings_backend::ModelType::TokenClassifier => {
+            ModelType::TokenClassifier(ClassifierModel {
+                id2label: config
+                    .id2label
+                    .context("`config.json` does not contain `id2label`")?,
+                label2id: config
+                    .label2id
+                    .context("`config.json` does not contain `label2id`")?,
+                problem_type: config.problem_type,
+            })
+        }
         text_embeddings_backend::ModelType::Classifier => {
             let id2label = config
                 .id2label
@@ -166,6 +517,7 @@ pub async fn run(
                 label2id: config
                     .label2id
                     .context("`config.json` does not contain `label2id`")?,
+                problem_type: config.problem_type,
             };
             if n_classes > 1 {
                 ModelType::Classifier(classifier_model)
@@ -176,10 +528,31 @@ pub async fn run(
         text_embeddings_backend::ModelType::Embedding(pool) => {
             ModelType::Embedding(EmbeddingModel {
                 pooling: pool.to_string(),
+                hidden_size: embedding_size(&model_root, config.hidden_size)?,
+                num_hidden_layers: config.num_hidden_layers,
+                colbert_dim: colbert_metadata.as_ref().map(|metadata| metadata.dim),
             })
         }
     };
 
+    if let Some(ranges) = &quantization_ranges {
+        let hidden_size = match &model_type {
+            ModelType::Embedding(embedding_model) => embedding_model.hidden_size,
+            _ => {
+                return Err(anyhow!(
+                    "Quantization ranges can only be set for embedding models"
+                ))
+            }
+        };
+        if ranges.min.len() != hidden_size || ranges.max.len() != hidden_size {
+            return Err(anyhow!(
+                "Quantization ranges must have {hidden_size} values. Given: {} `min` and {} `max` values",
+                ranges.min.len(),
+                ranges.max.len()
+            ));
+        }
+    }
+
     // Load tokenizer
     let tokenizer_path = model_root.join("tokenizer.json");
     let mut tokenizer = Tokenizer::from_file(tokenizer_path).expect(
@@ -234,38 +607,57 @@ pub async fn run(
     } else {
         0
     };
-    let max_input_length = config.max_position_embeddings - position_offset;
+    let (max_input_length, max_input_length_source) = resolve_max_input_length(
+        &model_root,
+        config.max_position_embeddings - position_offset,
+        max_input_length,
+    )?;
+    tracing::info!("Maximum input length: {max_input_length} (from {max_input_length_source})");
 
-    let tokenization_workers = tokenization_workers.unwrap_or_else(num_cpus::get_physical);
+    // The queue never schedules an entry longer than a batch
+    if max_batch_tokens < max_input_length {
+        let setting = match limits.max_batch_tokens {
+            Some(_) => "`max_batch_tokens` of this model",
+            None => "`max_batch_tokens`",
+        };
+        return Err(anyhow!(
+            "{setting} must be >= `max_input_length`. Given: {max_batch_tokens} and {max_input_length}"
+        ));
+    }
+
+    let colbert = match (&colbert_metadata, &model_type) {
+        (Some(metadata), ModelType::Embedding(_)) => {
+            let colbert = colbert_config(metadata, &tokenizer, max_input_length)?;
+            tracing::info!("ColBERT model: {colbert:?}");
+            Some(colbert)
+        }
+        _ => None,
+    };
+
+    let (prompts, default_prompt_name) = resolve_prompts(&model_root, prompts)?;
+    if !prompts.is_empty() {
+        let mut names: Vec<&String> = prompts.keys().collect();
+        names.sort();
+        tracing::info!("Prompts: {names:?}");
+    }
 
     // Tokenization logic
     let tokenization = Tokenization::new(
         tokenization_workers,
         tokenizer,
+        config.vocab_size,
         max_input_length,
         position_offset,
     );
 
-    // Get dtype
-    let dtype = dtype.unwrap_or({
-        #[cfg(any(feature = "accelerate", feature = "mkl", feature = "mkl-dynamic"))]
-        {
-            DType::Float32
-        }
-        #[cfg(not(any(feature = "accelerate", feature = "mkl", feature = "mkl-dynamic")))]
-        {
-            DType::Float16
-        }
-    });
-
     // Create backend
     tracing::info!("Starting model backend");
     let backend = text_embeddings_backend::Backend::new(
         model_root,
         dtype.clone(),
         backend_model_type,
-        uds_path.unwrap_or("/tmp/text-embeddings-inference-server".to_string()),
-        otlp_endpoint.clone(),
+        uds_path,
+        otlp_endpoint,
     )
     .context("Could not create backend")?;
     backend
@@ -291,7 +683,15 @@ pub async fn run(
     );
 
     // Create infer task
-    let infer = Infer::new(tokenization, queue, max_concurrent_requests, backend);
+    let infer = Infer::new(
+        tokenization,
+        queue,
+        max_concurrent_requests,
+        backend,
+        cache,
+        quantization_ranges,
+        colbert,
+    );
 
     // Endpoint info
     let info = Info {
@@ -299,50 +699,22 @@ pub async fn run(
         model_sha: revision,
         model_dtype: dtype.to_string(),
         model_type,
+        prompts,
+        default_prompt_name,
         max_concurrent_requests,
         max_input_length,
+        max_input_length_source,
         max_batch_tokens,
         tokenization_workers,
         max_batch_requests,
         max_client_batch_size,
+        limits,
         version: env!("CARGO_PKG_VERSION"),
         sha: option_env!("VERGEN_GIT_SHA"),
         docker_label: option_env!("DOCKER_LABEL"),
     };
 
-    let addr = match hostname.unwrap_or("0.0.0.0".to_string()).parse() {
-        Ok(ip) => SocketAddr::new(ip, port),
-        Err(_) => {
-            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)
-        }
-    };
-
-    let prom_builder = prometheus::prometheus_builer(info.max_input_length)?;
-
-    #[cfg(all(feature = "grpc", feature = "http"))]
-    compile_error!("Features `http` and `grpc` cannot be enabled at the same time.");
-
-    #[cfg(not(any(feature = "http", feature = "grpc")))]
-    compile_error!("Either feature `http` or `grpc` must be enabled.");
-
-    #[cfg(feature = "http")]
-    {
-        let server =
-            tokio::spawn(async move { http::server::run(infer, info, addr, prom_builder).await });
-        tracing::info!("Ready");
-        server.await??;
-    }
-
-    #[cfg(feature = "grpc")]
-    {
-        let server =
-            tokio::spawn(async move { grpc::server::run(infer, info, addr, prom_builder).await });
-        tracing::info!("Ready");
-        server.await??;
-    }
-
-    Ok(())
+    Ok((infer, info))
 }
 
 #[derive(Debug, Deserialize)]
@@ -351,9 +723,195 @@ pub struct ModelConfig {
     pub model_type: String,
     #[serde(alias = "n_positions")]
     pub max_position_embeddings: usize,
+    #[serde(alias = "d_model", alias = "n_embd")]
+    pub hidden_size: usize,
+    pub vocab_size: usize,
+    #[serde(alias = "num_layers", alias = "n_layer")]
+    pub num_hidden_layers: Option<usize>,
     pub pad_token_id: usize,
     pub id2label: Option<HashMap<String, String>>,
     pub label2id: Option<HashMap<String, usize>>,
+    pub problem_type: Option<ProblemType>,
+}
+
+/// Entry of the sentence-transformers `modules.json`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StModuleConfig {
+    path: String,
+    #[serde(rename = "type")]
+    module_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DenseConfig {
+    out_features: usize,
+}
+
+const ST_DENSE_MODULE: &str = "sentence_transformers.models.Dense";
+
+/// Paths of the `Dense` modules listed in `modules.json`
+fn dense_module_paths(modules_path: &Path) -> Result<Vec<String>> {
+    let modules = fs::read_to_string(modules_path).context("Failed to read `modules.json`")?;
+    let modules: Vec<StModuleConfig> =
+        serde_json::from_str(&modules).context("Failed to parse `modules.json`")?;
+    Ok(modules
+        .into_iter()
+        .filter(|module| module.module_type == ST_DENSE_MODULE)
+        .map(|module| module.path)
+        .collect())
+}
+
+/// Size of the embeddings returned by the model. `Dense` modules project the pooled embeddings
+fn embedding_size(model_root: &Path, hidden_size: usize) -> Result<usize> {
+    let modules_path = model_root.join("modules.json");
+    if !modules_path.exists() {
+        return Ok(hidden_size);
+    }
+
+    let mut embedding_size = hidden_size;
+    for path in dense_module_paths(&modules_path)? {
+        let config = fs::read_to_string(model_root.join(&path).join("config.json"))
+            .context(format!("`{path}/config.json` not found"))?;
+        let config: DenseConfig = serde_json::from_str(&config)
+            .context(format!("Failed to parse `{path}/config.json`"))?;
+        embedding_size = config.out_features;
+    }
+    Ok(embedding_size)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SentenceBertConfig {
+    max_seq_length: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenizerConfig {
+    // Set to a very large float when the tokenizer has no limit
+    model_max_length: Option<f64>,
+}
+
+/// Maximum number of tokens of the model inputs and where this limit comes from.
+/// The position embeddings limit can only be lowered
+fn resolve_max_input_length(
+    model_root: &Path,
+    max_position_length: usize,
+    max_input_length: Option<usize>,
+) -> Result<(usize, MaxInputLengthSource)> {
+    if let Some(max_input_length) = max_input_length {
+        if max_input_length == 0 || max_input_length > max_position_length {
+            return Err(anyhow!(
+                "`--max-input-length` must be > 0 and <= {max_position_length}. Given: {max_input_length}"
+            ));
+        }
+        return Ok((max_input_length, MaxInputLengthSource::Argument));
+    }
+
+    let st_config_path = model_root.join("sentence_bert_config.json");
+    if st_config_path.exists() {
+        let config = fs::read_to_string(st_config_path)
+            .context("Failed to read `sentence_bert_config.json`")?;
+        let config: SentenceBertConfig =
+            serde_json::from_str(&config).context("Failed to parse `sentence_bert_config.json`")?;
+        if let Some(max_seq_length) = config.max_seq_length {
+            return Ok((
+                max_seq_length.min(max_position_length),
+                MaxInputLengthSource::SentenceBertConfig,
+            ));
+        }
+    }
+
+    let tokenizer_config_path = model_root.join("tokenizer_config.json");
+    if tokenizer_config_path.exists() {
+        let config = fs::read_to_string(tokenizer_config_path)
+            .context("Failed to read `tokenizer_config.json`")?;
+        let config: TokenizerConfig =
+            serde_json::from_str(&config).context("Failed to parse `tokenizer_config.json`")?;
+        match config.model_max_length {
+            Some(model_max_length) if model_max_length < max_position_length as f64 => {
+                return Ok((
+                    model_max_length as usize,
+                    MaxInputLengthSource::TokenizerConfig,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok((max_position_length, MaxInputLengthSource::ModelConfig))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SentenceTransformersConfig {
+    #[serde(default)]
+    prompts: HashMap<String, String>,
+    default_prompt_name: Option<String>,
+}
+
+/// Prompts of `config_sentence_transformers.json` extended with the configured ones, and the
+/// name of the prompt applied when a request does not select one
+fn resolve_prompts(
+    model_root: &Path,
+    configured_prompts: HashMap<String, String>,
+) -> Result<(HashMap<String, String>, Option<String>)> {
+    let mut prompts = HashMap::new();
+    let mut default_prompt_name = None;
+
+    let st_config_path = model_root.join("config_sentence_transformers.json");
+    if st_config_path.exists() {
+        let config = fs::read_to_string(st_config_path)
+            .context("Failed to read `config_sentence_transformers.json`")?;
+        let config: SentenceTransformersConfig = serde_json::from_str(&config)
+            .context("Failed to parse `config_sentence_transformers.json`")?;
+        prompts = config.prompts;
+        default_prompt_name = config.default_prompt_name;
+    }
+    prompts.extend(configured_prompts);
+
+    if let Some(name) = &default_prompt_name {
+        if !prompts.contains_key(name) {
+            return Err(anyhow!(
+                "Default prompt `{name}` is not one of the model prompts"
+            ));
+        }
+    }
+
+    Ok((prompts, default_prompt_name))
+}
+
+/// Subset of the `artifact.metadata` of ColBERT checkpoints
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ColbertMetadata {
+    query_token_id: String,
+    doc_token_id: String,
+    query_maxlen: usize,
+    doc_maxlen: usize,
+    dim: usize,
+}
+
+/// Resolve the ColBERT markers in the vocabulary. Lengths are capped to the model max input
+/// length
+fn colbert_config(
+    metadata: &ColbertMetadata,
+    tokenizer: &Tokenizer,
+    max_input_length: usize,
+) -> Result<ColbertConfig> {
+    let token_id = |token: &str| {
+        tokenizer
+            .token_to_id(token)
+            .context(format!("`{token}` is not in the vocabulary"))
+    };
+    let mask_token_id = match tokenizer.token_to_id("[MASK]") {
+        Some(id) => id,
+        None => token_id("<mask>")?,
+    };
+
+    Ok(ColbertConfig {
+        query_marker_id: token_id(&metadata.query_token_id)?,
+        document_marker_id: token_id(&metadata.doc_token_id)?,
+        mask_token_id,
+        query_maxlen: metadata.query_maxlen.min(max_input_length),
+        document_maxlen: metadata.doc_maxlen.min(max_input_length),
+    })
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -362,6 +920,37 @@ pub struct PoolConfig {
     pooling_mode_mean_tokens: bool,
     pooling_mode_max_tokens: bool,
     pooling_mode_mean_sqrt_len_tokens: bool,
+    // Missing from the configurations of older sentence-transformers versions
+    #[serde(default)]
+    pooling_mode_weightedmean_tokens: bool,
+    #[serde(default)]
+    pooling_mode_lasttoken: bool,
+}
+
+/// Where the `max_input_length` of a model comes from
+#[derive(Clone, Copy, Debug, Serialize)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MaxInputLengthSource {
+    /// `--max-input-length` argument
+    Argument,
+    /// `max_seq_length` of `sentence_bert_config.json`
+    SentenceBertConfig,
+    /// `model_max_length` of `tokenizer_config.json`
+    TokenizerConfig,
+    /// `max_position_embeddings` of `config.json`
+    ModelConfig,
+}
+
+impl fmt::Display for MaxInputLengthSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaxInputLengthSource::Argument => write!(f, "`--max-input-length`"),
+            MaxInputLengthSource::SentenceBertConfig => write!(f, "`sentence_bert_config.json`"),
+            MaxInputLengthSource::TokenizerConfig => write!(f, "`tokenizer_config.json`"),
+            MaxInputLengthSource::ModelConfig => write!(f, "`config.json`"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -369,6 +958,23 @@ pub struct PoolConfig {
 pub struct EmbeddingModel {
     #[cfg_attr(feature = "http", schema(example = "cls"))]
     pub pooling: String,
+    #[cfg_attr(feature = "http", schema(example = "768"))]
+    pub hidden_size: usize,
+    /// Layers `/embed_all` can read the hidden states of
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "12"))]
+    pub num_hidden_layers: Option<usize>,
+    /// Size of the per-token vectors of ColBERT models
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "null"))]
+    pub colbert_dim: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemType {
+    Regression,
+    SingleLabelClassification,
+    MultiLabelClassification,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -378,6 +984,24 @@ pub struct ClassifierModel {
     pub id2label: HashMap<String, String>,
     #[cfg_attr(feature = "http", schema(example = json!({"LABEL": 0})))]
     pub label2id: HashMap<String, usize>,
+    /// `problem_type` of `config.json`
+    #[cfg_attr(
+        feature = "http",
+        schema(nullable = true, example = "single_label_classification")
+    )]
+    pub problem_type: Option<ProblemType>,
+}
+
+impl ClassifierModel {
+    /// Function applied to the logits when the request does not select one
+    pub(crate) fn default_function_to_apply(&self) -> FunctionToApply {
+        match self.problem_type {
+            Some(ProblemType::MultiLabelClassification) => FunctionToApply::Sigmoid,
+            Some(ProblemType::Regression) => FunctionToApply::None,
+            _ if self.id2label.len() > 1 => FunctionToApply::Softmax,
+            _ => FunctionToApply::Sigmoid,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -387,6 +1011,8 @@ pub enum ModelType {
     Classifier(ClassifierModel),
     Embedding(EmbeddingModel),
     Reranker(ClassifierModel),
+    #[serde(rename = "token_classifier")]
+    TokenClassifier(ClassifierModel),
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -403,11 +1029,17 @@ pub struct Info {
     #[cfg_attr(feature = "http", schema(example = "float16"))]
     pub model_dtype: String,
     pub model_type: ModelType,
+    /// Prompts that embedding requests select with `prompt_name`
+    #[cfg_attr(feature = "http", schema(example = json!({"query": "query: "})))]
+    pub prompts: HashMap<String, String>,
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "null"))]
+    pub default_prompt_name: Option<String>,
     /// Router Parameters
     #[cfg_attr(feature = "http", schema(example = "128"))]
     pub max_concurrent_requests: usize,
     #[cfg_attr(feature = "http", schema(example = "512"))]
     pub max_input_length: usize,
+    pub max_input_length_source: MaxInputLengthSource,
     #[cfg_attr(feature = "http", schema(example = "2048"))]
     pub max_batch_tokens: usize,
     #[cfg_attr(
@@ -419,6 +1051,9 @@ pub struct Info {
     pub max_client_batch_size: usize,
     #[cfg_attr(feature = "http", schema(example = "4"))]
     pub tokenization_workers: usize,
+    /// Limits of the model that are not reloaded with the global ones
+    #[serde(skip)]
+    pub(crate) limits: ModelLimits,
     /// Router Info
     #[cfg_attr(feature = "http", schema(example = "0.5.0"))]
     pub version: &'static str,
@@ -428,6 +1063,404 @@ pub struct Info {
     pub docker_label: Option<&'static str>,
 }
 
+/// Header (or gRPC metadata key) used to select a model when the request body does not set one
+pub(crate) const MODEL_HEADER: &str = "x-model-id";
+
+/// Header (or gRPC metadata key) used to set the priority class of a request
+pub(crate) const PRIORITY_HEADER: &str = "x-priority";
+
+/// Parse the value of the priority header. Requests without a priority are `normal`
+pub(crate) fn parse_priority(value: Option<&str>) -> Result<Priority, ErrorResponse> {
+    match value {
+        None => Ok(Priority::default()),
+        Some(value) => value.parse().map_err(|err: String| {
+            metrics::increment_counter!("te_request_failure", "err" => "validation");
+            tracing::error!("{err}");
+            ErrorResponse {
+                error: err,
+                error_type: ErrorType::Validation,
+            }
+        }),
+    }
+}
+
+/// Check the number of dimensions requested for the embeddings of a model
+pub(crate) fn validate_dimensions(
+    dimensions: Option<usize>,
+    info: &Info,
+) -> Result<(), ErrorResponse> {
+    match (dimensions, &info.model_type) {
+        (Some(dimensions), ModelType::Embedding(model))
+            if dimensions == 0 || dimensions > model.hidden_size =>
+        {
+            let message = format!(
+                "`dimensions` must be between 1 and {}. Given: {dimensions}",
+                model.hidden_size
+            );
+            metrics::increment_counter!("te_request_failure", "err" => "validation");
+            tracing::error!("{message}");
+            Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn validate_layer(layer: Option<usize>, info: &Info) -> Result<(), ErrorResponse> {
+    match (layer, &info.model_type) {
+        (Some(layer), ModelType::Embedding(model))
+            if model
+                .num_hidden_layers
+                .is_some_and(|num_hidden_layers| layer > num_hidden_layers) =>
+        {
+            let message = format!(
+                "`layer` must be <= {}. Given: {layer}",
+                model.num_hidden_layers.unwrap()
+            );
+            metrics::increment_counter!("te_request_failure", "err" => "validation");
+            tracing::error!("{message}");
+            Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// ColBERT vectors are always read from the last layer
+pub(crate) fn validate_input_type(
+    input_type: Option<InputType>,
+    layer: Option<usize>,
+) -> Result<(), ErrorResponse> {
+    if input_type.is_some() && layer.is_some() {
+        let message = "`input_type` and `layer` cannot be set at the same time".to_string();
+        metrics::increment_counter!("te_request_failure", "err" => "validation");
+        tracing::error!("{message}");
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        });
+    }
+    Ok(())
+}
+
+/// Prompt prepended to the inputs of an embedding request: the raw `prompt`, the prompt named
+/// `prompt_name` or the default prompt of the model
+pub(crate) fn resolve_prompt<'a>(
+    prompt_name: Option<&str>,
+    prompt: Option<&'a str>,
+    info: &'a Info,
+) -> Result<Option<&'a str>, ErrorResponse> {
+    let prompt = match (prompt_name, prompt) {
+        (Some(_), Some(_)) => {
+            Err("`prompt` and `prompt_name` cannot be set at the same time".to_string())
+        }
+        (None, Some(prompt)) => Ok(Some(prompt)),
+        (Some(name), None) => match info.prompts.get(name) {
+            Some(prompt) => Ok(Some(prompt.as_str())),
+            None => {
+                let mut names: Vec<&String> = info.prompts.keys().collect();
+                names.sort();
+                Err(format!(
+                    "`prompt_name` must be one of {names:?}. Given: {name}"
+                ))
+            }
+        },
+        (None, None) => Ok(info
+            .default_prompt_name
+            .as_ref()
+            .and_then(|name| info.prompts.get(name))
+            .map(|prompt| prompt.as_str())),
+    };
+
+    prompt.map_err(|message| {
+        metrics::increment_counter!("te_request_failure", "err" => "validation");
+        tracing::error!("{message}");
+        ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        }
+    })
+}
+
+/// Prepend `prompt` to `input`
+pub(crate) fn with_prompt(prompt: Option<&str>, input: String) -> String {
+    match prompt {
+        Some(prompt) => format!("{prompt}{input}"),
+        None => input,
+    }
+}
+
+/// Function applied to the logits of a prediction. `raw_scores` is only used when the request
+/// does not select a function
+pub(crate) fn function_to_apply(
+    requested: Option<FunctionToApply>,
+    raw_scores: bool,
+    info: &Info,
+) -> FunctionToApply {
+    match (requested, &info.model_type) {
+        (Some(function), _) => function,
+        (None, _) if raw_scores => FunctionToApply::None,
+        (None, ModelType::Classifier(classifier) | ModelType::Reranker(classifier)) => {
+            classifier.default_function_to_apply()
+        }
+        (None, ModelType::Embedding(_) | ModelType::TokenClassifier(_)) => FunctionToApply::None,
+    }
+}
+
+/// Entities of `inputs` found by a token classifier model. `inputs` is also tokenized on its own
+/// to get the character offsets of its tokens
+pub(crate) async fn token_classify_entities(
+    infer: &Infer,
+    info: &Info,
+    inputs: String,
+    strategy: AggregationStrategy,
+    priority: Priority,
+    deadline: Option<Instant>,
+    permit: OwnedSemaphorePermit,
+) -> Result<(Vec<Entity>, InferResponse), ErrorResponse> {
+    let id2label = match &info.model_type {
+        ModelType::TokenClassifier(classifier) => &classifier.id2label,
+        _ => {
+            metrics::increment_counter!("te_request_failure", "err" => "model_type");
+            let message = "model is not a token classifier model".to_string();
+            tracing::error!("{message}");
+            return Err(ErrorResponse::from(TextEmbeddingsError::Backend(
+                BackendError::Inference(message),
+            )));
+        }
+    };
+
+    let (encoding, response) = infer
+        .predict_tokens(inputs.clone(), priority, deadline, permit)
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    let entities =
+        token_classification::aggregate(&inputs, &encoding, &response.results, id2label, strategy);
+    Ok((entities, response))
+}
+
+/// Scores of `sentences` against `source`, used by similarity requests and by re-ranking with an
+/// embedding model. Identical inputs are only embedded once.
+/// ColBERT models score with MaxSim instead, ignoring `truncation` and `metric`
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn similarity_scores(
+    infer: &Infer,
+    source: String,
+    sentences: Vec<String>,
+    truncation: Truncation,
+    metric: SimilarityMetric,
+    priority: Priority,
+    deadline: Option<Instant>,
+    start_time: Instant,
+) -> Result<(Vec<f32>, ResponseMetadata), ErrorResponse> {
+    if infer.is_colbert() {
+        return colbert_scores(infer, source, sentences, priority, deadline, start_time).await;
+    }
+
+    let inputs = std::iter::once(source).chain(sentences).collect();
+    let (inputs, indices) = similarity::deduplicate(inputs);
+
+    let mut futures = Vec::with_capacity(inputs.len());
+    let mut compute_chars = 0;
+
+    for input in inputs {
+        compute_chars += input.chars().count();
+
+        let local_infer = infer.clone();
+        futures.push(async move {
+            let permit = local_infer.acquire_permit().await;
+            local_infer
+                .embed(
+                    input,
+                    truncation,
+                    false,
+                    None,
+                    quantization::Precision::Float32,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<InferResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let unique_size = results.len() as u64;
+    let mut embeddings = Vec::with_capacity(results.len());
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+    let mut cache_hit = true;
+
+    for r in results {
+        cache_hit &= r.cached;
+        total_tokenization_time += r.tokenization.as_nanos() as u64;
+        total_queue_time += r.queue.as_nanos() as u64;
+        total_inference_time += r.inference.as_nanos() as u64;
+        total_compute_tokens += r.prompt_tokens;
+        embeddings.push(r.results);
+    }
+
+    let source = &embeddings[indices[0]];
+    let scores = indices[1..]
+        .iter()
+        .map(|&i| similarity::score(source, &embeddings[i], metric))
+        .collect();
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / unique_size),
+        Duration::from_nanos(total_queue_time / unique_size),
+        Duration::from_nanos(total_inference_time / unique_size),
+        cache_hit,
+    );
+
+    Ok((scores, metadata))
+}
+
+/// MaxSim scores of `sentences` against the `source` query with a ColBERT model. Identical
+/// sentences are only embedded once
+async fn colbert_scores(
+    infer: &Infer,
+    source: String,
+    sentences: Vec<String>,
+    priority: Priority,
+    deadline: Option<Instant>,
+    start_time: Instant,
+) -> Result<(Vec<f32>, ResponseMetadata), ErrorResponse> {
+    let (sentences, indices) = similarity::deduplicate(sentences);
+    let inputs = std::iter::once((source, InputType::Query))
+        .chain(sentences.into_iter().map(|s| (s, InputType::Document)));
+
+    let mut futures = Vec::with_capacity(indices.len() + 1);
+    let mut compute_chars = 0;
+
+    for (input, input_type) in inputs {
+        compute_chars += input.chars().count();
+
+        let local_infer = infer.clone();
+        futures.push(async move {
+            let permit = local_infer.acquire_permit().await;
+            local_infer
+                .embed_colbert(input, input_type, priority, deadline, permit)
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<InferAllResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let unique_size = results.len() as u64;
+    let mut vectors = Vec::with_capacity(results.len());
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+
+    for r in results {
+        total_tokenization_time += r.tokenization.as_nanos() as u64;
+        total_queue_time += r.queue.as_nanos() as u64;
+        total_inference_time += r.inference.as_nanos() as u64;
+        total_compute_tokens += r.prompt_tokens;
+        vectors.push(r.results);
+    }
+
+    let query = &vectors[0];
+    let scores = indices
+        .into_iter()
+        .map(|i| similarity::max_sim(query, &vectors[i + 1]))
+        .collect();
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / unique_size),
+        Duration::from_nanos(total_queue_time / unique_size),
+        Duration::from_nanos(total_inference_time / unique_size),
+        false,
+    );
+
+    Ok((scores, metadata))
+}
+
+/// Models served by this router, indexed by the name they were declared with
+#[derive(Clone, Debug)]
+pub struct Models {
+    /// Model used when a request does not select one
+    default: String,
+    models: Arc<HashMap<String, (Infer, Info)>>,
+}
+
+impl Models {
+    /// The first model is the default model
+    fn new(models: Vec<(String, Infer, Info)>) -> Self {
+        let default = models[0].0.clone();
+        let models = models
+            .into_iter()
+            .map(|(name, infer, info)| (name, (infer, info)))
+            .collect();
+        Self {
+            default,
+            models: Arc::new(models),
+        }
+    }
+
+    /// Get the model selected by a request.
+    ///
+    /// When a single model is served, the selection is ignored to stay compatible with
+    /// clients that always send a `model` value.
+    pub(crate) fn get(&self, model: Option<&str>) -> Result<(&Infer, &Info), ErrorResponse> {
+        let name = match model {
+            Some(model) if self.models.len() > 1 => model,
+            _ => return Ok(self.default_model()),
+        };
+
+        match self.models.get(name) {
+            Some((infer, info)) => Ok((infer, info)),
+            None => {
+                metrics::increment_counter!("te_request_failure", "err" => "model_not_found");
+                let message = format!("model `{name}` is not served by this router");
+                tracing::error!("{message}");
+                Err(ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::ModelNotFound,
+                })
+            }
+        }
+    }
+
+    pub(crate) fn default_model(&self) -> (&Infer, &Info) {
+        let (infer, info) = &self.models[&self.default];
+        (infer, info)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&Infer, &Info)> {
+        self.models.values().map(|(infer, info)| (infer, info))
+    }
+
+    fn max_input_length(&self) -> usize {
+        self.iter()
+            .map(|(_, info)| info.max_input_length)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
 #[derive(Serialize)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub enum ErrorType {
@@ -435,7 +1468,11 @@ pub enum ErrorType {
     Backend,
     Overloaded,
     Validation,
+    InvalidInput,
     Tokenizer,
+    ModelNotFound,
+    Unauthorized,
+    DeadlineExceeded,
 }
 
 #[derive(Serialize)]
@@ -450,8 +1487,10 @@ impl From<TextEmbeddingsError> for ErrorResponse {
         let error_type = match err {
             TextEmbeddingsError::Tokenizer(_) => ErrorType::Tokenizer,
             TextEmbeddingsError::Validation(_) => ErrorType::Validation,
+            TextEmbeddingsError::InvalidInput(_) => ErrorType::InvalidInput,
             TextEmbeddingsError::Overloaded(_) => ErrorType::Overloaded,
             TextEmbeddingsError::Backend(_) => ErrorType::Backend,
+            TextEmbeddingsError::DeadlineExceeded => ErrorType::DeadlineExceeded,
         };
         Self {
             error: err.to_string(),
@@ -467,6 +1506,8 @@ struct ResponseMetadata {
     tokenization_time: Duration,
     queue_time: Duration,
     inference_time: Duration,
+    /// All the inputs were served from the cache
+    cache_hit: bool,
 }
 
 impl ResponseMetadata {
@@ -477,6 +1518,7 @@ impl ResponseMetadata {
         tokenization_time: Duration,
         queue_time: Duration,
         inference_time: Duration,
+        cache_hit: bool,
     ) -> Self {
         Self {
             compute_chars,
@@ -485,6 +1527,7 @@ impl ResponseMetadata {
             tokenization_time,
             queue_time,
             inference_time,
+            cache_hit,
         }
     }
 
@@ -571,6 +1614,7 @@ impl From<ResponseMetadata> for HeaderMap {
                 .parse()
                 .unwrap(),
         );
+        headers.insert("x-cache-hit", value.cache_hit.to_string().parse().unwrap());
         headers
     }
 }
@@ -1,7 +1,10 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use opentelemetry::global;
-use text_embeddings_backend::DType;
+use std::path::{Path, PathBuf};
+use text_embeddings_backend::{DType, Pool};
+use text_embeddings_router::{Config, Settings};
 use veil::Redact;
 
 /// App Configuration
@@ -22,9 +25,19 @@ struct Args {
     #[clap(long, env)]
     revision: Option<String>,
 
+    /// Additional models to serve next to `model_id`, as a comma separated list of MODEL_IDs or
+    /// local directories. Requests select a model with the `model` field or the `x-model-id`
+    /// header and default to `model_id`.
+    /// Additional models are loaded from their default revision with their own pooling configuration.
+    /// The `models` of the configuration file declare additional models with their own settings.
+    #[clap(long, env, value_delimiter = ',')]
+    #[redact(partial)]
+    additional_model_ids: Vec<String>,
+
     /// Optionally control the number of tokenizer workers used for payload tokenization, validation
     /// and truncation.
-    /// Default to the number of CPU cores on the machine.
+    /// Default to the number of CPU cores on the machine. The workers are split between the served
+    /// models.
     #[clap(long, env)]
     tokenization_workers: Option<usize>,
 
@@ -39,7 +52,15 @@ struct Args {
     ///
     /// If `pooling` is set, it will override the model pooling configuration
     #[clap(long, env, value_enum)]
-    pooling: Option<text_embeddings_backend::Pool>,
+    pooling: Option<Pool>,
+
+    /// Optionally lower the maximum number of tokens of the inputs.
+    ///
+    /// If `max_input_length` is not set, it is read from `max_seq_length` in
+    /// `sentence_bert_config.json` or `model_max_length` in `tokenizer_config.json`, capped by the
+    /// model position embeddings
+    #[clap(long, env)]
+    max_input_length: Option<usize>,
 
     /// The maximum amount of concurrent requests for this particular deployment.
     /// Having a low limit will refuse clients requests instead of having them
@@ -69,6 +90,11 @@ struct Args {
     #[clap(default_value = "32", long, env)]
     max_client_batch_size: usize,
 
+    /// Maximum memory, in megabytes, used to cache embeddings and predictions of recently seen
+    /// inputs. The cache is shared by all served models. 0 disables the cache
+    #[clap(default_value = "0", long, env)]
+    embedding_cache_size_mb: usize,
+
     /// Your HuggingFace hub token
     #[clap(long, env)]
     #[redact(partial)]
@@ -98,37 +124,149 @@ struct Args {
 
     #[clap(long, env)]
     otlp_endpoint: Option<String>,
+
+    /// Path to a JSON file listing the accepted API keys and their rate limits:
+    /// `[{"key": "...", "name": "team-a", "tokens_per_minute": 100000, "requests_per_second": 10}]`.
+    ///
+    /// If set, or if Consul provides an `apiKeys` value, inference requests must send an
+    /// `Authorization: Bearer <key>` header.
+    #[clap(long, env)]
+    api_keys_file: Option<String>,
+
+    /// Path to a TOML or YAML configuration file.
+    /// Its keys are the names of the other arguments in snake case, plus per endpoint limits in an
+    /// `endpoints` table.
+    /// Arguments set on the command line or through environment variables take precedence, also
+    /// when the file is reloaded.
+    ///
+    /// `max_concurrent_requests`, `max_batch_tokens`, `max_client_batch_size`,
+    /// `cors_allow_origin` and `endpoints` are reloaded without a restart when the file changes.
+    #[clap(long, env)]
+    config: Option<String>,
+
+    #[clap(long, env, value_delimiter = ',')]
+    cors_allow_origin: Option<Vec<String>>,
+}
+
+impl Args {
+    /// Build the router settings. Arguments that were not given on the command line or through
+    /// environment variables are read from the configuration file
+    fn merge(mut self, config: Config, matches: &ArgMatches) -> Result<Settings> {
+        let is_set = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine) | Some(ValueSource::EnvVariable)
+            )
+        };
+
+        macro_rules! merge {
+            ($field:ident) => {
+                if let Some(value) = config.$field.clone() {
+                    if !is_set(stringify!($field)) {
+                        self.$field = value;
+                    }
+                }
+            };
+            (Option $field:ident) => {
+                if let Some(value) = config.$field.clone() {
+                    if !is_set(stringify!($field)) {
+                        self.$field = Some(value);
+                    }
+                }
+            };
+        }
+
+        merge!(model_id);
+        merge!(Option revision);
+        merge!(additional_model_ids);
+        merge!(Option tokenization_workers);
+        merge!(Option max_input_length);
+        merge!(Option max_batch_requests);
+        merge!(max_concurrent_requests);
+        merge!(max_batch_tokens);
+        merge!(max_client_batch_size);
+        merge!(embedding_cache_size_mb);
+        merge!(Option hf_api_token);
+        merge!(hostname);
+        merge!(port);
+        merge!(uds_path);
+        merge!(Option huggingface_hub_cache);
+        merge!(json_output);
+        merge!(Option otlp_endpoint);
+        merge!(Option api_keys_file);
+        merge!(Option cors_allow_origin);
+
+        if let Some(dtype) = &config.dtype {
+            if !is_set("dtype") {
+                self.dtype = Some(
+                    DType::from_str(dtype, true)
+                        .map_err(|err| anyhow!("Invalid `dtype` in configuration file: {err}"))?,
+                );
+            }
+        }
+        if let Some(pooling) = &config.pooling {
+            if !is_set("pooling") {
+                self.pooling =
+                    Some(Pool::from_str(pooling, true).map_err(|err| {
+                        anyhow!("Invalid `pooling` in configuration file: {err}")
+                    })?);
+            }
+        }
+
+        let fixed_args = matches
+            .ids()
+            .map(|id| id.as_str())
+            .filter(|id| is_set(id))
+            .map(String::from)
+            .collect();
+
+        Ok(Settings {
+            model_id: self.model_id,
+            revision: self.revision,
+            additional_model_ids: self.additional_model_ids,
+            tokenization_workers: self.tokenization_workers,
+            dtype: self.dtype,
+            pooling: self.pooling,
+            max_input_length: self.max_input_length,
+            max_concurrent_requests: self.max_concurrent_requests,
+            max_batch_tokens: self.max_batch_tokens,
+            max_batch_requests: self.max_batch_requests,
+            max_client_batch_size: self.max_client_batch_size,
+            embedding_cache_size_mb: self.embedding_cache_size_mb,
+            hf_api_token: self.hf_api_token,
+            hostname: self.hostname,
+            port: self.port,
+            uds_path: self.uds_path,
+            huggingface_hub_cache: self.huggingface_hub_cache,
+            json_output: self.json_output,
+            otlp_endpoint: self.otlp_endpoint,
+            api_keys_file: self.api_keys_file,
+            cors_allow_origin: self.cors_allow_origin,
+            config_path: self.config.map(PathBuf::from),
+            config,
+            fixed_args,
+        })
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Pattern match configuration
-    let args: Args = Args::parse();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches)?;
+    let config = match &args.config {
+        Some(path) => Config::load(Path::new(path))?,
+        None => Config::default(),
+    };
+    let settings = args.merge(config, &matches)?;
 
     // Initialize logging and telemetry
     let global_tracer =
-        text_embeddings_router::init_logging(args.otlp_endpoint.as_ref(), args.json_output);
-
-    tracing::info!("{args:?}");
-
-    text_embeddings_router::run(
-        args.model_id,
-        args.revision,
-        args.tokenization_workers,
-        args.dtype,
-        args.pooling,
-        args.max_concurrent_requests,
-        args.max_batch_tokens,
-        args.max_batch_requests,
-        args.max_client_batch_size,
-        args.hf_api_token,
-        Some(args.hostname),
-        args.port,
-        Some(args.uds_path),
-        args.huggingface_hub_cache,
-        args.otlp_endpoint,
-    )
-    .await?;
+        text_embeddings_router::init_logging(settings.otlp_endpoint.as_ref(), settings.json_output);
+
+    tracing::info!("{settings:?}");
+
+    text_embeddings_router::run(settings).await?;
 
     if global_tracer {
         // Shutdown tracer
@@ -136,3 +274,75 @@ async fn main() -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> (Args, ArgMatches) {
+        let matches = Args::command()
+            .try_get_matches_from([&["text-embeddings-router"], args].concat())
+            .unwrap();
+        (Args::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    #[test]
+    fn merge_keeps_command_line_arguments() {
+        let (args, matches) = parse(&["--max-batch-tokens", "1024", "--pooling", "cls"]);
+        let config = Config {
+            max_batch_tokens: Some(2048),
+            max_concurrent_requests: Some(8),
+            pooling: Some("mean".to_string()),
+            dtype: Some("float32".to_string()),
+            cors_allow_origin: Some(vec!["https://example.com".to_string()]),
+            ..Default::default()
+        };
+
+        let settings = args.merge(config, &matches).unwrap();
+        assert_eq!(settings.max_batch_tokens, 1024);
+        assert_eq!(settings.pooling, Some(Pool::Cls));
+        assert_eq!(settings.max_concurrent_requests, 8);
+        assert_eq!(settings.dtype, Some(DType::Float32));
+        assert_eq!(
+            settings.cors_allow_origin,
+            Some(vec!["https://example.com".to_string()])
+        );
+        // Defaults are not fixed
+        assert_eq!(settings.max_client_batch_size, 32);
+        assert!(settings.fixed_args.contains("max_batch_tokens"));
+        assert!(settings.fixed_args.contains("pooling"));
+        assert!(!settings.fixed_args.contains("max_concurrent_requests"));
+        assert!(!settings.fixed_args.contains("max_client_batch_size"));
+        // The file is kept for the runtime limits
+        assert_eq!(settings.config.max_batch_tokens, Some(2048));
+    }
+
+    #[test]
+    fn merge_prefers_cors_allow_origin_argument() {
+        let (args, matches) = parse(&["--cors-allow-origin", "https://a.com,https://b.com"]);
+        let config = Config {
+            cors_allow_origin: Some(vec!["https://example.com".to_string()]),
+            ..Default::default()
+        };
+
+        let settings = args.merge(config, &matches).unwrap();
+        assert_eq!(
+            settings.cors_allow_origin,
+            Some(vec![
+                "https://a.com".to_string(),
+                "https://b.com".to_string()
+            ])
+        );
+        assert!(settings.fixed_args.contains("cors_allow_origin"));
+    }
+
+    #[test]
+    fn merge_rejects_invalid_values() {
+        let (args, matches) = parse(&[]);
+        let config = Config {
+            pooling: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        assert!(args.merge(config, &matches).is_err());
+    }
+}
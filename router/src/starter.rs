@@ -3,10 +3,13 @@ use std::collections::HashMap;
 use crate::consulr::{ConsulClient, ConsulError, VaultClient};
 use crate::s3_download::S3Downloader;
 
+/// Download the models from S3 and register the service in Consul.
+///
+/// Returns the local path of each model and the API keys stored under the `apiKeys` key of the
+/// Consul KV map, if any.
 pub(crate) async fn start_app(
-    model_id: String,
-    mut model_revision: String,
-) -> Result<String, ConsulError> {
+    models: Vec<(String, String)>,
+) -> Result<(Vec<String>, Option<String>), ConsulError> {
     let consul_client = ConsulClient::new();
 
     // Get kv from consul
@@ -61,30 +64,36 @@ pub(crate) async fn start_app(
         .get("bucket")
         .unwrap_or(&"".to_string())
         .to_string();
-    if model_revision == "" {
-        model_revision = "main".to_owned();
-    }
-    let sub_prefix = format!("{}/{}", model_id, model_revision);
-    match S3Downloader::new(
-        access_key,
-        secret_key,
-        region,
-        bucket,
-        "huggingface".to_string(),
-        sub_prefix.clone(),
-    )
-    .await
-    .download()
-    .await
-    {
-        Ok(_) => {
-            tracing::info!("Downloaded model from S3");
+    let mut model_paths = Vec::with_capacity(models.len());
+    for (model_id, mut model_revision) in models {
+        if model_revision == "" {
+            model_revision = "main".to_owned();
         }
-        Err(err) => {
-            tracing::warn!("Could not download model from S3: {:?}", err);
+        let sub_prefix = format!("{}/{}", model_id, model_revision);
+        match S3Downloader::new(
+            access_key.clone(),
+            secret_key.clone(),
+            region.clone(),
+            bucket.clone(),
+            "huggingface".to_string(),
+            sub_prefix.clone(),
+        )
+        .await
+        .download()
+        .await
+        {
+            Ok(_) => {
+                tracing::info!("Downloaded model {model_id} from S3");
+            }
+            Err(err) => {
+                tracing::warn!("Could not download model {model_id} from S3: {:?}", err);
+            }
         }
+        model_paths.push(format!("/tmp/{}", sub_prefix));
     }
 
+    let api_keys = consul_kv.remove("apiKeys");
+
     consul_client.register().await;
-    Ok(format!("/tmp/{}", sub_prefix))
+    Ok((model_paths, api_keys))
 }
@@ -1,8 +1,9 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::time::Duration;
 use text_embeddings_backend::DType;
-use text_embeddings_router::run;
+use text_embeddings_router::{run, Config, Settings};
 use tokio::time::Instant;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -43,26 +44,43 @@ async fn check_health(port: u16, timeout: Duration) -> Result<()> {
     }
 }
 
+/// Settings of the test server, listening on port 8090
+pub fn settings(model_id: String, revision: Option<String>, dtype: DType) -> Settings {
+    Settings {
+        model_id,
+        revision,
+        additional_model_ids: vec![],
+        tokenization_workers: Some(1),
+        dtype: Some(dtype),
+        pooling: None,
+        max_input_length: None,
+        max_concurrent_requests: 4,
+        max_batch_tokens: 1024,
+        max_batch_requests: None,
+        max_client_batch_size: 32,
+        embedding_cache_size_mb: 0,
+        hf_api_token: None,
+        hostname: "0.0.0.0".to_string(),
+        port: 8090,
+        uds_path: "/tmp/text-embeddings-inference-server".to_string(),
+        huggingface_hub_cache: None,
+        json_output: false,
+        otlp_endpoint: None,
+        api_keys_file: None,
+        cors_allow_origin: None,
+        config_path: None,
+        config: Config::default(),
+        fixed_args: HashSet::new(),
+    }
+}
+
+#[allow(dead_code)]
 pub async fn start_server(model_id: String, revision: Option<String>, dtype: DType) -> Result<()> {
-    let server_task = tokio::spawn({
-        run(
-            model_id,
-            revision,
-            Some(1),
-            Some(dtype),
-            None,
-            4,
-            1024,
-            None,
-            32,
-            None,
-            None,
-            8090,
-            None,
-            None,
-            None,
-        )
-    });
+    start_server_with_settings(settings(model_id, revision, dtype)).await
+}
+
+pub async fn start_server_with_settings(settings: Settings) -> Result<()> {
+    let server_task = tokio::spawn(run(settings));
 
     tokio::select! {
         err = server_task => err?,
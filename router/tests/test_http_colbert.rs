@@ -0,0 +1,82 @@
+mod common;
+
+use crate::common::start_server;
+use anyhow::Result;
+use serde_json::json;
+use text_embeddings_backend::DType;
+
+#[tokio::test]
+#[cfg(feature = "http")]
+async fn test_colbert() -> Result<()> {
+    start_server("colbert-ir/colbertv2.0".to_string(), None, DType::Float32).await?;
+
+    let client = reqwest::Client::new();
+
+    // Queries are augmented with mask tokens up to `query_maxlen`
+    let request = json!({"inputs": "What is Deep Learning?", "input_type": "query"});
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+    let vectors = res.json::<Vec<Vec<Vec<f32>>>>().await?;
+    assert_eq!(vectors[0].len(), 32);
+    for vector in &vectors[0] {
+        assert_eq!(vector.len(), 128);
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-3);
+    }
+
+    // Documents only get their marker
+    let request = json!({"inputs": ["Deep Learning is not..."], "input_type": "document"});
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+    let vectors = res.json::<Vec<Vec<Vec<f32>>>>().await?;
+    assert!(vectors[0].len() < 32);
+
+    // Without `input_type`, the hidden states are neither projected nor normalized
+    let request = json!({"inputs": "Deep Learning"});
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+    let vectors = res.json::<Vec<Vec<Vec<f32>>>>().await?;
+    assert_eq!(vectors[0].len(), 4);
+    assert_eq!(vectors[0][0].len(), 768);
+
+    let request = json!({"inputs": "test", "input_type": "query", "layer": 2});
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    // Pooled embeddings are not available
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&json!({"inputs": "test"}))
+        .send()
+        .await?;
+    assert_eq!(res.status(), 424);
+
+    // Documents are ranked with MaxSim
+    let request = json!({
+        "query": "What is the capital of France?",
+        "texts": vec!["Berlin is the capital of Germany.", "Paris is the capital of France."],
+    });
+    let res = client
+        .post("http://0.0.0.0:8090/rerank")
+        .json(&request)
+        .send()
+        .await?;
+    let ranks = res.json::<serde_json::Value>().await?;
+    assert_eq!(ranks[0]["index"], 1);
+    assert_eq!(ranks[1]["index"], 0);
+
+    Ok(())
+}
@@ -2,6 +2,8 @@ mod common;
 
 use crate::common::{start_server, Score};
 use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use insta::internals::YamlMatcher;
 use serde_json::json;
 use text_embeddings_backend::DType;
@@ -49,5 +51,317 @@ async fn test_embeddings() -> Result<()> {
         assert_eq!(embeddings, &embeddings_single[0]);
     }
 
+    let request = json!({
+        "inputs": "test",
+        "encoding_format": "base64",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+
+    let embeddings_base64 = res.json::<Vec<String>>().await?;
+    let values = decode_le_f32(&STANDARD.decode(&embeddings_base64[0])?);
+    let embeddings_base64 = serde_json::from_value::<Vec<Score>>(json!(values))?;
+    assert_eq!(embeddings_base64, embeddings_single[0]);
+
+    let request = json!({
+        "inputs": vec!["test", "test"],
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("Accept", "application/octet-stream")
+        .json(&request)
+        .send()
+        .await?;
+
+    let values = decode_le_f32(&res.bytes().await?);
+    assert_eq!(values.len(), 2 * embeddings_single[0].len());
+    let embeddings_raw = serde_json::from_value::<Vec<Score>>(json!(values))?;
+    assert_eq!(
+        embeddings_raw[..embeddings_single[0].len()],
+        embeddings_single[0]
+    );
+
+    let request = json!({
+        "inputs": "test",
+        "precision": "ubinary",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+
+    let embeddings_binary = res.json::<Vec<Vec<u8>>>().await?;
+    assert_eq!(embeddings_binary[0].len(), embeddings_single[0].len() / 8);
+
+    let request = json!({
+        "inputs": "test",
+        "precision": "int8",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    let request = json!({
+        "inputs": "test",
+        "dimensions": 128,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+
+    let embeddings_matryoshka = res.json::<Vec<Vec<f32>>>().await?;
+    assert_eq!(embeddings_matryoshka[0].len(), 128);
+    let norm = embeddings_matryoshka[0]
+        .iter()
+        .map(|v| v * v)
+        .sum::<f32>()
+        .sqrt();
+    assert!((norm - 1.0).abs() < 1e-4);
+
+    let request = json!({
+        "inputs": "test",
+        "dimensions": 1024,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    let request = json!({
+        "inputs": "test test",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_unprompted = res.json::<Vec<Vec<Score>>>().await?;
+
+    let request = json!({
+        "inputs": "test",
+        "prompt": "test ",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_prompted = res.json::<Vec<Vec<Score>>>().await?;
+    assert_eq!(embeddings_prompted, embeddings_unprompted);
+
+    let request = json!({
+        "inputs": "test",
+        "prompt_name": "unknown",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    let request = json!({
+        "inputs": "test ".repeat(32),
+        "max_tokens": 16,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    let request = json!({
+        "inputs": "test ".repeat(32),
+        "truncate": true,
+        "truncation_direction": "left",
+        "max_tokens": 16,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 200);
+
+    let request = json!({
+        "inputs": "test",
+        "truncate": true,
+        "truncation_strategy": "only_second",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    let request = json!({
+        "inputs": {
+            "source_sentence": "test",
+            "sentences": ["test", "cheese is made from milk", "test"],
+        },
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/similarity")
+        .json(&request)
+        .send()
+        .await?;
+
+    let scores = res.json::<Vec<f32>>().await?;
+    assert_eq!(scores.len(), 3);
+    assert!((scores[0] - 1.0).abs() < 1e-4);
+    assert!(scores[1] < scores[0]);
+    assert_eq!(scores[2], scores[0]);
+
+    let request = json!({
+        "query": "test",
+        "texts": ["cheese is made from milk", "test"],
+        "return_text": true,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/rerank")
+        .json(&request)
+        .send()
+        .await?;
+
+    let ranks = res.json::<Vec<serde_json::Value>>().await?;
+    assert_eq!(ranks.len(), 2);
+    assert_eq!(ranks[0]["index"], 1);
+    assert_eq!(ranks[0]["text"], "test");
+
+    let document = "test ".repeat(300);
+    let request = json!({
+        "inputs": document,
+        "chunk_size": 128,
+        "stride": 16,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed_chunks")
+        .json(&request)
+        .send()
+        .await?;
+
+    let documents = res.json::<Vec<serde_json::Value>>().await?;
+    let chunks = documents[0]["chunks"].as_array().unwrap();
+    assert!(chunks.len() > 1);
+    assert_eq!(chunks[0]["start"], 0);
+    assert_eq!(chunks.last().unwrap()["end"], document.trim_end().len());
+
+    let request = json!({
+        "inputs": document,
+        "chunk_size": 128,
+        "stride": 16,
+        "aggregation": "mean",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed_chunks")
+        .json(&request)
+        .send()
+        .await?;
+
+    let documents = res.json::<Vec<serde_json::Value>>().await?;
+    let embedding = documents[0]["embedding"].as_array().unwrap();
+    assert_eq!(embedding.len(), embeddings_single[0].len());
+
+    // `[CLS] test [SEP]`
+    let request = json!({
+        "inputs": vec!["test", "test"],
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+
+    let token_embeddings = res.json::<Vec<Vec<Vec<f32>>>>().await?;
+    assert_eq!(token_embeddings.len(), 2);
+    assert_eq!(token_embeddings[0].len(), 3);
+    assert_eq!(token_embeddings[0][0].len(), embeddings_single[0].len());
+
+    let request = json!({
+        "inputs": "test",
+        "layer": 7,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed_all")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 413);
+
+    // Pre-tokenized `[CLS] test [SEP]`
+    let request = json!({
+        "inputs": [[101, 3231, 102], "test"],
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+
+    let embeddings_ids = res.json::<Vec<Vec<Score>>>().await?;
+    assert_eq!(embeddings_ids[0], embeddings_single[0]);
+    assert_eq!(embeddings_ids[1], embeddings_single[0]);
+
+    let request = json!({
+        "input": [101, 3231, 102],
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embeddings")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 200);
+
+    let request = json!({
+        "inputs": [101, 1000000, 102],
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 422);
+
     Ok(())
 }
+
+fn decode_le_f32(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
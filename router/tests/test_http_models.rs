@@ -0,0 +1,137 @@
+mod common;
+
+use crate::common::{settings, start_server_with_settings, Score};
+use anyhow::Result;
+use serde_json::json;
+use text_embeddings_backend::DType;
+use text_embeddings_router::{run, ModelSettings};
+
+#[tokio::test]
+#[cfg(feature = "http")]
+async fn test_models() -> Result<()> {
+    let mut server_settings = settings(
+        "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+        None,
+        DType::Float32,
+    );
+    server_settings.additional_model_ids = vec!["BAAI/bge-small-en-v1.5".to_string()];
+    start_server_with_settings(server_settings).await?;
+
+    let client = reqwest::Client::new();
+
+    // Requests without a model use the default one
+    let res = client.get("http://0.0.0.0:8090/info").send().await?;
+    let info = res.json::<serde_json::Value>().await?;
+    assert_eq!(info["model_id"], "sentence-transformers/all-MiniLM-L6-v2");
+
+    let res = client
+        .get("http://0.0.0.0:8090/info")
+        .header("x-model-id", "BAAI/bge-small-en-v1.5")
+        .send()
+        .await?;
+    let info = res.json::<serde_json::Value>().await?;
+    assert_eq!(info["model_id"], "BAAI/bge-small-en-v1.5");
+
+    let request = json!({
+        "inputs": "test",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_default = res.json::<Vec<Vec<Score>>>().await?;
+
+    let request = json!({
+        "inputs": "test",
+        "model": "BAAI/bge-small-en-v1.5",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_field = res.json::<Vec<Vec<Score>>>().await?;
+    assert_ne!(embeddings_field[0], embeddings_default[0]);
+
+    let request = json!({
+        "inputs": "test",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("x-model-id", "BAAI/bge-small-en-v1.5")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_header = res.json::<Vec<Vec<Score>>>().await?;
+    assert_eq!(embeddings_header[0], embeddings_field[0]);
+
+    // The `model` field takes precedence over the header
+    let request = json!({
+        "inputs": "test",
+        "model": "sentence-transformers/all-MiniLM-L6-v2",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .header("x-model-id", "BAAI/bge-small-en-v1.5")
+        .json(&request)
+        .send()
+        .await?;
+    let embeddings_both = res.json::<Vec<Vec<Score>>>().await?;
+    assert_eq!(embeddings_both[0], embeddings_default[0]);
+
+    let request = json!({
+        "inputs": "test",
+        "model": "unknown",
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/embed")
+        .json(&request)
+        .send()
+        .await?;
+    assert_eq!(res.status(), 404);
+    let error = res.json::<serde_json::Value>().await?;
+    assert_eq!(error["error_type"], "ModelNotFound");
+
+    let res = client
+        .get("http://0.0.0.0:8090/info")
+        .header("x-model-id", "unknown")
+        .send()
+        .await?;
+    assert_eq!(res.status(), 404);
+
+    // A model can only be served once
+    let mut duplicated = settings(
+        "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+        None,
+        DType::Float32,
+    );
+    duplicated.additional_model_ids = vec!["sentence-transformers/all-MiniLM-L6-v2".to_string()];
+    let err = run(duplicated).await.unwrap_err();
+    assert!(err.to_string().contains("served more than once"));
+
+    let mut duplicated = settings(
+        "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+        None,
+        DType::Float32,
+    );
+    duplicated.additional_model_ids = vec!["BAAI/bge-small-en-v1.5".to_string()];
+    duplicated.config.models = vec![ModelSettings {
+        model_id: "BAAI/bge-small-en-v1.5".to_string(),
+        revision: None,
+        pooling: None,
+        max_input_length: None,
+        max_concurrent_requests: None,
+        max_batch_tokens: None,
+        max_batch_requests: None,
+    }];
+    let err = run(duplicated).await.unwrap_err();
+    assert!(err.to_string().contains("served more than once"));
+
+    Ok(())
+}
@@ -23,8 +23,10 @@ async fn test_predict() -> Result<()> {
     )
     .await?;
 
+    // The model is a multi-label classifier. The snapshots were taken with softmax scores
     let request = json!({
-        "inputs": "test"
+        "inputs": "test",
+        "function_to_apply": "softmax"
     });
 
     let client = reqwest::Client::new();
@@ -46,6 +48,7 @@ async fn test_predict() -> Result<()> {
             vec!["test"],
             vec!["test"],
         ],
+        "function_to_apply": "softmax"
     });
 
     let client = reqwest::Client::new();
@@ -63,5 +66,24 @@ async fn test_predict() -> Result<()> {
         assert_eq!(predictions, &predictions_single);
     }
 
+    // Multi-label models default to a per-label sigmoid
+    let request = json!({
+        "inputs": "test",
+        "top_k": 3
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/predict")
+        .json(&request)
+        .send()
+        .await?;
+
+    let predictions = res.json::<Vec<SnapshotPrediction>>().await?;
+    assert_eq!(predictions.len(), 3);
+    for (prediction, softmax_prediction) in predictions.iter().zip(&predictions_single) {
+        assert_eq!(prediction.label, softmax_prediction.label);
+    }
+    assert_ne!(predictions[0].score, predictions_single[0].score);
+
     Ok(())
 }
@@ -45,5 +45,31 @@ async fn test_rerank() -> Result<()> {
     assert_eq!(ranks[1].index, 0);
     assert_eq!(ranks[0].score, ranks[1].score);
 
+    // Cohere compatible route
+    let request = json!({
+        "query": "test",
+        "documents": [{"text": "other"}, "test", {"title": "t", "text": "test"}],
+        "top_n": 2,
+        "return_documents": true
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/v1/rerank")
+        .json(&request)
+        .send()
+        .await?;
+
+    let response = res.json::<serde_json::Value>().await?;
+    let results = response["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["index"], 2);
+    assert_eq!(results[1]["index"], 1);
+    assert_eq!(results[1]["document"], json!({"text": "test"}));
+    assert_eq!(
+        results[0]["document"],
+        json!({"title": "t", "text": "test"})
+    );
+    assert_eq!(response["meta"]["billed_units"]["search_units"], 1);
+
     Ok(())
 }
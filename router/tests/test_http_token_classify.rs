@@ -0,0 +1,55 @@
+mod common;
+
+use crate::common::start_server;
+use anyhow::Result;
+use serde_json::{json, Value};
+use text_embeddings_backend::DType;
+
+#[tokio::test]
+#[cfg(feature = "http")]
+async fn test_token_classify() -> Result<()> {
+    start_server("dslim/bert-base-NER".to_string(), None, DType::Float32).await?;
+
+    let request = json!({
+        "inputs": "My name is Wolfgang and I live in Berlin",
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post("http://0.0.0.0:8090/token_classify")
+        .json(&request)
+        .send()
+        .await?;
+
+    let entities = res.json::<Value>().await?;
+    let entities = entities[0].as_array().unwrap();
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0]["label"], "PER");
+    assert_eq!(entities[0]["text"], "Wolfgang");
+    assert_eq!(entities[0]["start"], 11);
+    assert_eq!(entities[0]["end"], 19);
+    assert_eq!(entities[1]["label"], "LOC");
+    assert_eq!(entities[1]["text"], "Berlin");
+    assert_eq!(entities[1]["start"], 34);
+    assert_eq!(entities[1]["end"], 40);
+
+    let request = json!({
+        "inputs": vec!["My name is Wolfgang", "I live in Berlin"],
+        "aggregation_strategy": "first",
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post("http://0.0.0.0:8090/token_classify")
+        .json(&request)
+        .send()
+        .await?;
+
+    let entities = res.json::<Value>().await?;
+    assert_eq!(entities.as_array().unwrap().len(), 2);
+    assert_eq!(entities[0][0]["text"], "Wolfgang");
+    assert_eq!(entities[1][0]["label"], "LOC");
+    assert_eq!(entities[1][0]["start"], 10);
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use crate::common::start_server;
+use anyhow::Result;
+use serde_json::{json, Value};
+use text_embeddings_backend::DType;
+
+#[tokio::test]
+#[cfg(feature = "http")]
+async fn test_tokenize() -> Result<()> {
+    start_server(
+        "sentence-transformers/all-MiniLM-L6-v2".to_string(),
+        None,
+        DType::Float32,
+    )
+    .await?;
+
+    let request = json!({
+        "inputs": "test"
+    });
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post("http://0.0.0.0:8090/tokenize")
+        .json(&request)
+        .send()
+        .await?;
+
+    let tokens = res.json::<Value>().await?;
+    assert_eq!(
+        tokens,
+        json!([[
+            {"id": 101, "token": "[CLS]", "special": true, "start": null, "stop": null},
+            {"id": 3231, "token": "test", "special": false, "start": 0, "stop": 4},
+            {"id": 102, "token": "[SEP]", "special": true, "start": null, "stop": null},
+        ]])
+    );
+
+    let request = json!({
+        "inputs": vec!["test", "test"],
+        "add_special_tokens": false,
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/tokenize")
+        .json(&request)
+        .send()
+        .await?;
+
+    let tokens = res.json::<Value>().await?;
+    let expected = json!([{"id": 3231, "token": "test", "special": false, "start": 0, "stop": 4}]);
+    assert_eq!(tokens, json!([expected, expected]));
+
+    let request = json!({
+        "ids": vec![101, 3231, 102]
+    });
+
+    let res = client
+        .post("http://0.0.0.0:8090/decode")
+        .json(&request)
+        .send()
+        .await?;
+
+    let texts = res.json::<Vec<String>>().await?;
+    assert_eq!(texts, vec!["test".to_string()]);
+
+    Ok(())
+}